@@ -0,0 +1,343 @@
+use id3::Tag;
+use std::{
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::list::*;
+use crate::mpeg::*;
+use crate::preview::*;
+use crate::tags::*;
+
+const PREAMBLE: &[u8; 8] = b"APETAGEX";
+const HEADER_LEN: usize = 32;
+const ID3V1_LEN: usize = 128;
+
+// APEv2 items and the ID3 frames they migrate to, as keys understood by
+// set_frame_value. APE keys are case-insensitive; any other text item
+// becomes a TXXX frame named after the item.
+const ITEM_FRAMES: [(&str, &str); 22] = [
+    ("title", "TIT2"),
+    ("artist", "TPE1"),
+    ("album", "TALB"),
+    ("album artist", "TPE2"),
+    ("albumartist", "TPE2"),
+    ("year", "TYER"),
+    ("track", "TRCK"),
+    ("disc", "TPOS"),
+    ("genre", "TCON"),
+    ("comment", "COMM"),
+    ("composer", "TCOM"),
+    ("conductor", "TPE3"),
+    ("lyricist", "TEXT"),
+    ("publisher", "TPUB"),
+    ("label", "TPUB"),
+    ("copyright", "TCOP"),
+    ("isrc", "TSRC"),
+    ("bpm", "TBPM"),
+    ("subtitle", "TIT3"),
+    ("language", "TLAN"),
+    ("mood", "TMOO"),
+    ("encoded by", "TENC"),
+];
+
+#[derive(Clone)]
+pub enum ApeValue {
+    // Multiple values are separated by NUL, as in ID3 text frames
+    Text(String),
+    Binary(usize),
+    Locator(String),
+}
+
+impl ApeValue {
+    pub fn display(&self) -> String {
+        match self {
+            ApeValue::Text(text) => text.replace('\0', " / "),
+            ApeValue::Binary(len) => format!("<binary, {} bytes>", len),
+            ApeValue::Locator(url) => url.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ApeItem {
+    pub key: String,
+    pub value: ApeValue,
+}
+
+impl ApeItem {
+    // The key of the ID3 frame the item migrates to. Binary items such as
+    // cover art and external locators are not migrated.
+    pub fn frame_key(&self) -> Option<String> {
+        if !matches!(self.value, ApeValue::Text(_)) {
+            return None;
+        }
+
+        let lower = self.key.to_lowercase();
+        let key = match ITEM_FRAMES.iter().find(|(item, _)| *item == lower) {
+            Some((_, id)) => id.to_string(),
+            None => format!("TXXX:{}", self.key.to_uppercase()),
+        };
+
+        Some(key)
+    }
+}
+
+// An APE tag at the end of a file, with the byte range it occupies
+// (header and footer included)
+pub struct ApeTag {
+    pub version: u32,
+    pub start: usize,
+    pub end: usize,
+    pub items: Vec<ApeItem>,
+}
+
+// Finds an APEv1 or APEv2 tag at the end of the file or right before an
+// ID3v1 tag, where foobar2000 and others put it
+pub fn find_ape_tag(data: &[u8]) -> Option<ApeTag> {
+    let mut end = data.len();
+    if end >= ID3V1_LEN && &data[end - ID3V1_LEN..end - ID3V1_LEN + 3] == b"TAG" {
+        end -= ID3V1_LEN;
+    }
+    let footer = data.get(end.checked_sub(HEADER_LEN)?..end)?;
+    if &footer[..8] != PREAMBLE {
+        return None;
+    }
+
+    let field = |pos: usize| u32::from_le_bytes(footer[pos..pos + 4].try_into().unwrap());
+    let version = field(8);
+    // The size counts the items and the footer, but not the header
+    let size = field(12) as usize;
+    let count = field(16) as usize;
+    if size < HEADER_LEN {
+        return None;
+    }
+    let has_header = version >= 2000 && field(20) & 0x8000_0000 != 0;
+
+    let items_start = end.checked_sub(size)?;
+    let start = if has_header { items_start.checked_sub(HEADER_LEN)? } else { items_start };
+    let items = parse_items(data.get(items_start..end - HEADER_LEN)?, count)?;
+
+    Some(ApeTag { version, start, end, items })
+}
+
+fn parse_items(data: &[u8], count: usize) -> Option<Vec<ApeItem>> {
+    let mut items = Vec::new();
+    let mut pos = 0;
+    for _ in 0..count {
+        let header = data.get(pos..pos + 8)?;
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let flags = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let key_len = data.get(pos + 8..)?.iter().position(|byte| *byte == 0)?;
+        let key = String::from_utf8_lossy(&data[pos + 8..pos + 8 + key_len]).to_string();
+        let value_start = pos + 8 + key_len + 1;
+        let value = data.get(value_start..value_start.checked_add(len)?)?;
+
+        let value = match (flags >> 1) & 0x3 {
+            1 => ApeValue::Binary(value.len()),
+            2 => ApeValue::Locator(String::from_utf8_lossy(value).to_string()),
+            _ => ApeValue::Text(String::from_utf8_lossy(value).to_string()),
+        };
+        items.push(ApeItem { key, value });
+        pos = value_start + len;
+    }
+
+    Some(items)
+}
+
+pub fn read_ape_tag(path: &Path) -> io::Result<Option<ApeTag>> {
+    let data = fs::read(path)?;
+    Ok(find_ape_tag(&data))
+}
+
+// Cuts the APE tag out of the file. Anything after it, such as an ID3v1 tag,
+// is kept, and the MPEG audio is checked to be byte for byte the same before
+// the file is written.
+pub fn remove_ape_tag(path: &Path) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let tag = match find_ape_tag(&data) {
+        Some(tag) => tag,
+        None => return Ok(()),
+    };
+
+    let mut contents = data[..tag.start].to_vec();
+    contents.extend(&data[tag.end..]);
+
+    let (start, end) = audio_range(&data);
+    let (new_start, new_end) = audio_range(&contents);
+    if data[start..end] != contents[new_start..new_end] {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Removing the APE tag would change the audio"
+        ));
+    }
+
+    fs::write(path, contents)
+}
+
+pub struct ApeFile {
+    pub path: PathBuf,
+    pub tag: ApeTag,
+}
+
+#[derive(Clone)]
+pub enum ApeLine {
+    File(usize),
+    Item(usize, usize),
+}
+
+pub struct ApeView {
+    pub files: Vec<ApeFile>,
+    pub lines: GenericList<ApeLine>,
+    // Number of selected MPEG files that were checked
+    pub num_checked: usize,
+    pub confirm: bool,
+}
+
+impl ApeView {
+    pub fn new(files: Vec<ApeFile>, num_checked: usize) -> Self {
+        let mut lines = Vec::new();
+        for (i, file) in files.iter().enumerate() {
+            lines.push(ApeLine::File(i));
+            for j in 0..file.tag.items.len() {
+                lines.push(ApeLine::Item(i, j));
+            }
+        }
+
+        Self {
+            files,
+            lines: GenericList::from(lines),
+            num_checked,
+            confirm: false,
+        }
+    }
+
+    pub fn num_items(&self) -> usize {
+        self.files.iter().map(|file| file.tag.items.len()).sum()
+    }
+
+    // The ID3 changes that migrate the APE items of one file into its tag.
+    // Frames the tag already has are only replaced when overwrite is set.
+    pub fn diff(&self, file: &ApeFile, tag: &Tag, overwrite: bool) -> FileDiff {
+        let mut changes: Vec<FrameChange> = Vec::new();
+        let mut kept = 0;
+        let mut skipped = 0;
+        for item in file.tag.items.iter() {
+            let (key, new) = match (item.frame_key(), &item.value) {
+                (Some(key), ApeValue::Text(text)) => (key, text.clone()),
+                _ => {
+                    skipped += 1;
+                    continue;
+                },
+            };
+            // Aliases such as "Label" and "Publisher" only migrate once
+            if new.is_empty() || changes.iter().any(|change| change.id == key) {
+                continue;
+            }
+
+            let old = frame_value_for_key(tag, &key);
+            match old.as_deref() {
+                Some(old) if old == new => {},
+                Some(_) if !overwrite => kept += 1,
+                _ => changes.push(FrameChange { id: key, old, new }),
+            }
+        }
+
+        let mut notes = Vec::new();
+        if kept > 0 {
+            notes.push(format!("{} items differ from the ID3 tag, kept the ID3 values", kept));
+        }
+        if skipped > 0 {
+            notes.push(format!("{} binary or locator items not migrated", skipped));
+        }
+        let warning = if notes.is_empty() { None } else { Some(notes.join("; ")) };
+
+        FileDiff { path: file.path.clone(), changes, warning }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    // An APEv2 tag with a header, holding the given text items
+    fn ape_tag(items: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (key, value) in items {
+            body.extend((value.len() as u32).to_le_bytes());
+            body.extend(0u32.to_le_bytes());
+            body.extend(key.as_bytes());
+            body.push(0);
+            body.extend(value.as_bytes());
+        }
+
+        let block = |flags: u32| {
+            let mut block = PREAMBLE.to_vec();
+            block.extend(2000u32.to_le_bytes());
+            block.extend(((body.len() + HEADER_LEN) as u32).to_le_bytes());
+            block.extend((items.len() as u32).to_le_bytes());
+            block.extend(flags.to_le_bytes());
+            block.extend([0; 8]);
+            block
+        };
+        let mut tag = block(0xa000_0000);
+        tag.extend(&body);
+        tag.extend(block(0x8000_0000));
+
+        tag
+    }
+
+    #[test]
+    fn tags_are_found_before_id3v1() {
+        let mut data = b"audio".to_vec();
+        data.extend(ape_tag(&[("Title", "Song"), ("Artist", "Someone")]));
+        let end = data.len();
+        data.extend(b"TAG");
+        data.resize(end + ID3V1_LEN, 0);
+
+        let tag = find_ape_tag(&data).unwrap();
+        assert_eq!((tag.version, tag.start, tag.end), (2000, 5, end));
+        assert_eq!(tag.items[1].key, "Artist");
+        assert_eq!(tag.items[1].value.display(), "Someone");
+    }
+
+    #[test]
+    fn malformed_footers_are_ignored() {
+        let tag = ape_tag(&[("Title", "Song")]);
+        let size_field = tag.len() - HEADER_LEN + 12;
+        for size in [0u32, 31, 1 << 20] {
+            let mut data = tag.clone();
+            data[size_field..size_field + 4].copy_from_slice(&size.to_le_bytes());
+            assert!(find_ape_tag(&data).is_none());
+        }
+
+        let mut data = tag.clone();
+        data[size_field + 4] = 5;
+        assert!(find_ape_tag(&data).is_none());
+        assert!(find_ape_tag(&tag[HEADER_LEN + 1..]).is_none());
+    }
+
+    #[test]
+    fn removing_keeps_audio_and_id3v1() {
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.resize(ID3V1_LEN, b'x');
+        let mut data = mpeg_frames(3);
+        data.extend(ape_tag(&[("Title", "Song")]));
+        data.extend(&id3v1);
+        let path = scratch_file("track.mp3", &data);
+
+        remove_ape_tag(&path).unwrap();
+        let mut expected = mpeg_frames(3);
+        expected.extend(&id3v1);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        // Nothing left to remove
+        remove_ape_tag(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+}
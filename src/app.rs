@@ -1,9 +1,8 @@
 use id3::{
-    Content,
+    Frame,
     Tag,
 };
 use std::{
-    cmp,
     collections::HashMap,
     env,
     error,
@@ -12,33 +11,133 @@ use std::{
         DirEntry,
     },
     io,
-    path::PathBuf,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+use unicode_normalization::{
+    is_nfc,
+    UnicodeNormalization,
 };
 
+use crate::ape::*;
+use crate::audiohash::*;
+use crate::backend::*;
+use crate::bpm::*;
+use crate::clipboard::*;
+use crate::container::*;
+use crate::duplicates::*;
+use crate::edit::*;
+use crate::health::*;
+use crate::input::*;
+use crate::length::*;
+use crate::library::*;
+use crate::lint::*;
 use crate::list::*;
+use crate::merge::*;
+use crate::mojibake::*;
+use crate::mpeg::*;
+use crate::normalize::*;
+use crate::preview::*;
+use crate::profiles::*;
+use crate::replace::*;
+use crate::replaygain::*;
+use crate::split::*;
+use crate::tags::*;
+use crate::templates::*;
+use crate::transform::*;
+use crate::worker::*;
 
+#[derive(Clone, Copy, PartialEq)]
 pub enum AppState {
     FileNavigation,
+    FindReplace,
+    Transform,
+    NormalizeForm,
+    Renames,
+    Split,
+    Length,
+    Copy,
+    Templates,
+    TemplateName,
+    Edit,
+    Check,
+    Integrity,
+    Health,
+    Ape,
+    EncodingForm,
+    EncodingReview,
+    MergeField,
+    Merge,
+    DuplicatesForm,
+    Duplicates,
+    ReplayGainForm,
+    ReplayGain,
+    Bpm,
+    BpmReview,
+    Profiles,
+    WriteReport,
+    Preview,
 }
 
 pub struct App {
     pub state: AppState,
     pub quit: bool,
+    pub confirm_quit: bool,
     pub pwd: GenericList<PathBuf>,
     pub selected_files: HashMap<PathBuf, Option<Tag>>,
     pub tag_sum: HashMap<String, String>,
+    pub pending_tags: HashMap<PathBuf, Tag>,
+    pub new_tag_sum: HashMap<String, String>,
+    pub status: Option<String>,
+    pub replace_form: InputForm,
+    pub transform_dialog: TransformDialog,
+    pub normalize_form: InputForm,
+    pub renames: GenericList<Rename>,
+    pub split_form: InputForm,
+    pub copy_dialog: Option<CopyDialog>,
+    pub clipboard: Vec<Frame>,
+    pub templates: GenericList<String>,
+    pub confirm_template_delete: bool,
+    pub template_form: InputForm,
+    pub edit_dialog: EditDialog,
+    pub library: Library,
+    pub findings: GenericList<Finding>,
+    pub merge_form: InputForm,
+    pub merge_view: Option<MergeView>,
+    pub duplicates_form: InputForm,
+    pub duplicates: Option<DuplicateView>,
+    pub hash_cache: HashCache,
+    pub verify_writes: bool,
+    pub profiles: GenericList<WriteProfile>,
+    pub default_profile: Option<String>,
+    pub write_report: GenericList<Finding>,
+    pub write_profile: Option<WriteProfile>,
+    pub integrity: GenericList<Finding>,
+    pub stream_info: Option<(PathBuf, Option<StreamInfo>)>,
+    pub health: Option<HealthReport>,
+    pub ape: Option<ApeView>,
+    pub encoding_form: InputForm,
+    pub encoding_review: Option<EncodingReview>,
+    pub length_form: InputForm,
+    pub replaygain_form: InputForm,
+    pub gain_job: Option<GainJob>,
+    pub bpm_job: Option<Job<TempoEstimate>>,
+    pub bpm_review: Option<BpmReview>,
+    pub preview: Option<Preview>,
 }
 
 impl App {
     pub fn default() -> Result<Self, io::Error> {
         let mut pwd = vec![PathBuf::from("..")];
         let mut dirs = fs::read_dir(".")?
-            .filter_map(|res| is_dir(res))
+            .filter_map(is_dir)
             .collect::<Vec<PathBuf>>();
         dirs.sort();
         pwd.append(&mut dirs);
         let mut files = fs::read_dir(".")?
-            .filter_map(|res| is_file(res))
+            .filter_map(is_file)
             .collect::<Vec<PathBuf>>();
         files.sort();
         pwd.append(&mut files);
@@ -46,9 +145,56 @@ impl App {
         let app = Self {
             state: AppState::FileNavigation,
             quit: false,
+            confirm_quit: false,
             pwd: GenericList::<PathBuf>::from(pwd),
             selected_files: HashMap::new(),
             tag_sum: HashMap::new(),
+            pending_tags: HashMap::new(),
+            new_tag_sum: HashMap::new(),
+            status: None,
+            replace_form: replace_form(),
+            transform_dialog: TransformDialog::new(),
+            normalize_form: normalize_form(),
+            renames: GenericList::from(Vec::new()),
+            split_form: split_form(),
+            copy_dialog: None,
+            clipboard: Vec::new(),
+            templates: GenericList::from(Vec::new()),
+            confirm_template_delete: false,
+            template_form: InputForm::new(
+                "New Template from Highlighted File",
+                "Enter: save | Esc: cancel",
+                &[("Name", "")]
+            ),
+            edit_dialog: EditDialog::new(),
+            library: Library::load(),
+            findings: GenericList::from(Vec::new()),
+            merge_form: InputForm::new(
+                "Merge Spellings",
+                "Field name or frame ID | Enter: find clusters",
+                &[("Frame", "artist")]
+            ),
+            merge_view: None,
+            duplicates_form: duplicates_form(),
+            duplicates: None,
+            hash_cache: HashCache::load(),
+            verify_writes: false,
+            profiles: GenericList::from(Vec::new()),
+            default_profile: load_default_profile(),
+            write_report: GenericList::from(Vec::new()),
+            write_profile: None,
+            integrity: GenericList::from(Vec::new()),
+            stream_info: None,
+            health: None,
+            ape: None,
+            encoding_form: encoding_form(),
+            encoding_review: None,
+            length_form: length_form(),
+            replaygain_form: replaygain_form(),
+            gain_job: None,
+            bpm_job: None,
+            bpm_review: None,
+            preview: None,
         };
 
         Ok(app)
@@ -67,12 +213,12 @@ impl App {
     fn refresh_pwd(&mut self) -> Result<(), io::Error> {
         let mut pwd = vec![PathBuf::from("..")];
         let mut dirs = fs::read_dir(".")?
-            .filter_map(|res| is_dir(res))
+            .filter_map(is_dir)
             .collect::<Vec<PathBuf>>();
         dirs.sort();
         pwd.append(&mut dirs);
         let mut files = fs::read_dir(".")?
-            .filter_map(|res| is_file(res))
+            .filter_map(is_file)
             .collect::<Vec<PathBuf>>();
         files.sort();
         pwd.append(&mut files);
@@ -83,64 +229,228 @@ impl App {
     }
 
     pub fn on_tick(&mut self) {
-        match self.state {
-            AppState::FileNavigation => {}
-        }
+        self.poll_gain_job();
+        self.poll_bpm_job();
     }
 
     pub fn list_down(&mut self) {
         match self.state {
-            AppState::FileNavigation => {
-                if !self.pwd.is_empty() {
-                    let index = self.pwd.get_index().unwrap();
-                    let index = cmp::min(index + 1, self.pwd.len() - 1);
-                    self.pwd.select(Some(index));
+            AppState::FileNavigation => self.pwd.select_next(),
+            AppState::Preview => {
+                if let Some(preview) = self.preview.as_mut() {
+                    preview.lines.select_next();
+                }
+            }
+            AppState::Copy => {
+                if let Some(dialog) = self.copy_dialog.as_mut() {
+                    dialog.frames.select_next();
+                }
+            }
+            AppState::Templates => self.templates.select_next(),
+            AppState::Check => self.findings.select_next(),
+            AppState::Integrity => self.integrity.select_next(),
+            AppState::Renames => self.renames.select_next(),
+            AppState::Profiles => self.profiles.select_next(),
+            AppState::WriteReport => self.write_report.select_next(),
+            AppState::Health => {
+                if let Some(report) = self.health.as_mut() {
+                    report.issues.select_next();
+                }
+            },
+            AppState::Ape => {
+                if let Some(view) = self.ape.as_mut() {
+                    view.lines.select_next();
+                }
+            },
+            AppState::EncodingReview => {
+                if let Some(review) = self.encoding_review.as_mut() {
+                    review.rows.select_next();
+                }
+            },
+            AppState::BpmReview => {
+                if let Some(review) = self.bpm_review.as_mut() {
+                    review.rows.select_next();
+                }
+            },
+            AppState::Merge => {
+                if let Some(view) = self.merge_view.as_mut() {
+                    view.lines.select_next();
                 }
             }
+            AppState::Duplicates => {
+                if let Some(view) = self.duplicates.as_mut() {
+                    view.lines.select_next();
+                }
+            }
+            _ => {}
         }
     }
 
     pub fn list_up(&mut self) {
         match self.state {
-            AppState::FileNavigation => {
-                if !self.pwd.is_empty() {
-                    let index = self.pwd.get_index().unwrap();
-                    let index = index.checked_sub(1).unwrap_or(0);
-                    self.pwd.select(Some(index));
+            AppState::FileNavigation => self.pwd.select_prev(),
+            AppState::Preview => {
+                if let Some(preview) = self.preview.as_mut() {
+                    preview.lines.select_prev();
+                }
+            }
+            AppState::Copy => {
+                if let Some(dialog) = self.copy_dialog.as_mut() {
+                    dialog.frames.select_prev();
                 }
             }
+            AppState::Templates => self.templates.select_prev(),
+            AppState::Check => self.findings.select_prev(),
+            AppState::Integrity => self.integrity.select_prev(),
+            AppState::Renames => self.renames.select_prev(),
+            AppState::Profiles => self.profiles.select_prev(),
+            AppState::WriteReport => self.write_report.select_prev(),
+            AppState::Health => {
+                if let Some(report) = self.health.as_mut() {
+                    report.issues.select_prev();
+                }
+            },
+            AppState::Ape => {
+                if let Some(view) = self.ape.as_mut() {
+                    view.lines.select_prev();
+                }
+            },
+            AppState::EncodingReview => {
+                if let Some(review) = self.encoding_review.as_mut() {
+                    review.rows.select_prev();
+                }
+            },
+            AppState::BpmReview => {
+                if let Some(review) = self.bpm_review.as_mut() {
+                    review.rows.select_prev();
+                }
+            },
+            AppState::Merge => {
+                if let Some(view) = self.merge_view.as_mut() {
+                    view.lines.select_prev();
+                }
+            }
+            AppState::Duplicates => {
+                if let Some(view) = self.duplicates.as_mut() {
+                    view.lines.select_prev();
+                }
+            }
+            _ => {}
         }
     }
 
     pub fn jump_to_list_top(&mut self) {
         match self.state {
-            AppState::FileNavigation => {
-                if !self.pwd.is_empty() {
-                    self.pwd.select(Some(0));
+            AppState::FileNavigation => self.pwd.select_first(),
+            AppState::Preview => {
+                if let Some(preview) = self.preview.as_mut() {
+                    preview.lines.select_first();
                 }
             }
+            AppState::Copy => {
+                if let Some(dialog) = self.copy_dialog.as_mut() {
+                    dialog.frames.select_first();
+                }
+            }
+            AppState::Templates => self.templates.select_first(),
+            AppState::Check => self.findings.select_first(),
+            AppState::Integrity => self.integrity.select_first(),
+            AppState::Renames => self.renames.select_first(),
+            AppState::Profiles => self.profiles.select_first(),
+            AppState::WriteReport => self.write_report.select_first(),
+            AppState::Health => {
+                if let Some(report) = self.health.as_mut() {
+                    report.issues.select_first();
+                }
+            },
+            AppState::Ape => {
+                if let Some(view) = self.ape.as_mut() {
+                    view.lines.select_first();
+                }
+            },
+            AppState::EncodingReview => {
+                if let Some(review) = self.encoding_review.as_mut() {
+                    review.rows.select_first();
+                }
+            },
+            AppState::BpmReview => {
+                if let Some(review) = self.bpm_review.as_mut() {
+                    review.rows.select_first();
+                }
+            },
+            AppState::Merge => {
+                if let Some(view) = self.merge_view.as_mut() {
+                    view.lines.select_first();
+                }
+            }
+            AppState::Duplicates => {
+                if let Some(view) = self.duplicates.as_mut() {
+                    view.lines.select_first();
+                }
+            }
+            _ => {}
         }
     }
 
     pub fn jump_to_list_bottom(&mut self) {
         match self.state {
-            AppState::FileNavigation => {
-                if !self.pwd.is_empty() {
-                    let index = self.pwd.len() - 1;
-                    self.pwd.select(Some(index));
+            AppState::FileNavigation => self.pwd.select_last(),
+            AppState::Preview => {
+                if let Some(preview) = self.preview.as_mut() {
+                    preview.lines.select_last();
+                }
+            }
+            AppState::Copy => {
+                if let Some(dialog) = self.copy_dialog.as_mut() {
+                    dialog.frames.select_last();
                 }
             }
+            AppState::Templates => self.templates.select_last(),
+            AppState::Check => self.findings.select_last(),
+            AppState::Integrity => self.integrity.select_last(),
+            AppState::Renames => self.renames.select_last(),
+            AppState::Profiles => self.profiles.select_last(),
+            AppState::WriteReport => self.write_report.select_last(),
+            AppState::Health => {
+                if let Some(report) = self.health.as_mut() {
+                    report.issues.select_last();
+                }
+            },
+            AppState::Ape => {
+                if let Some(view) = self.ape.as_mut() {
+                    view.lines.select_last();
+                }
+            },
+            AppState::EncodingReview => {
+                if let Some(review) = self.encoding_review.as_mut() {
+                    review.rows.select_last();
+                }
+            },
+            AppState::BpmReview => {
+                if let Some(review) = self.bpm_review.as_mut() {
+                    review.rows.select_last();
+                }
+            },
+            AppState::Merge => {
+                if let Some(view) = self.merge_view.as_mut() {
+                    view.lines.select_last();
+                }
+            }
+            AppState::Duplicates => {
+                if let Some(view) = self.duplicates.as_mut() {
+                    view.lines.select_last();
+                }
+            }
+            _ => {}
         }
     }
 
     pub fn enter_dir(&mut self) -> Result<(), io::Error> {
-        match self.state {
-            AppState::FileNavigation => {
-                if let Some(entry) = self.pwd.get_selected() {
-                    if entry.is_dir() {
-                        env::set_current_dir(&entry)?;
-                        self.refresh_pwd()?;
-                    }
+        if self.state == AppState::FileNavigation {
+            if let Some(entry) = self.pwd.get_selected() {
+                if entry.is_dir() {
+                    env::set_current_dir(&entry)?;
+                    self.refresh_pwd()?;
                 }
             }
         }
@@ -149,12 +459,10 @@ impl App {
     }
 
     pub fn select(&mut self) -> Result<(), Box<dyn error::Error>> {
-        match self.state {
-            AppState::FileNavigation => {
-                if let Some(entry) = self.pwd.get_selected() {
-                    if self.pwd.get_index().unwrap() != 0 {
-                        self.toggle_select_path(entry)?;
-                    }
+        if self.state == AppState::FileNavigation {
+            if let Some(entry) = self.pwd.get_selected() {
+                if self.pwd.get_index().unwrap() != 0 {
+                    self.toggle_select_path(entry)?;
                 }
             }
         }
@@ -166,10 +474,12 @@ impl App {
         &mut self,
         path: PathBuf
     ) -> Result<(), Box<dyn error::Error>> {
-        if let Some(_) = self.selected_files.get(&path) {
+        if self.selected_files.contains_key(&path) {
             self.selected_files.remove(&path);
+            self.pending_tags.remove(&path);
         } else {
-            if let Ok(tags) = Tag::read_from_path(path.clone()) {
+            if let Ok(tags) = read_tag(&path) {
+                self.library.record(&path, &tags);
                 self.selected_files.insert(path.clone(), Some(tags));
             } else {
                 self.selected_files.insert(path.clone(), None);
@@ -179,12 +489,12 @@ impl App {
         if path.is_dir() {
             let mut entries = Vec::new();
             let mut dirs = fs::read_dir(path.clone())?
-                .filter_map(|res| is_dir(res))
+                .filter_map(is_dir)
                 .collect::<Vec<PathBuf>>();
             dirs.sort();
             entries.append(&mut dirs);
             let mut files = fs::read_dir(path)?
-                .filter_map(|res| is_file(res))
+                .filter_map(is_file)
                 .collect::<Vec<PathBuf>>();
             files.sort();
             entries.append(&mut files);
@@ -204,49 +514,29 @@ impl App {
             .iter()
             .filter_map(|(k, v)| {
                 if k.is_file() {
-                    Some(v.clone().unwrap_or(Tag::new()))
+                    Some(v.clone().unwrap_or_default())
                 } else {
                     None
                 }
             })
             .collect::<Vec<Tag>>();
+        self.tag_sum = summarize_tags(&tags);
 
-        self.tag_sum.clear();
-        for tag in tags {
-            for frame in tag.frames() {
-                let tag_value = match frame.content() {
-                    // TODO: Handle 'TXXX' frames (Custom frame data)
-                    // TODO: Handle 'USLT' frame (Unsynced Lyrics)
-                    // TODO: TCON (Genre) strips out slashes, which is not ideal
-                    Content::Text(text) => text.to_string(),
-                    Content::Comment(comment) => comment.text.to_string(),
-                    Content::Picture(picture) => {
-                        // TODO: Handle empty picture description
-                        format!("{} <{}>", picture.description, picture.mime_type)
-                    },
-                    _ => "<other>".to_string()
-                };
-
-                self.add_to_tag_sum(frame.id().to_string(), tag_value);
-            }
-        }
-    }
-
-    fn add_to_tag_sum(&mut self, key: String, value: String) {
-        if self.tag_sum.contains_key(&key) {
-            // Ignore the case where the values are the same
-            if self.tag_sum.get(&key).unwrap() != &value {
-                self.tag_sum.insert(key, "<multiple>".to_string());
-            }
+        if self.pending_tags.is_empty() {
+            self.new_tag_sum.clear();
         } else {
-            self.tag_sum.insert(key, value);
+            let tags = self.selected_tags()
+                .into_iter()
+                .map(|(_, tag)| tag)
+                .collect::<Vec<Tag>>();
+            self.new_tag_sum = summarize_tags(&tags);
         }
     }
 
     pub fn num_selected_files(&self) -> usize {
         let count = self.selected_files
-            .iter()
-            .filter_map(|(path, _)| {
+            .keys()
+            .filter_map(|path| {
                 if path.is_file() {
                     Some(1)
                 } else {
@@ -260,10 +550,1217 @@ impl App {
 
     pub fn clear_selected_files(&mut self) {
         self.selected_files.clear();
+        self.pending_tags.clear();
+        self.update_tag_sum();
+    }
+
+    // Selected files (not directories) in path order
+    pub fn selected_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.selected_files
+            .keys()
+            .filter(|path| path.is_file())
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+
+        paths
+    }
+
+    // The tag as it would be written, including any pending edits
+    pub fn working_tag(&self, path: &PathBuf) -> Tag {
+        if let Some(tag) = self.pending_tags.get(path) {
+            return tag.clone();
+        }
+
+        match self.selected_files.get(path) {
+            Some(Some(tag)) => tag.clone(),
+            _ => Tag::new(),
+        }
+    }
+
+    pub fn selected_tags(&self) -> Vec<(PathBuf, Tag)> {
+        self.selected_paths()
+            .into_iter()
+            .map(|path| {
+                let tag = self.working_tag(&path);
+                (path, tag)
+            })
+            .collect()
+    }
+
+    pub fn stage_tag(&mut self, path: PathBuf, tag: Tag) {
+        self.pending_tags.insert(path, tag);
+    }
+
+    // Pending edits only live in memory, so ask before throwing them away
+    pub fn request_quit(&mut self) {
+        if self.pending_tags.is_empty() {
+            self.quit = true;
+            return;
+        }
+
+        self.confirm_quit = true;
+        self.status = Some(format!(
+            "{} files have unwritten edits, quit anyway? (y/n)",
+            self.pending_tags.len()
+        ));
+    }
+
+    pub fn discard_pending_tags(&mut self) {
+        self.pending_tags.clear();
+        self.update_tag_sum();
+        self.status = Some("Discarded pending edits".to_string());
+    }
+
+    // Writes pending edits under the default profile, if one is set
+    pub fn write_pending_tags(&mut self) {
+        let profile = match self.default_profile.as_deref().map(find_profile) {
+            Some(Ok(profile)) => Some(profile),
+            Some(Err(err)) => {
+                self.status = Some(format!("Could not load the default write profile: {}", err));
+                return;
+            },
+            None => None,
+        };
+
+        self.start_write(profile);
+    }
+
+    // Anything the profile can't carry over is reported before writing
+    fn start_write(&mut self, profile: Option<WriteProfile>) {
+        if let Some(profile) = profile.as_ref() {
+            let mut tags = self.pending_tags
+                .iter()
+                .map(|(path, tag)| (path.clone(), tag.clone()))
+                .collect::<Vec<(PathBuf, Tag)>>();
+            tags.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let findings = profile_report(profile, &tags);
+            if !findings.is_empty() {
+                self.write_report = GenericList::from(findings);
+                self.write_profile = Some(profile.clone());
+                self.state = AppState::WriteReport;
+                return;
+            }
+        }
+
+        self.write_with_profile(profile.as_ref());
+    }
+
+    pub fn confirm_write(&mut self) {
+        let profile = self.write_profile.take();
+        self.state = AppState::FileNavigation;
+        self.write_with_profile(profile.as_ref());
+    }
+
+    pub fn cancel_write(&mut self) {
+        self.write_profile = None;
+        self.state = AppState::FileNavigation;
+        self.status = Some("Write cancelled, edits are still pending".to_string());
+    }
+
+    fn write_with_profile(&mut self, profile: Option<&WriteProfile>) {
+        let mut paths = self.pending_tags.keys().cloned().collect::<Vec<PathBuf>>();
+        paths.sort();
+
+        let mut errors = Vec::new();
+        let mut written = 0;
+        for path in paths {
+            let original = self.pending_tags.remove(&path).unwrap();
+            let tag = match (profile, Container::from_path(&path)) {
+                (Some(profile), Some(container)) if container.has_id3() => {
+                    profile.convert(&original, container).0
+                },
+                _ => original.clone(),
+            };
+            let result = if self.verify_writes {
+                write_verified(&tag, &path, profile, &mut self.hash_cache)
+            } else {
+                write_tag_as(&tag, &path, profile)
+            };
+            match result {
+                Ok(_) => {
+                    self.library.record(&path, &tag);
+                    self.selected_files.insert(path, Some(tag));
+                    written += 1;
+                },
+                Err(err) => {
+                    let mismatch = is_audio_mismatch(&err);
+                    errors.push(format!("{}: {}", path.display(), err));
+                    self.pending_tags.insert(path, original);
+                    // Stop the batch rather than risk any more files
+                    if mismatch {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if self.verify_writes {
+            let _ = self.hash_cache.save();
+        }
+
+        self.update_tag_sum();
+        self.status = if errors.is_empty() {
+            match profile {
+                Some(profile) => Some(format!("Wrote tags to {} files as {}", written, profile.name)),
+                None => Some(format!("Wrote tags to {} files", written)),
+            }
+        } else {
+            Some(format!("Failed to write {} files ({})", errors.len(), errors[0]))
+        };
+    }
+
+    pub fn open_profiles(&mut self) {
+        match list_profiles() {
+            Ok(profiles) => {
+                self.profiles = GenericList::from(profiles);
+                self.state = AppState::Profiles;
+            },
+            Err(err) => self.status = Some(format!("Could not load write profiles: {}", err)),
+        }
+    }
+
+    pub fn write_with_selected_profile(&mut self) {
+        if let Some(profile) = self.profiles.get_selected() {
+            self.state = AppState::FileNavigation;
+            self.start_write(Some(profile));
+        }
+    }
+
+    // Makes the highlighted profile the default, or clears the default if
+    // it already is
+    pub fn toggle_default_profile(&mut self) {
+        let name = match self.profiles.get_selected() {
+            Some(profile) => profile.name,
+            None => return,
+        };
+        let default = if self.default_profile.as_deref() == Some(name.as_str()) {
+            None
+        } else {
+            Some(name)
+        };
+
+        self.status = Some(match save_default_profile(default.as_deref()) {
+            Ok(_) => match default.as_ref() {
+                Some(name) => format!("Writing as {} by default", name),
+                None => "Cleared the default write profile".to_string(),
+            },
+            Err(err) => format!("Could not save the default write profile: {}", err),
+        });
+        self.default_profile = default;
+    }
+
+    pub fn toggle_verify_writes(&mut self) {
+        self.verify_writes = !self.verify_writes;
+        self.status = Some(format!(
+            "Audio verification on write {}",
+            if self.verify_writes { "enabled" } else { "disabled" }
+        ));
+    }
+
+    pub fn open_integrity(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        let mut paths = self.selected_paths();
+        paths.sort();
+        self.integrity = GenericList::from(verify_integrity(&paths, &mut self.hash_cache));
+        if let Err(err) = self.hash_cache.save() {
+            self.status = Some(format!("Could not save audio hashes: {}", err));
+        }
+        self.state = AppState::Integrity;
+    }
+
+    pub fn jump_to_integrity_finding(&mut self) -> Result<(), io::Error> {
+        if let Some(finding) = self.integrity.get_selected() {
+            self.jump_to_file(&finding.path)?;
+            self.state = AppState::FileNavigation;
+        }
+
+        Ok(())
+    }
+
+    pub fn open_health(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        // Only MPEG streams are walked frame by frame
+        let paths = self.selected_paths()
+            .into_iter()
+            .filter(|path| Container::from_path(path) == Some(Container::Mpeg))
+            .collect::<Vec<PathBuf>>();
+        let issues = paths
+            .iter()
+            .flat_map(|path| check_file(path))
+            .collect::<Vec<HealthIssue>>();
+        self.health = Some(HealthReport::new(issues, paths.len()));
+        self.state = AppState::Health;
+    }
+
+    pub fn jump_to_health_issue(&mut self) -> Result<(), io::Error> {
+        let issue = self.health
+            .as_ref()
+            .and_then(|report| report.issues.get_selected());
+        if let Some(issue) = issue {
+            self.jump_to_file(&issue.path)?;
+            self.state = AppState::FileNavigation;
+        }
+
+        Ok(())
+    }
+
+    pub fn open_ape(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        // APE tags are only looked for at the end of MPEG streams
+        let paths = self.selected_paths()
+            .into_iter()
+            .filter(|path| Container::from_path(path) == Some(Container::Mpeg))
+            .collect::<Vec<PathBuf>>();
+        let files = paths
+            .iter()
+            .filter_map(|path| {
+                let tag = read_ape_tag(path).ok()??;
+                Some(ApeFile { path: path.clone(), tag })
+            })
+            .collect::<Vec<ApeFile>>();
+        if files.is_empty() {
+            self.status = Some(format!("No APE tags in {} MPEG files", paths.len()));
+            return;
+        }
+
+        self.ape = Some(ApeView::new(files, paths.len()));
+        self.state = AppState::Ape;
+    }
+
+    pub fn preview_ape_migration(&mut self, overwrite: bool) {
+        let diffs = match self.ape.as_ref() {
+            Some(view) => view.files
+                .iter()
+                .map(|file| view.diff(file, &self.working_tag(&file.path), overwrite))
+                .collect::<Vec<FileDiff>>(),
+            None => return,
+        };
+
+        self.open_preview("Migrate APE Tags".to_string(), diffs);
+    }
+
+    pub fn remove_ape_tags(&mut self) {
+        let view = match self.ape.take() {
+            Some(view) => view,
+            None => return,
+        };
+
+        let mut errors = Vec::new();
+        for file in view.files.iter() {
+            if let Err(err) = remove_ape_tag(&file.path) {
+                errors.push(format!("{}: {}", file.path.display(), err));
+            }
+        }
+
+        self.state = AppState::FileNavigation;
+        self.status = Some(if errors.is_empty() {
+            format!("Removed APE tags from {} files", view.files.len())
+        } else {
+            format!("Could not remove {} APE tags: {}", errors.len(), errors.join(", "))
+        });
+    }
+
+    pub fn open_encoding(&mut self) {
+        self.encoding_form.error = None;
+        self.state = AppState::EncodingForm;
+    }
+
+    // Looks for frames to repair in the selection, or for likely mojibake in
+    // every file under a directory. Files found by a scan are selected so
+    // their repairs can be staged like any other edit.
+    pub fn find_mojibake(&mut self) {
+        let filter = FrameFilter::parse(self.encoding_form.value(0));
+        let library = self.encoding_form.value(1).trim().to_string();
+
+        let rows = if library.is_empty() {
+            if self.num_selected_files() == 0 {
+                self.encoding_form.error = Some("No files selected".to_string());
+                return;
+            }
+
+            self.selected_tags()
+                .iter()
+                .flat_map(|(path, tag)| encoding_rows(path, tag, &filter))
+                .collect::<Vec<EncodingRow>>()
+        } else {
+            let root = match PathBuf::from(&library).canonicalize() {
+                Ok(root) if root.is_dir() => root,
+                _ => {
+                    self.encoding_form.error = Some("Not a directory".to_string());
+                    return;
+                }
+            };
+
+            let mut rows = Vec::new();
+            for path in audio_files_in(&root) {
+                let tag = match self.selected_files.get(&path) {
+                    Some(_) => self.working_tag(&path),
+                    None => match read_tag(&path) {
+                        Ok(tag) => tag,
+                        Err(_) => continue,
+                    },
+                };
+                let mut likely = encoding_rows(&path, &tag, &filter)
+                    .into_iter()
+                    .filter(|row| row.likely)
+                    .collect::<Vec<EncodingRow>>();
+                if likely.is_empty() {
+                    continue;
+                }
+
+                if !self.selected_files.contains_key(&path) {
+                    self.library.record(&path, &tag);
+                    self.selected_files.insert(path.clone(), Some(tag));
+                }
+                rows.append(&mut likely);
+            }
+            self.update_tag_sum();
+
+            rows
+        };
+        if rows.is_empty() {
+            self.encoding_form.error = Some("No mis-encoded text found".to_string());
+            return;
+        }
+
+        self.encoding_review = Some(EncodingReview::new(rows));
+        self.state = AppState::EncodingReview;
+    }
+
+    pub fn preview_encoding(&mut self) {
+        let diffs = match self.encoding_review.as_ref() {
+            Some(review) => review.diffs(),
+            None => return,
+        };
+
+        self.open_preview("Repair Text Encoding".to_string(), diffs);
+    }
+
+    pub fn open_find_replace(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.replace_form.error = None;
+        self.state = AppState::FindReplace;
+    }
+
+    pub fn preview_find_replace(&mut self) {
+        let diffs = match find_and_replace(&self.selected_tags(), &self.replace_form) {
+            Ok(diffs) => diffs,
+            Err(err) => {
+                self.replace_form.error = Some(err.to_string());
+                return;
+            }
+        };
+
+        self.open_preview("Find and Replace".to_string(), diffs);
+    }
+
+    pub fn open_transform(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.transform_dialog.load_frames(&self.selected_tags());
+        self.state = AppState::Transform;
+    }
+
+    pub fn preview_transform(&mut self) {
+        let diffs = transform_tags(&self.selected_tags(), &self.transform_dialog);
+
+        self.open_preview("Transform Text".to_string(), diffs);
+    }
+
+    pub fn open_normalize(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.normalize_form.error = None;
+        self.state = AppState::NormalizeForm;
+    }
+
+    pub fn find_filename_renames(&mut self) {
+        let form = match NormalForm::parse(self.normalize_form.value(0)) {
+            Some(form) => form,
+            None => {
+                self.normalize_form.error = Some("Form must be NFC or NFD".to_string());
+                return;
+            }
+        };
+
+        let renames = find_renames(&self.selected_paths(), form);
+        if renames.is_empty() {
+            self.normalize_form.error = Some(format!("Every filename is already {}", form.name()));
+            return;
+        }
+
+        self.renames = GenericList::from(renames);
+        self.state = AppState::Renames;
+    }
+
+    // Renamed files stay selected, with their pending edits and stored audio
+    // hashes following them to the new path
+    pub fn rename_files(&mut self) -> Result<(), io::Error> {
+        let renames = self.renames.items.clone();
+        let mut errors = Vec::new();
+        for rename in renames.iter() {
+            if let Err(err) = rename_file(rename) {
+                errors.push(format!("{}: {}", rename.from.display(), err));
+                continue;
+            }
+
+            if let Some(tag) = self.selected_files.remove(&rename.from) {
+                self.selected_files.insert(rename.to.clone(), tag);
+            }
+            if let Some(tag) = self.pending_tags.remove(&rename.from) {
+                self.pending_tags.insert(rename.to.clone(), tag);
+            }
+            self.library.rename(&rename.from, &rename.to);
+            self.hash_cache.rename(&rename.from, &rename.to);
+        }
+        let _ = self.hash_cache.save();
+
+        self.state = AppState::FileNavigation;
         self.update_tag_sum();
+        self.refresh_pwd()?;
+        self.status = Some(if errors.is_empty() {
+            format!("Renamed {} files", renames.len())
+        } else {
+            format!("Could not rename {} files: {}", errors.len(), errors.join(", "))
+        });
+
+        Ok(())
+    }
+
+    pub fn open_split(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.split_form.error = None;
+        self.state = AppState::Split;
+    }
+
+    pub fn preview_split(&mut self) {
+        let diffs = match split_field(&self.selected_tags(), &self.split_form) {
+            Ok(diffs) => diffs,
+            Err(err) => {
+                self.split_form.error = Some(err);
+                return;
+            }
+        };
+
+        self.open_preview("Split Field".to_string(), diffs);
+    }
+
+    pub fn open_length(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.length_form.error = None;
+        self.state = AppState::Length;
+    }
+
+    pub fn preview_length(&mut self) {
+        let diffs = match fill_lengths(&self.selected_tags(), &self.length_form) {
+            Ok(diffs) => diffs,
+            Err(err) => {
+                self.length_form.error = Some(err);
+                return;
+            }
+        };
+
+        self.open_preview("Fill Length".to_string(), diffs);
+    }
+
+    // The highlighted file in the navigator and its tag, including any
+    // pending edits if it is selected
+    fn highlighted_tag(&self) -> Option<(PathBuf, Tag)> {
+        let path = self.pwd.get_selected().filter(|path| path.is_file())?;
+        let tag = if self.selected_files.contains_key(&path) {
+            self.working_tag(&path)
+        } else {
+            read_tag(&path).unwrap_or_default()
+        };
+
+        Some((path, tag))
+    }
+
+    // Stream info of the highlighted file, read once per highlighted path
+    pub fn highlighted_stream_info(&mut self) -> Option<&StreamInfo> {
+        let path = self.pwd.get_selected().filter(|path| path.is_file())?;
+        let is_stale = match &self.stream_info {
+            Some((cached, _)) => *cached != path,
+            None => true,
+        };
+        if is_stale {
+            let info = read_stream_info(&path).ok();
+            self.stream_info = Some((path, info));
+        }
+
+        self.stream_info.as_ref().and_then(|(_, info)| info.as_ref())
+    }
+
+    pub fn copy_highlighted_tag(&mut self) {
+        let (path, tag) = match self.highlighted_tag() {
+            Some(highlighted) => highlighted,
+            None => {
+                self.status = Some("Highlight a file to copy its tag".to_string());
+                return;
+            }
+        };
+        let frames = tag.frames().cloned().collect::<Vec<Frame>>();
+
+        let source = path.file_name().unwrap().to_string_lossy().to_string();
+        self.open_copy_dialog(source, frames);
+    }
+
+    pub fn copy_selection_tag(&mut self) {
+        let tags = self.selected_tags()
+            .into_iter()
+            .map(|(_, tag)| tag)
+            .collect::<Vec<Tag>>();
+        let frames = common_frames(&tags);
+
+        let source = format!("{} Selected Files", tags.len());
+        self.open_copy_dialog(source, frames);
+    }
+
+    fn open_copy_dialog(&mut self, source: String, frames: Vec<Frame>) {
+        if frames.is_empty() {
+            self.status = Some("Nothing to copy".to_string());
+            return;
+        }
+
+        self.copy_dialog = Some(CopyDialog::new(source, frames));
+        self.state = AppState::Copy;
+    }
+
+    pub fn confirm_copy(&mut self) {
+        if let Some(dialog) = self.copy_dialog.take() {
+            self.clipboard = dialog.checked_frames();
+            self.status = Some(format!("Copied {} frames", self.clipboard.len()));
+        }
+        self.state = AppState::FileNavigation;
+    }
+
+    pub fn cancel_copy(&mut self) {
+        self.copy_dialog = None;
+        self.state = AppState::FileNavigation;
+    }
+
+    pub fn paste_clipboard(&mut self) {
+        if self.clipboard.is_empty() {
+            self.status = Some("Clipboard is empty".to_string());
+            return;
+        }
+
+        let tags = self.selected_tags();
+        for (path, mut tag) in tags.into_iter() {
+            paste_frames(&mut tag, &self.clipboard);
+            self.stage_tag(path, tag);
+        }
+
+        self.update_tag_sum();
+        self.status = Some(format!(
+            "Pasted {} frames onto {} files, press 'w' to write them",
+            self.clipboard.len(),
+            self.num_selected_files()
+        ));
+    }
+
+    pub fn open_templates(&mut self) {
+        match list_templates() {
+            Ok(names) => {
+                self.templates = GenericList::from(names);
+                self.confirm_template_delete = false;
+                self.state = AppState::Templates;
+            },
+            Err(err) => self.status = Some(format!("Could not load templates: {}", err)),
+        }
+    }
+
+    pub fn apply_template(&mut self) {
+        let name = match self.templates.get_selected() {
+            Some(name) => name,
+            None => return,
+        };
+        let template = match Template::load(&name) {
+            Ok(template) => template,
+            Err(err) => {
+                self.status = Some(format!("Could not load template '{}': {}", name, err));
+                return;
+            }
+        };
+
+        let tags = self.selected_tags();
+        let num_files = tags.len();
+        for (path, mut tag) in tags.into_iter() {
+            template.apply(&mut tag, &path);
+            self.stage_tag(path, tag);
+        }
+
+        self.update_tag_sum();
+        self.status = Some(format!(
+            "Applied template '{}' to {} files, press 'w' to write them",
+            name, num_files
+        ));
+        self.state = AppState::FileNavigation;
+    }
+
+    pub fn delete_selected_template(&mut self) {
+        if let Some(name) = self.templates.get_selected() {
+            if let Err(err) = delete_template(&name) {
+                self.status = Some(format!("Could not delete template '{}': {}", name, err));
+            }
+            self.open_templates();
+        }
+    }
+
+    pub fn open_template_name(&mut self) {
+        if self.highlighted_tag().is_none() {
+            self.status = Some("Highlight a file to create a template from".to_string());
+            return;
+        }
+
+        self.template_form.error = None;
+        self.state = AppState::TemplateName;
+    }
+
+    pub fn save_template(&mut self) {
+        let name = self.template_form.value(0).trim().to_string();
+        if !is_valid_template_name(&name) {
+            self.template_form.error = Some("Invalid template name".to_string());
+            return;
+        }
+
+        let (_, tag) = match self.highlighted_tag() {
+            Some(highlighted) => highlighted,
+            None => return,
+        };
+        match Template::from_tag(name.clone(), &tag).save() {
+            Ok(_) => {
+                self.status = Some(format!("Saved template '{}'", name));
+                self.open_templates();
+            },
+            Err(err) => self.template_form.error = Some(err.to_string()),
+        }
+    }
+
+    pub fn open_edit(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.edit_dialog.form.error = None;
+        self.edit_dialog.form.focus = 0;
+        self.edit_dialog.suggestions.clear();
+        self.state = AppState::Edit;
+    }
+
+    pub fn edit_switch_field(&mut self, forward: bool) {
+        let dialog = &mut self.edit_dialog;
+        if forward {
+            dialog.form.next_field();
+        } else {
+            dialog.form.prev_field();
+        }
+
+        // Start from the current value when all selected files share one
+        if dialog.editing_value() && dialog.form.value(1).is_empty() {
+            let sum = if self.pending_tags.is_empty() {
+                &self.tag_sum
+            } else {
+                &self.new_tag_sum
+            };
+            if let Some(id) = frame_for_field(dialog.form.value(0).trim()) {
+                if let Some(value) = sum.get(&id).filter(|value| *value != "<multiple>") {
+                    dialog.form.inputs[1] = TextInput::from(value.clone());
+                }
+            }
+        }
+
+        self.refresh_suggestions();
+    }
+
+    pub fn refresh_suggestions(&mut self) {
+        let dialog = &self.edit_dialog;
+        let id = frame_for_field(dialog.form.value(0).trim());
+        let suggestions = match id {
+            Some(id) if dialog.editing_value() => {
+                self.library.suggestions(&id, dialog.form.value(1), &self.selected_tags())
+            },
+            _ => Vec::new(),
+        };
+
+        self.edit_dialog.set_suggestions(suggestions);
+    }
+
+    pub fn apply_edit(&mut self) {
+        let form = &mut self.edit_dialog.form;
+        let id = match frame_for_field(form.value(0).trim()) {
+            Some(id) => id,
+            None => {
+                form.error = Some(format!("'{}' is not a text frame", form.value(0).trim()));
+                return;
+            }
+        };
+        let value = form.value(1).to_string();
+
+        let tags = self.selected_tags();
+        let num_files = tags.len();
+        for (path, mut tag) in tags.into_iter() {
+            if value.is_empty() {
+                remove_frame_value(&mut tag, &id);
+            } else {
+                set_frame_value(&mut tag, &id, &value);
+            }
+            self.stage_tag(path, tag);
+        }
+
+        self.update_tag_sum();
+        self.status = Some(format!(
+            "Staged {} for {} files, press 'w' to write them",
+            id, num_files
+        ));
+        self.state = AppState::FileNavigation;
+    }
+
+    pub fn open_check(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.run_check();
+        self.state = AppState::Check;
+    }
+
+    fn run_check(&mut self) {
+        let files = self.selected_tags()
+            .into_iter()
+            .map(|(path, tag)| {
+                let version = self.selected_files
+                    .get(&path)
+                    .and_then(|tag| tag.as_ref().map(|tag| tag.version()));
+                LintFile { path, tag, version }
+            })
+            .collect::<Vec<LintFile>>();
+
+        let index = self.findings.get_index();
+        self.findings = GenericList::from(lint(&files));
+        if let Some(index) = index {
+            if !self.findings.is_empty() {
+                self.findings.select(Some(index.min(self.findings.len() - 1)));
+            }
+        }
+    }
+
+    pub fn fix_finding(&mut self) {
+        if let Some(finding) = self.findings.get_selected() {
+            self.apply_fixes(vec![finding]);
+        }
+    }
+
+    pub fn fix_all_findings(&mut self) {
+        let findings = self.findings.items.clone();
+        self.apply_fixes(findings);
+    }
+
+    fn apply_fixes(&mut self, findings: Vec<Finding>) {
+        let mut fixed = 0;
+        for finding in findings {
+            if let Some(fix) = finding.fix {
+                let mut tag = self.working_tag(&finding.path);
+                set_frame_value(&mut tag, &fix.id, &fix.new);
+                self.stage_tag(finding.path, tag);
+                fixed += 1;
+            }
+        }
+
+        self.update_tag_sum();
+        self.run_check();
+        self.status = Some(if fixed == 0 {
+            "No suggested fix".to_string()
+        } else {
+            format!("Staged {} fixes, press 'w' to write them", fixed)
+        });
+    }
+
+    pub fn jump_to_finding(&mut self) -> Result<(), io::Error> {
+        if let Some(finding) = self.findings.get_selected() {
+            self.jump_to_file(&finding.path)?;
+            self.state = AppState::FileNavigation;
+        }
+
+        Ok(())
+    }
+
+    // Opens the file's directory in the navigator and highlights the file
+    pub fn jump_to_file(&mut self, path: &PathBuf) -> Result<(), io::Error> {
+        if let Some(parent) = path.parent() {
+            env::set_current_dir(parent)?;
+            self.refresh_pwd()?;
+            if let Some(index) = self.pwd.items.iter().position(|item| item == path) {
+                self.pwd.select(Some(index));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn open_merge(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.merge_form.error = None;
+        self.state = AppState::MergeField;
+    }
+
+    pub fn find_merge_clusters(&mut self) {
+        let id = match frame_for_field(self.merge_form.value(0).trim()) {
+            Some(id) => id,
+            None => {
+                self.merge_form.error = Some("Not a text frame".to_string());
+                return;
+            }
+        };
+
+        let view = MergeView::new(id, &self.selected_tags());
+        if view.clusters.is_empty() {
+            self.merge_form.error = Some("No differing spellings found".to_string());
+            return;
+        }
+
+        self.merge_view = Some(view);
+        self.state = AppState::Merge;
+    }
+
+    pub fn preview_merge(&mut self) {
+        let diffs = match self.merge_view.as_ref() {
+            Some(view) => view.diffs(),
+            None => return,
+        };
+        if diffs.is_empty() {
+            self.status = Some("Choose a canonical spelling with Space first".to_string());
+            return;
+        }
+
+        self.open_preview("Merge Spellings".to_string(), diffs);
+    }
+
+    pub fn open_duplicates(&mut self) {
+        self.duplicates_form.error = None;
+        self.state = AppState::DuplicatesForm;
+    }
+
+    pub fn find_duplicates(&mut self) {
+        let form = &mut self.duplicates_form;
+        let root = match PathBuf::from(form.value(0).trim()).canonicalize() {
+            Ok(root) if root.is_dir() => root,
+            _ => {
+                form.error = Some("Not a directory".to_string());
+                return;
+            }
+        };
+        let match_audio = match form.value(1).trim().to_lowercase().as_str() {
+            "tags" => false,
+            "audio" => true,
+            _ => {
+                form.error = Some("Match must be tags or audio".to_string());
+                return;
+            }
+        };
+        let match_album = form.value(2).trim().to_lowercase().starts_with('y');
+        let tolerance = match form.value(3).trim() {
+            "" => None,
+            value => match value.parse::<f64>() {
+                Ok(tolerance) if tolerance.is_finite() && tolerance >= 0.0 => Some(tolerance),
+                _ => {
+                    form.error = Some("Tolerance must be a number of seconds, 0 or more".to_string());
+                    return;
+                }
+            },
+        };
+
+        let groups = if match_audio {
+            let files = audio_files_in(&root)
+                .into_iter()
+                .filter_map(|path| {
+                    let hash = self.hash_cache.hash(&path).ok()?;
+                    let tag = read_tag(&path).unwrap_or_default();
+                    Some((path, tag, hash))
+                })
+                .collect::<Vec<(PathBuf, Tag, String)>>();
+            if let Err(error) = self.hash_cache.save() {
+                self.status = Some(format!("Could not save audio hashes: {}", error));
+            }
+            find_audio_duplicates(files)
+        } else {
+            let files = audio_files_in(&root)
+                .into_iter()
+                .filter_map(|path| {
+                    let tag = read_tag(&path).ok()?;
+                    Some((path, tag))
+                })
+                .collect::<Vec<(PathBuf, Tag)>>();
+            find_duplicates(files, match_album, tolerance)
+        };
+        if groups.is_empty() {
+            self.duplicates_form.error = Some("No duplicates found".to_string());
+            return;
+        }
+
+        self.duplicates = Some(DuplicateView::new(root, groups));
+        self.state = AppState::Duplicates;
+    }
+
+    pub fn remove_duplicates(&mut self) -> Result<(), io::Error> {
+        let paths = match self.duplicates.as_ref() {
+            Some(view) => view.files_to_remove(),
+            None => return Ok(()),
+        };
+
+        // A file that can't be removed shouldn't stop the rest, or take any
+        // pending edits down with it
+        let mut errors = Vec::new();
+        for path in paths.iter() {
+            if let Err(err) = fs::remove_file(path) {
+                errors.push(format!("{}: {}", path.display(), err));
+                continue;
+            }
+            self.selected_files.remove(path);
+            self.pending_tags.remove(path);
+        }
+
+        self.duplicates = None;
+        self.state = AppState::FileNavigation;
+        self.update_tag_sum();
+        self.refresh_pwd()?;
+        self.status = Some(if errors.is_empty() {
+            format!("Removed {} duplicate files", paths.len())
+        } else {
+            format!(
+                "Removed {} duplicate files, could not remove {}: {}",
+                paths.len() - errors.len(),
+                errors.len(),
+                errors.join(", ")
+            )
+        });
+
+        Ok(())
+    }
+
+    pub fn open_replaygain(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        self.replaygain_form.error = None;
+        self.state = AppState::ReplayGainForm;
+    }
+
+    pub fn start_replaygain(&mut self) {
+        let form = &mut self.replaygain_form;
+        let grouping = match AlbumGrouping::parse(form.value(0)) {
+            Some(grouping) => grouping,
+            None => {
+                form.error = Some("Album must be directory, album or none".to_string());
+                return;
+            }
+        };
+        let rva2 = form.value(1).trim().to_lowercase().starts_with('y');
+
+        let mut tags = self.selected_tags();
+        tags.sort_by(|a, b| a.0.cmp(&b.0));
+        self.gain_job = Some(GainJob::start(tags, grouping, rva2));
+        self.state = AppState::ReplayGain;
+    }
+
+    pub fn cancel_replaygain(&mut self) {
+        if let Some(gain_job) = self.gain_job.take() {
+            gain_job.job.cancel();
+        }
+        self.status = Some("ReplayGain analysis cancelled".to_string());
+        self.state = AppState::FileNavigation;
+    }
+
+    // Opens the preview once the background analysis has finished
+    fn poll_gain_job(&mut self) {
+        let done = match self.gain_job.as_mut() {
+            Some(gain_job) => gain_job.job.poll(),
+            None => return,
+        };
+
+        if done {
+            let gain_job = self.gain_job.take().unwrap();
+            self.state = AppState::ReplayGainForm;
+            self.open_preview("ReplayGain".to_string(), gain_job.diffs());
+        }
+    }
+
+    pub fn start_bpm(&mut self) {
+        if self.num_selected_files() == 0 {
+            self.status = Some("No files selected".to_string());
+            return;
+        }
+
+        let mut paths = self.selected_paths();
+        paths.sort();
+        self.bpm_job = Some(Job::start(paths, estimate_tempo));
+        self.state = AppState::Bpm;
+    }
+
+    pub fn cancel_bpm(&mut self) {
+        if let Some(job) = self.bpm_job.take() {
+            job.cancel();
+        }
+        self.status = Some("BPM detection cancelled".to_string());
+        self.state = AppState::FileNavigation;
+    }
+
+    // Opens the review table once the background analysis has finished
+    fn poll_bpm_job(&mut self) {
+        let done = match self.bpm_job.as_mut() {
+            Some(job) => job.poll(),
+            None => return,
+        };
+
+        if done {
+            let job = self.bpm_job.take().unwrap();
+            let tags = self.selected_tags().into_iter().collect();
+            self.bpm_review = Some(BpmReview::new(job.results, &tags));
+            self.state = AppState::BpmReview;
+        }
+    }
+
+    pub fn preview_bpm(&mut self) {
+        if let Some(review) = self.bpm_review.as_ref() {
+            let diffs = review.diffs();
+            self.open_preview("BPM".to_string(), diffs);
+        }
+    }
+
+    pub fn open_preview(&mut self, title: String, diffs: Vec<FileDiff>) {
+        self.preview = Some(Preview::new(title, diffs, self.state));
+        self.state = AppState::Preview;
+    }
+
+    pub fn close_preview(&mut self) {
+        if let Some(preview) = self.preview.take() {
+            self.state = preview.return_state;
+        } else {
+            self.state = AppState::FileNavigation;
+        }
+    }
+
+    // Stages every change in the preview as a pending edit
+    pub fn apply_preview(&mut self) {
+        let preview = match self.preview.take() {
+            Some(preview) => preview,
+            None => return,
+        };
+
+        for diff in preview.diffs.iter() {
+            if diff.changes.is_empty() {
+                continue;
+            }
+
+            let mut tag = self.working_tag(&diff.path);
+            for change in diff.changes.iter() {
+                if change.new.is_empty() {
+                    remove_frame_value(&mut tag, &change.id);
+                } else {
+                    set_frame_value(&mut tag, &change.id, &change.new);
+                }
+            }
+            self.stage_tag(diff.path.clone(), tag);
+        }
+
+        self.update_tag_sum();
+        self.status = Some(format!(
+            "Staged edits for {} files, press 'w' to write them",
+            preview.num_changed_files()
+        ));
+        self.state = AppState::FileNavigation;
+    }
+}
+
+fn summarize_tags(tags: &[Tag]) -> HashMap<String, String> {
+    let mut sum = HashMap::new();
+    for tag in tags {
+        for frame in tag.frames() {
+            add_to_tag_sum(&mut sum, frame.id().to_string(), frame_value(frame));
+        }
+    }
+
+    sum
+}
+
+fn add_to_tag_sum(sum: &mut HashMap<String, String>, key: String, value: String) {
+    if sum.contains_key(&key) {
+        let current = sum.get(&key).unwrap();
+        // Ignore the case where the values are the same, including composed
+        // and decomposed spellings of the same characters
+        if !current.nfc().eq(value.nfc()) {
+            sum.insert(key, "<multiple>".to_string());
+        } else if current != "<multiple>" && !is_nfc(&value) {
+            // Show a decomposed spelling so the value is marked as not NFC
+            sum.insert(key, value);
+        }
+    } else {
+        sum.insert(key, value);
     }
 }
 
+// Every supported file under the directory, recursively, in path order
+pub fn audio_files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => {
+                    files.append(&mut audio_files_in(&path));
+                },
+                Ok(file_type) if file_type.is_file() && is_audio_file(&path) => {
+                    files.push(path);
+                },
+                _ => {}
+            }
+        }
+    }
+    files.sort();
+
+    files
+}
+
 fn is_dir(res: Result<DirEntry, io::Error>) -> Option<PathBuf> {
     let entry = res.as_ref().unwrap();
     let file_type = entry.file_type().unwrap();
@@ -277,18 +1774,8 @@ fn is_dir(res: Result<DirEntry, io::Error>) -> Option<PathBuf> {
 fn is_file(res: Result<DirEntry, io::Error>) -> Option<PathBuf> {
     let entry = res.as_ref().unwrap();
     let file_type = entry.file_type().unwrap();
-    if file_type.is_file() {
-        // Only accept mp3 files
-        // TODO: In the future, we want to accept any filetype that can use id3 tags
-        if let Some(extension) = entry.path().extension() {
-            if extension == "mp3" {
-                Some(entry.path().canonicalize().unwrap())
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+    if file_type.is_file() && is_audio_file(&entry.path()) {
+        Some(entry.path().canonicalize().unwrap())
     } else {
         None
     }
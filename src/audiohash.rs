@@ -0,0 +1,366 @@
+use sha2::{
+    Digest,
+    Sha256,
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::UNIX_EPOCH,
+};
+
+use id3::Tag;
+
+use crate::config::*;
+use crate::container::*;
+use crate::flac::*;
+use crate::lint::*;
+use crate::mp4::*;
+use crate::mpeg::*;
+use crate::ogg::*;
+use crate::profiles::*;
+
+const CACHE_FILE: &str = "audio_hashes";
+
+// SHA-256 of the MPEG audio frames only, so that retagging a file never
+// changes its hash. The Xing/LAME frame is skipped as encoders and taggers
+// may rewrite it.
+pub fn audio_hash(data: &[u8]) -> Option<String> {
+    let frames = walk_frames(data);
+    let mut hasher = Sha256::new();
+    let mut hashed = 0;
+
+    for (i, (pos, header)) in frames.iter().enumerate() {
+        if i == 0 && is_info_frame(data, *pos, header) {
+            continue;
+        }
+        hasher.update(&data[*pos..*pos + header.frame_len()]);
+        hashed += 1;
+    }
+
+    if hashed == 0 {
+        return None;
+    }
+
+    Some(hex_digest(hasher))
+}
+
+// SHA-256 of every AIFF/WAV chunk apart from the ID3 chunk, ids included
+fn chunk_hash(data: &[u8], container: Container) -> Option<String> {
+    let chunks = chunks_without_tag(data, container)?;
+    let mut hasher = Sha256::new();
+    for (id, body) in chunks {
+        hasher.update(id);
+        hasher.update(body);
+    }
+
+    Some(hex_digest(hasher))
+}
+
+fn hex_digest(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn hash_for_path(path: &Path, data: &[u8]) -> Option<String> {
+    match Container::from_path(path) {
+        Some(container @ (Container::Aiff | Container::Wav)) => chunk_hash(data, container),
+        Some(Container::Flac) => {
+            let offset = flac_audio_offset(data)?;
+            let mut hasher = Sha256::new();
+            hasher.update(&data[offset..]);
+            Some(hex_digest(hasher))
+        },
+        Some(Container::Ogg) => {
+            let mut hasher = Sha256::new();
+            hasher.update(ogg_audio(data)?);
+            Some(hex_digest(hasher))
+        },
+        Some(Container::Mp4) => {
+            let mut hasher = Sha256::new();
+            hasher.update(mp4_audio(data)?);
+            Some(hex_digest(hasher))
+        },
+        _ => audio_hash(data),
+    }
+}
+
+pub fn audio_hash_file(path: &Path) -> Result<String, io::Error> {
+    let data = fs::read(path)?;
+
+    hash_for_path(path, &data)
+        .ok_or(io::Error::new(io::ErrorKind::InvalidData, "No audio found"))
+}
+
+struct CacheEntry {
+    size: u64,
+    modified: u128,
+    hash: String,
+}
+
+// Audio hashes keyed by path, stored in the cache directory. An entry is only
+// reused while the file's size and modification time are unchanged.
+pub struct HashCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+    dirty: bool,
+}
+
+impl HashCache {
+    pub fn load() -> Self {
+        let mut entries = HashMap::new();
+        let contents = cache_file(CACHE_FILE)
+            .and_then(fs::read_to_string)
+            .unwrap_or_default();
+
+        for line in contents.lines() {
+            let fields = line.splitn(4, '\t').collect::<Vec<&str>>();
+            if let [hash, size, modified, path] = fields[..] {
+                if let (Ok(size), Ok(modified)) = (size.parse(), modified.parse()) {
+                    entries.insert(PathBuf::from(path), CacheEntry {
+                        size,
+                        modified,
+                        hash: hash.to_string(),
+                    });
+                }
+            }
+        }
+
+        Self { entries, dirty: false }
+    }
+
+    pub fn save(&mut self) -> Result<(), io::Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let mut paths = self.entries.keys().collect::<Vec<&PathBuf>>();
+        paths.sort();
+
+        let mut contents = String::new();
+        for path in paths {
+            let entry = &self.entries[path];
+            contents.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                entry.hash, entry.size, entry.modified, path.display()
+            ));
+        }
+
+        fs::write(cache_file(CACHE_FILE)?, contents)?;
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    // The audio hash of the file, computed only if the cached one is stale
+    pub fn hash(&mut self, path: &Path) -> Result<String, io::Error> {
+        let (size, modified) = file_stamp(path)?;
+        if let Some(entry) = self.entries.get(path) {
+            if entry.size == size && entry.modified == modified {
+                return Ok(entry.hash.clone());
+            }
+        }
+
+        let hash = audio_hash_file(path)?;
+        self.insert(path, hash.clone())?;
+
+        Ok(hash)
+    }
+
+    // The last hash recorded for the path, even if the file changed since
+    pub fn stored(&self, path: &Path) -> Option<&str> {
+        self.entries.get(path).map(|entry| entry.hash.as_str())
+    }
+
+    // Renaming a file keeps its size and modification time, so the entry
+    // stays valid under the new path
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        if let Some(entry) = self.entries.remove(from) {
+            self.entries.insert(to.to_path_buf(), entry);
+            self.dirty = true;
+        }
+    }
+
+    pub fn insert(&mut self, path: &Path, hash: String) -> Result<(), io::Error> {
+        let (size, modified) = file_stamp(path)?;
+        self.entries.insert(path.to_path_buf(), CacheEntry { size, modified, hash });
+        self.dirty = true;
+
+        Ok(())
+    }
+}
+
+fn file_stamp(path: &Path) -> Result<(u64, u128), io::Error> {
+    let meta = fs::metadata(path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or(0);
+
+    Ok((meta.len(), modified))
+}
+
+// The error write_verified returns when the audio changed and the original
+// file had to be restored
+#[derive(Debug)]
+pub struct AudioMismatch;
+
+impl fmt::Display for AudioMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Audio changed while writing, original file restored")
+    }
+}
+
+impl Error for AudioMismatch {}
+
+pub fn is_audio_mismatch(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<AudioMismatch>())
+}
+
+// Writes the tag, then checks that the audio frames hash the same as before.
+// On a mismatch the original file is restored byte for byte.
+pub fn write_verified(
+    tag: &Tag,
+    path: &Path,
+    profile: Option<&WriteProfile>,
+    cache: &mut HashCache
+) -> Result<(), io::Error> {
+    let original = fs::read(path)?;
+    let before = hash_for_path(path, &original)
+        .ok_or(io::Error::new(io::ErrorKind::InvalidData, "No audio to verify"))?;
+
+    if let Err(err) = write_tag_as(tag, path, profile) {
+        let _ = fs::write(path, &original);
+        return Err(err);
+    }
+
+    let after = audio_hash_file(path).unwrap_or_default();
+    if after != before {
+        fs::write(path, &original)?;
+        return Err(io::Error::new(io::ErrorKind::InvalidData, AudioMismatch));
+    }
+
+    cache.insert(path, after)
+}
+
+// Checks each file's audio against its stored hash. Files without a stored
+// hash have their current one recorded as the baseline for later checks.
+pub fn verify_integrity(paths: &[PathBuf], cache: &mut HashCache) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for path in paths {
+        let hash = match audio_hash_file(path) {
+            Ok(hash) => hash,
+            Err(err) => {
+                findings.push(Finding::new(path, format!("Could not hash audio: {}", err)));
+                continue;
+            }
+        };
+
+        match cache.stored(path) {
+            Some(stored) if stored == hash => {},
+            Some(_) => findings.push(Finding::new(
+                path,
+                "Audio differs from the stored hash".to_string()
+            )),
+            None => {
+                if cache.insert(path, hash).is_ok() {
+                    findings.push(Finding::new(
+                        path,
+                        "No stored hash, recorded the current audio".to_string()
+                    ));
+                }
+            },
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+    use crate::testutil::*;
+
+    fn wav(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut body = b"WAVE".to_vec();
+        for (id, data) in chunks {
+            body.extend(*id);
+            body.extend((data.len() as u32).to_le_bytes());
+            body.extend(*data);
+            if data.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut wav = b"RIFF".to_vec();
+        wav.extend((body.len() as u32).to_le_bytes());
+        wav.extend(body);
+
+        wav
+    }
+
+    #[test]
+    fn retagging_keeps_the_audio_hash() {
+        let audio = mpeg_frames(4);
+        let hash = audio_hash(&audio).unwrap();
+
+        let mut tagged = b"ID3\x04\0\0\0\0\0\x03abc".to_vec();
+        tagged.extend(&audio);
+        tagged.extend(b"TAG");
+        tagged.resize(tagged.len() + 125, 0);
+        assert_eq!(audio_hash(&tagged).unwrap(), hash);
+
+        let mut changed = audio.clone();
+        changed[MPEG_FRAME_LEN + 10] ^= 1;
+        assert_ne!(audio_hash(&changed).unwrap(), hash);
+        assert!(audio_hash(b"not audio").is_none());
+    }
+
+    #[test]
+    fn wav_hash_ignores_the_id3_chunk() {
+        let plain = wav(&[(b"fmt ", &[1; 16]), (b"data", &[2; 7])]);
+        let tagged = wav(&[(b"fmt ", &[1; 16]), (b"id3 ", b"ID3 tag"), (b"data", &[2; 7])]);
+        let changed = wav(&[(b"fmt ", &[1; 16]), (b"data", &[3; 7])]);
+
+        let hash = chunk_hash(&plain, Container::Wav).unwrap();
+        assert_eq!(chunk_hash(&tagged, Container::Wav).unwrap(), hash);
+        assert_ne!(chunk_hash(&changed, Container::Wav).unwrap(), hash);
+        assert!(chunk_hash(&plain, Container::Aiff).is_none());
+    }
+
+    #[test]
+    fn verified_writes_record_the_hash() {
+        let audio = mpeg_frames(4);
+        let path = scratch_file("track.mp3", &audio);
+        let mut cache = HashCache { entries: HashMap::new(), dirty: false };
+        let mut tag = Tag::new();
+        tag.set_title("Verified");
+
+        write_verified(&tag, &path, None, &mut cache).unwrap();
+        let data = fs::read(&path).unwrap();
+        assert!(data.ends_with(&audio));
+        assert_eq!(cache.stored(&path), audio_hash(&audio).as_deref());
+    }
+
+    #[test]
+    fn only_audio_mismatches_are_marked() {
+        let mismatch = io::Error::new(io::ErrorKind::InvalidData, AudioMismatch);
+        assert!(is_audio_mismatch(&mismatch));
+
+        let path = scratch_file("empty.mp3", b"no frames here");
+        let mut cache = HashCache { entries: HashMap::new(), dirty: false };
+        let err = write_verified(&Tag::new(), &path, None, &mut cache).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!is_audio_mismatch(&err));
+    }
+}
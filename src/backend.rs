@@ -0,0 +1,552 @@
+use id3::{
+    frame::{
+        Comment,
+        ExtendedText,
+        Picture,
+        PictureType,
+    },
+    Content,
+    Frame,
+    Tag,
+    TagLike,
+    Version,
+};
+use std::{
+    io,
+    path::Path,
+};
+
+use crate::container::*;
+
+// Logical fields shared by every tag format. Each backend maps them onto its
+// own frames, comments or atoms.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Composer,
+    Genre,
+    // The year or date a release is known by (TYER), and the more precise
+    // recording time of ID3v2.4 tags (TDRC)
+    Date,
+    RecordingDate,
+    Track,
+    TrackTotal,
+    Disc,
+    DiscTotal,
+    Comment,
+    Bpm,
+    Isrc,
+    Encoder,
+    EncodedBy,
+    Publisher,
+    Copyright,
+    Conductor,
+    Remixer,
+    Lyricist,
+    Grouping,
+    Subtitle,
+    Compilation,
+    Language,
+    Mood,
+    OriginalDate,
+    TitleSort,
+    ArtistSort,
+    AlbumSort,
+    AlbumArtistSort,
+    Length,
+    // A user-defined field: TXXX, an unmapped Vorbis comment or an iTunes
+    // freeform atom
+    Custom(String),
+}
+
+// The same numbering as ID3 APIC and FLAC picture types
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cover {
+    pub mime_type: String,
+    pub picture_type: u8,
+    pub description: String,
+    pub data: Vec<u8>,
+}
+
+// Where a model was read from, so that writing it back in the same format
+// can keep format specific details
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TagFormat {
+    Id3(Version),
+    Vorbis,
+    Mp4,
+}
+
+// Frames, comments or atoms with no logical field. They are written back
+// only to files of the format they came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NativeFrame {
+    Id3(Frame),
+    Mp4(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagModel {
+    pub format: TagFormat,
+    pub fields: Vec<(Field, Vec<String>)>,
+    pub pictures: Vec<Cover>,
+    pub native: Vec<NativeFrame>,
+    // The language of an ID3 comment, so that it survives the round trip
+    pub comment_lang: Option<String>,
+}
+
+impl TagModel {
+    pub fn new(format: TagFormat) -> Self {
+        Self {
+            format,
+            fields: Vec::new(),
+            pictures: Vec::new(),
+            native: Vec::new(),
+            comment_lang: None,
+        }
+    }
+
+    pub fn values(&self, field: &Field) -> Option<&[String]> {
+        self.fields
+            .iter()
+            .find(|(other, _)| other == field)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    pub fn first(&self, field: &Field) -> Option<&str> {
+        self.values(field)?.first().map(|value| value.as_str())
+    }
+
+    // Adds a value after any the field already has
+    pub fn add(&mut self, field: Field, value: String) {
+        match self.fields.iter_mut().find(|(other, _)| *other == field) {
+            Some((_, values)) => values.push(value),
+            None => self.fields.push((field, vec![value])),
+        }
+    }
+}
+
+// Reads and writes one tag format through the neutral model
+pub trait TagBackend {
+    // Ok(None) when the file has no tag yet
+    fn read(&self, path: &Path) -> io::Result<Option<TagModel>>;
+    fn write(&self, model: &TagModel, path: &Path) -> io::Result<()>;
+}
+
+pub fn backend_for(path: &Path) -> Option<&'static dyn TagBackend> {
+    let backend: &'static dyn TagBackend = match Container::from_path(path)? {
+        Container::Mpeg => &Id3Backend { container: Container::Mpeg },
+        Container::Aiff => &Id3Backend { container: Container::Aiff },
+        Container::Wav => &Id3Backend { container: Container::Wav },
+        Container::Flac => &crate::flac::FlacBackend,
+        Container::Ogg => &crate::ogg::OggBackend,
+        Container::Mp4 => &crate::mp4::Mp4Backend,
+    };
+
+    Some(backend)
+}
+
+// The rest of rime edits tags as ID3 frames, whatever the file's format: the
+// neutral model is only the layer between those frames and each format on
+// disk. ID3 tags are read and written as they are, frame encodings included;
+// only other formats go through the model. Their native items never reach
+// the ID3 tag and are kept from the file being written instead.
+pub fn read_tag(path: &Path) -> io::Result<Tag> {
+    let container = Container::from_path(path)
+        .ok_or(io::Error::new(io::ErrorKind::Unsupported, "Unsupported file type"))?;
+    let tag = if container.has_id3() {
+        read_id3(path, container)?
+    } else {
+        backend_for(path)
+            .ok_or(io::Error::new(io::ErrorKind::Unsupported, "Unsupported file type"))?
+            .read(path)?
+            .map(|model| model.to_id3())
+    };
+
+    tag.ok_or(io::Error::new(io::ErrorKind::NotFound, "No tag"))
+}
+
+pub fn write_tag(tag: &Tag, path: &Path) -> io::Result<()> {
+    let container = Container::from_path(path)
+        .ok_or(io::Error::new(io::ErrorKind::Unsupported, "Unsupported file type"))?;
+    if container.has_id3() {
+        return write_id3(tag, path, container);
+    }
+
+    backend_for(path)
+        .ok_or(io::Error::new(io::ErrorKind::Unsupported, "Unsupported file type"))?
+        .write(&TagModel::from_id3(tag), path)
+}
+
+// Whether a frame key (see frame_key_values) has a place in the file's own
+// tag format. ID3 files store every frame; other formats only those with a
+// logical field.
+pub fn can_store_key(path: &Path, key: &str) -> bool {
+    match Container::from_path(path) {
+        Some(container) if !container.has_id3() => {
+            key == "COMM"
+                || key.starts_with("TXXX:")
+                || ["TRCK", "TPOS"].contains(&key)
+                || ID3_FRAMES.iter().any(|(_, id)| *id == key)
+        },
+        _ => true,
+    }
+}
+
+pub fn id3_error(err: id3::Error) -> io::Error {
+    match err.kind {
+        id3::ErrorKind::Io(err) => err,
+        _ => io::Error::new(io::ErrorKind::InvalidData, err.description),
+    }
+}
+
+// Text frames with a logical field. Track, disc and comment frames are
+// handled separately.
+const ID3_FRAMES: [(Field, &str); 28] = [
+    (Field::Title, "TIT2"),
+    (Field::Artist, "TPE1"),
+    (Field::Album, "TALB"),
+    (Field::AlbumArtist, "TPE2"),
+    (Field::Composer, "TCOM"),
+    (Field::Genre, "TCON"),
+    (Field::Date, "TYER"),
+    (Field::RecordingDate, "TDRC"),
+    (Field::Bpm, "TBPM"),
+    (Field::Isrc, "TSRC"),
+    (Field::Encoder, "TSSE"),
+    (Field::EncodedBy, "TENC"),
+    (Field::Publisher, "TPUB"),
+    (Field::Copyright, "TCOP"),
+    (Field::Conductor, "TPE3"),
+    (Field::Remixer, "TPE4"),
+    (Field::Lyricist, "TEXT"),
+    (Field::Grouping, "TIT1"),
+    (Field::Subtitle, "TIT3"),
+    (Field::Compilation, "TCMP"),
+    (Field::Language, "TLAN"),
+    (Field::Mood, "TMOO"),
+    (Field::OriginalDate, "TDOR"),
+    (Field::TitleSort, "TSOT"),
+    (Field::ArtistSort, "TSOP"),
+    (Field::AlbumSort, "TSOA"),
+    (Field::AlbumArtistSort, "TSO2"),
+    (Field::Length, "TLEN"),
+];
+
+impl TagModel {
+    // Frames with no logical field, and any comment frame after the first, are
+    // kept as native frames so that ID3 tags round-trip unchanged
+    pub fn from_id3(tag: &Tag) -> Self {
+        let mut model = TagModel::new(TagFormat::Id3(tag.version()));
+        let mut has_comment = false;
+
+        for frame in tag.frames() {
+            let id = frame.id();
+            match frame.content() {
+                Content::Text(text) if id == "TRCK" || id == "TPOS" => {
+                    let (number, total) = if id == "TRCK" {
+                        (Field::Track, Field::TrackTotal)
+                    } else {
+                        (Field::Disc, Field::DiscTotal)
+                    };
+                    let (value, count) = text.split_once('/').unwrap_or((text, ""));
+                    if !value.is_empty() {
+                        model.add(number, value.to_string());
+                    }
+                    if !count.is_empty() {
+                        model.add(total, count.to_string());
+                    }
+                },
+                Content::Text(text) => match ID3_FRAMES.iter().find(|(_, frame)| *frame == id) {
+                    Some((field, _)) => add_values(&mut model, field.clone(), text),
+                    None => model.native.push(NativeFrame::Id3(frame.clone())),
+                },
+                Content::Comment(comment) if !has_comment && comment.description.is_empty() => {
+                    add_values(&mut model, Field::Comment, &comment.text);
+                    model.comment_lang = Some(comment.lang.clone());
+                    has_comment = true;
+                },
+                Content::ExtendedText(extended) => {
+                    add_values(&mut model, Field::Custom(extended.description.clone()), &extended.value);
+                },
+                Content::Picture(picture) => model.pictures.push(Cover {
+                    mime_type: picture.mime_type.clone(),
+                    picture_type: u8::from(picture.picture_type),
+                    description: picture.description.clone(),
+                    data: picture.data.clone(),
+                }),
+                _ => model.native.push(NativeFrame::Id3(frame.clone())),
+            }
+        }
+
+        model
+    }
+
+    // Models read from other formats become ID3v2.4 tags, where a date
+    // belongs in TDRC rather than the v2.3 TYER frame
+    pub fn to_id3(&self) -> Tag {
+        let version = match self.format {
+            TagFormat::Id3(version) => version,
+            _ => Version::Id3v24,
+        };
+        let mut tag = Tag::with_version(version);
+
+        for (number, total, id) in [
+            (Field::Track, Field::TrackTotal, "TRCK"),
+            (Field::Disc, Field::DiscTotal, "TPOS"),
+        ] {
+            match (self.first(&number), self.first(&total)) {
+                (Some(number), Some(total)) => tag.set_text(id, format!("{}/{}", number, total)),
+                (Some(number), None) => tag.set_text(id, number),
+                (None, Some(total)) => tag.set_text(id, format!("/{}", total)),
+                (None, None) => {},
+            }
+        }
+
+        for (field, values) in self.fields.iter() {
+            let joined = values.join("\0");
+            match field {
+                Field::Track | Field::TrackTotal | Field::Disc | Field::DiscTotal => {},
+                Field::Comment => {
+                    tag.add_frame(Comment {
+                        lang: self.comment_lang.clone().unwrap_or_else(|| "eng".to_string()),
+                        description: String::new(),
+                        text: joined,
+                    });
+                },
+                Field::Date if !matches!(self.format, TagFormat::Id3(_)) => {
+                    if self.values(&Field::RecordingDate).is_none() {
+                        tag.set_text("TDRC", joined);
+                    }
+                },
+                Field::Custom(description) => {
+                    tag.add_frame(ExtendedText {
+                        description: description.clone(),
+                        value: joined,
+                    });
+                },
+                field => {
+                    if let Some((_, id)) = ID3_FRAMES.iter().find(|(other, _)| other == field) {
+                        tag.set_text(*id, joined);
+                    }
+                },
+            }
+        }
+
+        // add_frame would keep only one picture of each type, and one of any
+        // unknown frame such as RVA2, so these are appended as they are
+        tag.extend(self.pictures.iter().map(|cover| {
+            Frame::with_content("APIC", Content::Picture(Picture {
+                mime_type: cover.mime_type.clone(),
+                picture_type: picture_type(cover.picture_type),
+                description: cover.description.clone(),
+                data: cover.data.clone(),
+            }))
+        }));
+
+        tag.extend(self.native.iter().filter_map(|native| match native {
+            NativeFrame::Id3(frame) => Some(frame.clone()),
+            _ => None,
+        }));
+
+        tag
+    }
+}
+
+// ID3 keeps multiple values in one frame, separated by NUL
+fn add_values(model: &mut TagModel, field: Field, text: &str) {
+    for value in text.split('\0') {
+        model.add(field.clone(), value.to_string());
+    }
+}
+
+const PICTURE_TYPES: [PictureType; 21] = [
+    PictureType::Other,
+    PictureType::Icon,
+    PictureType::OtherIcon,
+    PictureType::CoverFront,
+    PictureType::CoverBack,
+    PictureType::Leaflet,
+    PictureType::Media,
+    PictureType::LeadArtist,
+    PictureType::Artist,
+    PictureType::Conductor,
+    PictureType::Band,
+    PictureType::Composer,
+    PictureType::Lyricist,
+    PictureType::RecordingLocation,
+    PictureType::DuringRecording,
+    PictureType::DuringPerformance,
+    PictureType::ScreenCapture,
+    PictureType::BrightFish,
+    PictureType::Illustration,
+    PictureType::BandLogo,
+    PictureType::PublisherLogo,
+];
+
+fn picture_type(value: u8) -> PictureType {
+    PICTURE_TYPES
+        .get(value as usize)
+        .copied()
+        .unwrap_or(PictureType::Undefined(value))
+}
+
+// ID3v2 tags at the start of MPEG files, or in an "ID3 " chunk of AIFF and
+// WAV files
+pub struct Id3Backend {
+    container: Container,
+}
+
+impl TagBackend for Id3Backend {
+    fn read(&self, path: &Path) -> io::Result<Option<TagModel>> {
+        Ok(read_id3(path, self.container)?.map(|tag| TagModel::from_id3(&tag)))
+    }
+
+    fn write(&self, model: &TagModel, path: &Path) -> io::Result<()> {
+        write_id3(&model.to_id3(), path, self.container)
+    }
+}
+
+fn read_id3(path: &Path, container: Container) -> io::Result<Option<Tag>> {
+    let result = match container {
+        Container::Aiff => Tag::read_from_aiff_path(path),
+        Container::Wav => Tag::read_from_wav_path(path),
+        _ => Tag::read_from_path(path),
+    };
+
+    match result {
+        Ok(tag) => Ok(Some(tag)),
+        Err(err) if matches!(err.kind, id3::ErrorKind::NoTag) => Ok(None),
+        Err(err) => Err(id3_error(err)),
+    }
+}
+
+fn write_id3(tag: &Tag, path: &Path, container: Container) -> io::Result<()> {
+    let result = match container {
+        Container::Aiff => tag.write_to_aiff_path(path, tag.version()),
+        Container::Wav => tag.write_to_wav_path(path, tag.version()),
+        _ => tag.write_to_path(path, tag.version()),
+    };
+
+    result.map_err(id3_error)
+}
+
+// Writes an ID3 tag to an MPEG, AIFF or WAV file as it is, frame encodings
+// included, rather than rebuilding it from the neutral model
+pub fn write_id3_tag(tag: &Tag, path: &Path) -> io::Result<()> {
+    match Container::from_path(path) {
+        Some(container) if container.has_id3() => write_id3(tag, path, container),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Not an ID3 file")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::{
+        frame::Unknown,
+        Encoding,
+    };
+    use std::fs;
+
+    use super::*;
+    use crate::testutil::*;
+
+    fn cover(picture_type: u8, data: &[u8]) -> Cover {
+        Cover {
+            mime_type: "image/jpeg".to_string(),
+            picture_type,
+            description: String::new(),
+            data: data.to_vec(),
+        }
+    }
+
+    // Frame order differs after a round trip, so compare frames as a set
+    fn assert_same_frames(a: &Tag, b: &Tag) {
+        assert_eq!(a.frames().count(), b.frames().count());
+        for frame in a.frames() {
+            assert!(b.frames().any(|other| other == frame), "{:?} missing", frame);
+        }
+    }
+
+    #[test]
+    fn every_picture_survives_the_round_trip() {
+        let mut model = TagModel::new(TagFormat::Mp4);
+        model.pictures = vec![cover(3, b"one"), cover(3, b"two"), cover(3, b"three"), cover(4, b"back")];
+
+        let tag = model.to_id3();
+        assert_eq!(tag.pictures().count(), 4);
+        assert_eq!(TagModel::from_id3(&tag).pictures, model.pictures);
+    }
+
+    #[test]
+    fn id3_tags_survive_the_model() {
+        let mut tag = Tag::with_version(Version::Id3v24);
+        tag.set_text("TIT2", "Title");
+        tag.set_text("TPE1", "One\0Two");
+        tag.set_text("TRCK", "3/12");
+        tag.add_frame(Comment {
+            lang: "deu".to_string(),
+            description: String::new(),
+            text: "Hallo".to_string(),
+        });
+        tag.add_frame(Comment {
+            lang: "eng".to_string(),
+            description: "iTunNORM".to_string(),
+            text: " 000001F4".to_string(),
+        });
+        tag.add_frame(ExtendedText {
+            description: "REPLAYGAIN_TRACK_GAIN".to_string(),
+            value: "-4.27 dB".to_string(),
+        });
+        tag.extend([
+            Frame::with_content("RVA2", Content::Unknown(Unknown {
+                data: b"track\0\x01\x00\x10\x00".to_vec(),
+                version: Version::Id3v24,
+            })),
+            Frame::with_content("RVA2", Content::Unknown(Unknown {
+                data: b"album\0\x01\x00\x20\x00".to_vec(),
+                version: Version::Id3v24,
+            })),
+        ]);
+
+        assert_same_frames(&tag, &TagModel::from_id3(&tag).to_id3());
+    }
+
+    #[test]
+    fn id3_files_keep_frame_encodings() {
+        let path = scratch_file("track.mp3", &[0; 64]);
+        let mut tag = Tag::with_version(Version::Id3v24);
+        tag.add_frame(Frame::text("TIT2", "Título").set_encoding(Some(Encoding::UTF16)));
+        write_tag(&tag, &path).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let frame = data.windows(4).position(|window| window == b"TIT2").unwrap();
+        assert_eq!(data[frame + 10], 1);
+        assert_eq!(read_tag(&path).unwrap().get("TIT2").unwrap().content().text(), Some("Título"));
+    }
+
+    #[test]
+    fn other_formats_keep_dates_in_tdrc() {
+        let mut model = TagModel::new(TagFormat::Vorbis);
+        model.add(Field::Date, "2001-05-04".to_string());
+
+        let tag = model.to_id3();
+        assert_eq!(tag.get("TYER"), None);
+        assert_eq!(tag.get("TDRC").and_then(|frame| frame.content().text()), Some("2001-05-04"));
+        assert_eq!(TagModel::from_id3(&tag).first(&Field::RecordingDate), Some("2001-05-04"));
+    }
+
+    #[test]
+    fn frames_without_a_field_only_fit_id3_files() {
+        for key in ["TIT2", "TXXX:SOURCE", "COMM", "TRCK", "TSRC"] {
+            assert!(can_store_key(Path::new("a.m4a"), key));
+        }
+        for key in ["TKEY", "RVA2:track"] {
+            assert!(!can_store_key(Path::new("a.flac"), key));
+            assert!(can_store_key(Path::new("a.mp3"), key));
+        }
+    }
+}
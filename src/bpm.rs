@@ -0,0 +1,211 @@
+use id3::Tag;
+use std::{
+    collections::HashMap,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use crate::decode::*;
+use crate::list::*;
+use crate::preview::*;
+use crate::tags::*;
+
+// Onset envelope resolution in frames per second
+const ENVELOPE_RATE: u32 = 200;
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+
+#[derive(Clone, Copy)]
+pub struct TempoEstimate {
+    pub bpm: f64,
+    pub confidence: f64,
+}
+
+// Builds an onset envelope from rises in log energy, then picks the beat
+// period with the strongest autocorrelation, weighted towards common tempos
+pub fn estimate_tempo(path: &Path, cancel: &AtomicBool) -> Result<TempoEstimate, String> {
+    let mut envelope = Vec::new();
+    let mut hop = 0;
+    let mut pos = 0;
+    let mut energy = 0.0;
+    let mut last_energy = 0.0;
+
+    decode_file(path, |samples, channels, rate| {
+        hop = (rate / ENVELOPE_RATE).max(1) as usize;
+        for frame in samples.chunks_exact(channels) {
+            let mono = frame.iter().sum::<f32>() as f64 / channels as f64;
+            energy += mono * mono;
+            pos += 1;
+            if pos == hop {
+                let log_energy = (energy / hop as f64 + 1e-10).ln();
+                envelope.push((log_energy - last_energy).max(0.0));
+                last_energy = log_energy;
+                energy = 0.0;
+                pos = 0;
+            }
+        }
+        !cancel.load(Ordering::Relaxed)
+    })?;
+
+    let min_lag = (ENVELOPE_RATE as f64 * 60.0 / MAX_BPM).floor() as usize;
+    let max_lag = (ENVELOPE_RATE as f64 * 60.0 / MIN_BPM).ceil() as usize;
+    if envelope.len() < max_lag * 4 {
+        return Err("too short to estimate".to_string());
+    }
+
+    let mean = envelope.iter().sum::<f64>() / envelope.len() as f64;
+    let envelope = envelope.iter().map(|value| value - mean).collect::<Vec<f64>>();
+    let autocorrelation = |lag: usize| -> f64 {
+        envelope.iter().zip(envelope[lag..].iter()).map(|(a, b)| a * b).sum::<f64>()
+    };
+
+    let zero_lag = autocorrelation(0);
+    if zero_lag <= 0.0 {
+        return Err("no beats found".to_string());
+    }
+    let correlations = (min_lag - 1..=max_lag + 1)
+        .map(|lag| autocorrelation(lag) / zero_lag)
+        .collect::<Vec<f64>>();
+
+    let weight = |lag: f64| -> f64 {
+        let bpm = ENVELOPE_RATE as f64 * 60.0 / lag;
+        (-0.5 * (bpm / 120.0).log2().powi(2)).exp()
+    };
+    let (best, _) = (1..correlations.len() - 1)
+        .map(|i| (i, correlations[i] * weight((min_lag - 1 + i) as f64)))
+        .fold((1, f64::MIN), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+
+    // Parabolic interpolation between neighbouring lags
+    let (before, peak, after) = (correlations[best - 1], correlations[best], correlations[best + 1]);
+    let curvature = before - 2.0 * peak + after;
+    let offset = if curvature < 0.0 { 0.5 * (before - after) / curvature } else { 0.0 };
+    let lag = (min_lag - 1 + best) as f64 + offset;
+
+    Ok(TempoEstimate {
+        bpm: ENVELOPE_RATE as f64 * 60.0 / lag,
+        confidence: peak.clamp(0.0, 1.0),
+    })
+}
+
+#[derive(Clone)]
+pub struct BpmRow {
+    pub path: PathBuf,
+    pub old: Option<String>,
+    pub estimate: Result<TempoEstimate, String>,
+    pub bpm: f64,
+    pub accepted: bool,
+}
+
+pub struct BpmReview {
+    pub rows: GenericList<BpmRow>,
+    // TBPM is defined as a whole number, tenths are only written on request
+    pub tenths: bool,
+}
+
+impl BpmReview {
+    pub fn new(
+        results: Vec<(PathBuf, Result<TempoEstimate, String>)>,
+        tags: &HashMap<PathBuf, Tag>
+    ) -> Self {
+        let mut rows = results
+            .into_iter()
+            .map(|(path, estimate)| {
+                let old = tags.get(&path).and_then(|tag| text_value(tag, "TBPM"));
+                let bpm = estimate.as_ref().map(|estimate| estimate.bpm).unwrap_or(0.0);
+                let accepted = estimate.is_ok();
+                BpmRow { path, old, estimate, bpm, accepted }
+            })
+            .collect::<Vec<BpmRow>>();
+        rows.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Self { rows: GenericList::from(rows), tenths: false }
+    }
+
+    fn update_selected(&mut self, update: impl FnOnce(&mut BpmRow)) {
+        if let Some(index) = self.rows.get_index() {
+            let row = &mut self.rows.items[index];
+            if row.estimate.is_ok() {
+                update(row);
+            }
+        }
+    }
+
+    pub fn toggle_accept(&mut self) {
+        self.update_selected(|row| row.accepted = !row.accepted);
+    }
+
+    pub fn toggle_tenths(&mut self) {
+        self.tenths = !self.tenths;
+    }
+
+    pub fn halve(&mut self) {
+        self.update_selected(|row| row.bpm /= 2.0);
+    }
+
+    pub fn double(&mut self) {
+        self.update_selected(|row| row.bpm *= 2.0);
+    }
+
+    // The TBPM value the row would be written as
+    pub fn new_value(&self, row: &BpmRow) -> String {
+        format_bpm(row.bpm, self.tenths)
+    }
+
+    pub fn diffs(&self) -> Vec<FileDiff> {
+        self.rows
+            .items
+            .iter()
+            .filter(|row| row.accepted)
+            .map(|row| {
+                let new = self.new_value(row);
+                let changes = if row.old.as_deref() == Some(new.as_str()) {
+                    Vec::new()
+                } else {
+                    vec![FrameChange { id: "TBPM".to_string(), old: row.old.clone(), new }]
+                };
+
+                FileDiff { path: row.path.clone(), changes, warning: None }
+            })
+            .collect()
+    }
+}
+
+pub fn format_bpm(bpm: f64, tenths: bool) -> String {
+    let tenth = (bpm * 10.0).round() / 10.0;
+    if tenths && tenth.fract() != 0.0 {
+        format!("{:.1}", tenth)
+    } else {
+        format!("{:.0}", bpm.round())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(bpm: f64) -> BpmReview {
+        let estimate = TempoEstimate { bpm, confidence: 0.9 };
+        BpmReview::new(vec![(PathBuf::from("a.mp3"), Ok(estimate))], &HashMap::new())
+    }
+
+    #[test]
+    fn tbpm_is_whole_unless_tenths_are_asked_for() {
+        let mut review = review(128.26);
+        assert_eq!(review.diffs()[0].changes[0].new, "128");
+
+        review.toggle_tenths();
+        assert_eq!(review.diffs()[0].changes[0].new, "128.3");
+
+        review.rows.select_first();
+        review.halve();
+        assert_eq!(review.diffs()[0].changes[0].new, "64.1");
+        review.toggle_tenths();
+        assert_eq!(review.diffs()[0].changes[0].new, "64");
+    }
+}
@@ -0,0 +1,193 @@
+use id3::{
+    Content,
+    Frame,
+    Tag,
+    TagLike,
+};
+
+use crate::list::*;
+
+// Checklist of frames offered for copying into the clipboard
+pub struct CopyDialog {
+    pub source: String,
+    pub frames: GenericList<Frame>,
+    pub checked: Vec<bool>,
+}
+
+impl CopyDialog {
+    pub fn new(source: String, frames: Vec<Frame>) -> Self {
+        let checked = vec![true; frames.len()];
+
+        Self {
+            source,
+            frames: GenericList::from(frames),
+            checked,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        if let Some(i) = self.frames.get_index() {
+            self.checked[i] = !self.checked[i];
+        }
+    }
+
+    pub fn toggle_all(&mut self) {
+        let all_checked = self.checked.iter().all(|checked| *checked);
+        self.checked.iter_mut().for_each(|checked| *checked = !all_checked);
+    }
+
+    pub fn checked_frames(&self) -> Vec<Frame> {
+        self.frames
+            .items
+            .iter()
+            .zip(self.checked.iter())
+            .filter(|(_, checked)| **checked)
+            .map(|(frame, _)| frame.clone())
+            .collect()
+    }
+}
+
+// Frames that are identical in every one of the given tags
+pub fn common_frames(tags: &[Tag]) -> Vec<Frame> {
+    let (first, rest) = match tags.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+
+    first.frames()
+        .filter(|frame| {
+            rest.iter().all(|tag| tag.frames().any(|other| other == *frame))
+        })
+        .cloned()
+        .collect()
+}
+
+// Copies frames onto a tag, replacing whatever frames they conflict with
+pub fn paste_frames(tag: &mut Tag, frames: &[Frame]) {
+    for frame in frames {
+        if let Content::Text(_) = frame.content() {
+            tag.remove(frame.id());
+        }
+        tag.add_frame(frame.clone().set_encoding(None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::{
+        frame::{
+            Comment,
+            ExtendedText,
+            Picture,
+            PictureType,
+        },
+        Encoding,
+    };
+
+    use super::*;
+
+    fn comment(description: &str, text: &str) -> Frame {
+        Frame::with_content("COMM", Content::Comment(Comment {
+            lang: "eng".to_string(),
+            description: description.to_string(),
+            text: text.to_string(),
+        }))
+    }
+
+    fn picture(picture_type: PictureType, data: &[u8]) -> Frame {
+        Frame::with_content("APIC", Content::Picture(Picture {
+            mime_type: "image/jpeg".to_string(),
+            picture_type,
+            description: String::new(),
+            data: data.to_vec(),
+        }))
+    }
+
+    fn tag(frames: Vec<Frame>) -> Tag {
+        let mut tag = Tag::new();
+        for frame in frames {
+            tag.add_frame(frame);
+        }
+        tag
+    }
+
+    #[test]
+    fn pasted_text_frames_replace_existing_ones() {
+        let mut target = tag(vec![Frame::text("TPE1", "Old"), Frame::text("TALB", "Kept")]);
+        let pasted = Frame::text("TPE1", "New").set_encoding(Some(Encoding::UTF16));
+        paste_frames(&mut target, &[pasted]);
+
+        assert_eq!(target.artist(), Some("New"));
+        assert_eq!(target.album(), Some("Kept"));
+        assert_eq!(target.get("TPE1").unwrap().encoding(), None);
+    }
+
+    #[test]
+    fn pasted_comments_and_pictures_only_replace_their_own_kind() {
+        let mut target = tag(vec![
+            comment("", "Old comment"),
+            comment("Source", "Vinyl"),
+            picture(PictureType::CoverFront, b"old front"),
+            picture(PictureType::CoverBack, b"back"),
+        ]);
+        paste_frames(&mut target, &[
+            comment("", "New comment"),
+            picture(PictureType::CoverFront, b"new front"),
+        ]);
+
+        let comments = target.comments().map(|comment| comment.text.as_str()).collect::<Vec<&str>>();
+        assert_eq!(comments.len(), 2);
+        assert!(comments.contains(&"New comment") && comments.contains(&"Vinyl"));
+
+        let front = target.pictures().find(|picture| picture.picture_type == PictureType::CoverFront);
+        assert_eq!(front.unwrap().data, b"new front");
+        assert_eq!(target.pictures().count(), 2);
+    }
+
+    #[test]
+    fn user_text_frames_are_merged_by_description() {
+        let extended = |description: &str, value: &str| {
+            Frame::with_content("TXXX", Content::ExtendedText(ExtendedText {
+                description: description.to_string(),
+                value: value.to_string(),
+            }))
+        };
+        let mut target = tag(vec![extended("CATALOG", "A-1"), extended("SOURCE", "CD")]);
+        paste_frames(&mut target, &[extended("CATALOG", "B-2")]);
+
+        let mut values = target
+            .extended_texts()
+            .map(|extended| format!("{}={}", extended.description, extended.value))
+            .collect::<Vec<String>>();
+        values.sort();
+        assert_eq!(values, vec!["CATALOG=B-2", "SOURCE=CD"]);
+    }
+
+    #[test]
+    fn only_frames_shared_by_every_tag_are_common() {
+        let tags = [
+            tag(vec![Frame::text("TALB", "Low"), Frame::text("TIT2", "Speed of Life")]),
+            tag(vec![Frame::text("TALB", "Low"), Frame::text("TIT2", "Breaking Glass")]),
+        ];
+        let common = common_frames(&tags);
+
+        assert_eq!(common, vec![Frame::text("TALB", "Low")]);
+        assert!(common_frames(&[]).is_empty());
+    }
+
+    #[test]
+    fn the_copy_dialog_starts_with_everything_checked() {
+        let mut dialog = CopyDialog::new(
+            "a.mp3".to_string(),
+            vec![Frame::text("TALB", "Low"), Frame::text("TPE1", "Bowie")]
+        );
+        assert_eq!(dialog.checked_frames().len(), 2);
+
+        dialog.toggle_all();
+        assert!(dialog.checked_frames().is_empty());
+        dialog.toggle_all();
+        dialog.frames.select(Some(1));
+        dialog.toggle();
+        assert_eq!(dialog.checked_frames(), vec![Frame::text("TALB", "Low")]);
+    }
+}
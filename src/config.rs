@@ -0,0 +1,30 @@
+use std::{
+    fs,
+    io,
+    path::PathBuf,
+};
+
+// rime's directory under the user config directory, e.g. ~/.config/rime
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rime"))
+}
+
+// A subdirectory of the config directory, created if it doesn't exist yet
+pub fn config_subdir(name: &str) -> Result<PathBuf, io::Error> {
+    let dir = config_dir()
+        .ok_or(io::Error::new(io::ErrorKind::NotFound, "No config directory"))?
+        .join(name);
+    fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+// A file in rime's directory under the user cache directory
+pub fn cache_file(name: &str) -> Result<PathBuf, io::Error> {
+    let dir = dirs::cache_dir()
+        .ok_or(io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?
+        .join("rime");
+    fs::create_dir_all(&dir)?;
+
+    Ok(dir.join(name))
+}
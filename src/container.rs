@@ -0,0 +1,72 @@
+use std::path::Path;
+
+// The file formats rime can read and write tags in. AIFF and WAV files keep
+// their ID3 tag in an "ID3 " chunk rather than at the start of the file,
+// FLAC, Ogg Vorbis and Opus files use Vorbis comments, and MP4 files
+// iTunes-style ilst atoms. See backend.rs for how each is read and written.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Container {
+    Mpeg,
+    Aiff,
+    Wav,
+    Flac,
+    Ogg,
+    Mp4,
+}
+
+impl Container {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "mp3" => Some(Container::Mpeg),
+            "aif" | "aiff" | "aifc" => Some(Container::Aiff),
+            "wav" | "wave" => Some(Container::Wav),
+            "flac" => Some(Container::Flac),
+            "ogg" | "oga" | "opus" => Some(Container::Ogg),
+            "m4a" | "m4b" | "mp4" => Some(Container::Mp4),
+            _ => None,
+        }
+    }
+
+    // Whether the container's tag is ID3v2
+    pub fn has_id3(&self) -> bool {
+        matches!(self, Container::Mpeg | Container::Aiff | Container::Wav)
+    }
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    Container::from_path(path).is_some()
+}
+
+// Every chunk in an AIFF (big endian sizes) or WAV (little endian sizes)
+// file other than the ID3 chunk, as (id, body) pairs
+pub fn chunks_without_tag(data: &[u8], container: Container) -> Option<Vec<(&[u8], &[u8])>> {
+    let (root, forms): (&[u8], &[&[u8]]) = match container {
+        Container::Aiff => (b"FORM", &[b"AIFF", b"AIFC"]),
+        Container::Wav => (b"RIFF", &[b"WAVE"]),
+        _ => return None,
+    };
+    if data.len() < 12 || &data[0..4] != root || !forms.contains(&&data[8..12]) {
+        return None;
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let size_bytes = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+        let size = match container {
+            Container::Wav => u32::from_le_bytes(size_bytes),
+            _ => u32::from_be_bytes(size_bytes),
+        } as usize;
+        let start = pos + 8;
+        let end = (start + size).min(data.len());
+        if !id.eq_ignore_ascii_case(b"ID3 ") {
+            chunks.push((id, &data[start..end]));
+        }
+        // Chunks are padded to an even length
+        pos = start + size + size % 2;
+    }
+
+    Some(chunks)
+}
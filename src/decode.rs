@@ -0,0 +1,74 @@
+use std::{
+    fs::File,
+    path::Path,
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::DecoderOptions,
+    errors::Error,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+// Decodes the file's audio and passes each chunk of interleaved samples to
+// on_samples along with the channel count and sample rate. Decoding stops
+// early if on_samples returns false.
+pub fn decode_file<F>(path: &Path, mut on_samples: F) -> Result<(), String>
+where
+    F: FnMut(&[f32], usize, u32) -> bool,
+{
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|err| err.to_string())?;
+    let mut format = probed.format;
+    let track = format
+        .default_track()
+        .ok_or("No audio track".to_string())?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|err| err.to_string())?;
+
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(Error::ResetRequired) => break,
+            Err(err) => return Err(err.to_string()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Damaged frames are skipped, as players do
+            Err(Error::DecodeError(_)) => continue,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let spec = *decoded.spec();
+        let buffer = match buffer.as_mut() {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() * spec.channels.count() => buffer,
+            _ => buffer.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+
+        if !on_samples(buffer.samples(), spec.channels.count(), spec.rate) {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use id3::{
+    Tag,
+    TagLike,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+};
+
+use crate::input::*;
+use crate::list::*;
+use crate::merge::normalize_key;
+use crate::mpeg::*;
+use crate::tags::*;
+
+// Frames that make up a "complete" tag for the completeness score
+const COMPLETENESS_FRAMES: [&str; 6] = ["TIT2", "TPE1", "TALB", "TRCK", "TYER", "TCON"];
+
+pub fn duplicates_form() -> InputForm {
+    InputForm::new(
+        "Find Duplicate Tracks",
+        "Match: tags/audio | Album: yes/no | Tolerance in seconds, blank to ignore",
+        &[("Directory", "."), ("Match", "tags"), ("Album", "no"), ("Tolerance", "")]
+    )
+}
+
+pub struct DuplicateFile {
+    pub path: PathBuf,
+    pub bitrate: Option<u32>,
+    pub duration: Option<f64>,
+    pub size: u64,
+    pub completeness: usize,
+    pub keep: bool,
+}
+
+impl DuplicateFile {
+    fn read(path: PathBuf, tag: &Tag) -> Self {
+        let info = read_stream_info(&path).ok();
+        let size = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        let mut completeness = COMPLETENESS_FRAMES
+            .iter()
+            .filter(|id| match **id {
+                "TYER" => year_value(tag).is_some(),
+                id => text_value(tag, id).is_some(),
+            })
+            .count();
+        if tag.pictures().next().is_some() {
+            completeness += 1;
+        }
+
+        Self {
+            path,
+            bitrate: info.as_ref().map(|info| info.bitrate),
+            duration: info.as_ref().map(|info| info.duration),
+            size,
+            completeness,
+            keep: true,
+        }
+    }
+}
+
+pub fn max_completeness() -> usize {
+    COMPLETENESS_FRAMES.len() + 1
+}
+
+pub struct DuplicateGroup {
+    pub title: String,
+    pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Clone)]
+pub enum DuplicateLine {
+    Group(usize),
+    File(usize, usize),
+}
+
+pub struct DuplicateView {
+    pub root: PathBuf,
+    pub groups: Vec<DuplicateGroup>,
+    pub lines: GenericList<DuplicateLine>,
+    pub confirm: bool,
+}
+
+impl DuplicateView {
+    pub fn new(root: PathBuf, groups: Vec<DuplicateGroup>) -> Self {
+        let mut lines = Vec::new();
+        for (i, group) in groups.iter().enumerate() {
+            lines.push(DuplicateLine::Group(i));
+            for j in 0..group.files.len() {
+                lines.push(DuplicateLine::File(i, j));
+            }
+        }
+
+        Self {
+            root,
+            groups,
+            lines: GenericList::from(lines),
+            confirm: false,
+        }
+    }
+
+    pub fn toggle_keep(&mut self) {
+        if let Some(DuplicateLine::File(i, j)) = self.lines.get_selected() {
+            let file = &mut self.groups[i].files[j];
+            file.keep = !file.keep;
+        }
+    }
+
+    pub fn files_to_remove(&self) -> Vec<PathBuf> {
+        self.groups
+            .iter()
+            .flat_map(|group| group.files.iter())
+            .filter(|file| !file.keep)
+            .map(|file| file.path.clone())
+            .collect()
+    }
+}
+
+pub fn find_duplicates(
+    files: Vec<(PathBuf, Tag)>,
+    match_album: bool,
+    tolerance: Option<f64>
+) -> Vec<DuplicateGroup> {
+    let mut candidates: BTreeMap<String, Vec<(PathBuf, Tag)>> = BTreeMap::new();
+    for (path, tag) in files {
+        let artist = normalize_key(tag.artist().unwrap_or_default());
+        let title = normalize_key(tag.title().unwrap_or_default());
+        if artist.is_empty() || title.is_empty() {
+            continue;
+        }
+
+        let mut key = format!("{}\u{1}{}", artist, title);
+        if match_album {
+            key.push('\u{1}');
+            key.push_str(&normalize_key(tag.album().unwrap_or_default()));
+        }
+        candidates.entry(key).or_default().push((path, tag));
+    }
+
+    let mut groups = Vec::new();
+    for (_, candidates) in candidates.into_iter().filter(|(_, files)| files.len() > 1) {
+        let tag = &candidates[0].1;
+        let title = format!(
+            "{} - {}",
+            tag.artist().unwrap_or_default(),
+            tag.title().unwrap_or_default()
+        );
+
+        let files = candidates
+            .into_iter()
+            .map(|(path, tag)| DuplicateFile::read(path, &tag))
+            .collect::<Vec<DuplicateFile>>();
+
+        let split = match tolerance {
+            Some(tolerance) => split_by_duration(files, tolerance),
+            None => vec![files],
+        };
+        for files in split.into_iter().filter(|files| files.len() > 1) {
+            groups.push(mark_best_copy(title.clone(), files));
+        }
+    }
+
+    groups
+}
+
+// Groups files whose audio hashes are identical, whatever their tags say
+pub fn find_audio_duplicates(files: Vec<(PathBuf, Tag, String)>) -> Vec<DuplicateGroup> {
+    let mut candidates: BTreeMap<String, Vec<(PathBuf, Tag)>> = BTreeMap::new();
+    for (path, tag, hash) in files {
+        candidates.entry(hash).or_default().push((path, tag));
+    }
+
+    candidates
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(hash, candidates)| {
+            let title = format!("Audio {}", &hash[..12]);
+            let files = candidates
+                .into_iter()
+                .map(|(path, tag)| DuplicateFile::read(path, &tag))
+                .collect::<Vec<DuplicateFile>>();
+
+            mark_best_copy(title, files)
+        })
+        .collect()
+}
+
+// Splits files into runs whose durations are each within the tolerance of the
+// previous one. Files without a readable duration (anything but MPEG) can't be
+// compared, so they stay together as a run matched on tags alone.
+fn split_by_duration(files: Vec<DuplicateFile>, tolerance: f64) -> Vec<Vec<DuplicateFile>> {
+    let (mut timed, untimed): (Vec<DuplicateFile>, Vec<DuplicateFile>) = files
+        .into_iter()
+        .partition(|file| file.duration.is_some());
+    timed.sort_by(|a, b| a.duration.partial_cmp(&b.duration).unwrap());
+
+    let mut runs: Vec<Vec<DuplicateFile>> = Vec::new();
+    for file in timed {
+        match runs.last_mut() {
+            Some(run) if file.duration.unwrap() - run.last().unwrap().duration.unwrap() <= tolerance => {
+                run.push(file);
+            },
+            _ => runs.push(vec![file]),
+        }
+    }
+    if !untimed.is_empty() {
+        runs.push(untimed);
+    }
+
+    runs
+}
+
+// Keeps only the copy with the highest bitrate, then the most complete tag,
+// then the largest file
+fn mark_best_copy(title: String, mut files: Vec<DuplicateFile>) -> DuplicateGroup {
+    files.sort_by(|a, b| {
+        b.bitrate.cmp(&a.bitrate)
+            .then(b.completeness.cmp(&a.completeness))
+            .then(b.size.cmp(&a.size))
+            .then(a.path.cmp(&b.path))
+    });
+    for (i, file) in files.iter_mut().enumerate() {
+        file.keep = i == 0;
+    }
+
+    DuplicateGroup { title, files }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, duration: Option<f64>) -> DuplicateFile {
+        DuplicateFile {
+            path: PathBuf::from(name),
+            bitrate: None,
+            duration,
+            size: 0,
+            completeness: 0,
+            keep: true,
+        }
+    }
+
+    fn names(runs: &[Vec<DuplicateFile>]) -> Vec<Vec<&str>> {
+        runs.iter()
+            .map(|run| run.iter().map(|file| file.path.to_str().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn durations_split_into_runs_within_the_tolerance() {
+        let files = vec![
+            file("a.mp3", Some(200.0)),
+            file("b.mp3", Some(240.0)),
+            file("c.mp3", Some(201.5)),
+        ];
+        let runs = split_by_duration(files, 2.0);
+        assert_eq!(names(&runs), vec![vec!["a.mp3", "c.mp3"], vec!["b.mp3"]]);
+    }
+
+    #[test]
+    fn files_without_a_duration_are_kept_as_their_own_run() {
+        let files = vec![
+            file("a.flac", None),
+            file("b.mp3", Some(200.0)),
+            file("c.m4a", None),
+        ];
+        let runs = split_by_duration(files, 2.0);
+        assert_eq!(names(&runs), vec![vec!["b.mp3"], vec!["a.flac", "c.m4a"]]);
+    }
+}
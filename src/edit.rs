@@ -0,0 +1,55 @@
+use crate::input::*;
+
+const MAX_SUGGESTIONS: usize = 8;
+
+// Sets a single frame on every selected file, with completions drawn from the
+// library for the value
+pub struct EditDialog {
+    pub form: InputForm,
+    pub suggestions: Vec<(String, usize)>,
+    pub suggestion: usize,
+}
+
+impl EditDialog {
+    pub fn new() -> Self {
+        Self {
+            form: InputForm::new(
+                "Edit Frame",
+                "Tab: accept suggestion | Enter: stage edit | Esc: cancel",
+                &[("Frame", "TPE1"), ("Value", "")]
+            ),
+            suggestions: Vec::new(),
+            suggestion: 0,
+        }
+    }
+
+    pub fn editing_value(&self) -> bool {
+        self.form.focus == 1
+    }
+
+    pub fn set_suggestions(&mut self, mut suggestions: Vec<(String, usize)>) {
+        suggestions.truncate(MAX_SUGGESTIONS);
+        self.suggestions = suggestions;
+        self.suggestion = 0;
+    }
+
+    pub fn next_suggestion(&mut self) {
+        if !self.suggestions.is_empty() {
+            self.suggestion = (self.suggestion + 1) % self.suggestions.len();
+        }
+    }
+
+    pub fn prev_suggestion(&mut self) {
+        if !self.suggestions.is_empty() {
+            let len = self.suggestions.len();
+            self.suggestion = (self.suggestion + len - 1) % len;
+        }
+    }
+
+    pub fn accept_suggestion(&mut self) {
+        if let Some((value, _)) = self.suggestions.get(self.suggestion) {
+            self.form.inputs[1] = TextInput::from(value.clone());
+            self.suggestions.clear();
+        }
+    }
+}
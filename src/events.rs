@@ -9,6 +9,7 @@ use crossterm::{
 use std::error::Error;
 
 use crate::app::*;
+use crate::input::*;
 
 type DynResult<T> = Result<T, Box<dyn Error>>;
 
@@ -17,6 +18,60 @@ pub fn handle_events(app: &mut App) -> DynResult<()> {
         match app.state {
             AppState::FileNavigation =>
                 handle_file_navigation_events(key, app)?,
+            AppState::FindReplace =>
+                handle_find_replace_events(key, app)?,
+            AppState::Transform =>
+                handle_transform_events(key, app)?,
+            AppState::NormalizeForm =>
+                handle_normalize_form_events(key, app)?,
+            AppState::Renames =>
+                handle_renames_events(key, app)?,
+            AppState::Split =>
+                handle_split_events(key, app)?,
+            AppState::Length =>
+                handle_length_events(key, app)?,
+            AppState::Copy =>
+                handle_copy_events(key, app)?,
+            AppState::Templates =>
+                handle_templates_events(key, app)?,
+            AppState::TemplateName =>
+                handle_template_name_events(key, app)?,
+            AppState::Edit =>
+                handle_edit_events(key, app)?,
+            AppState::Check =>
+                handle_check_events(key, app)?,
+            AppState::Integrity =>
+                handle_integrity_events(key, app)?,
+            AppState::Health =>
+                handle_health_events(key, app)?,
+            AppState::Ape =>
+                handle_ape_events(key, app)?,
+            AppState::EncodingForm =>
+                handle_encoding_form_events(key, app)?,
+            AppState::EncodingReview =>
+                handle_encoding_review_events(key, app)?,
+            AppState::MergeField =>
+                handle_merge_field_events(key, app)?,
+            AppState::Merge =>
+                handle_merge_events(key, app)?,
+            AppState::DuplicatesForm =>
+                handle_duplicates_form_events(key, app)?,
+            AppState::Duplicates =>
+                handle_duplicates_events(key, app)?,
+            AppState::ReplayGainForm =>
+                handle_replaygain_form_events(key, app)?,
+            AppState::ReplayGain =>
+                handle_replaygain_events(key, app)?,
+            AppState::Bpm =>
+                handle_bpm_events(key, app)?,
+            AppState::BpmReview =>
+                handle_bpm_review_events(key, app)?,
+            AppState::Profiles =>
+                handle_profiles_events(key, app)?,
+            AppState::WriteReport =>
+                handle_write_report_events(key, app)?,
+            AppState::Preview =>
+                handle_preview_events(key, app)?,
         }
     }
 
@@ -27,9 +82,20 @@ fn handle_file_navigation_events(
     key: KeyEvent,
     app: &mut App
 ) -> DynResult<()> {
+    if app.confirm_quit {
+        app.confirm_quit = false;
+        app.status = None;
+        if key.code == KeyCode::Char('y') {
+            app.quit = true;
+        }
+        return Ok(());
+    }
+
+    app.status = None;
+
     match key.code {
-        KeyCode::Char('q') => app.quit = true,
-        KeyCode::Esc => app.quit = true,
+        KeyCode::Char('q') => app.request_quit(),
+        KeyCode::Esc => app.request_quit(),
         KeyCode::Char('h') => app.exit_dir()?,
         KeyCode::Char('l') => app.enter_dir()?,
         KeyCode::Char('j') => app.list_down(),
@@ -43,8 +109,650 @@ fn handle_file_navigation_events(
         KeyCode::Char(' ') => app.select()?,
         KeyCode::Enter => app.select()?,
         KeyCode::Char('C') => app.clear_selected_files(),
+        KeyCode::Char('e') => app.open_edit(),
+        KeyCode::Char('R') => app.open_find_replace(),
+        KeyCode::Char('T') => app.open_transform(),
+        KeyCode::Char('N') => app.open_normalize(),
+        KeyCode::Char('S') => app.open_split(),
+        KeyCode::Char('L') => app.open_length(),
+        KeyCode::Char('A') => app.open_replaygain(),
+        KeyCode::Char('B') => app.start_bpm(),
+        KeyCode::Char('y') => app.copy_highlighted_tag(),
+        KeyCode::Char('Y') => app.copy_selection_tag(),
+        KeyCode::Char('p') => app.paste_clipboard(),
+        KeyCode::Char('t') => app.open_templates(),
+        KeyCode::Char('c') => app.open_check(),
+        KeyCode::Char('M') => app.open_merge(),
+        KeyCode::Char('D') => app.open_duplicates(),
+        KeyCode::Char('H') => app.open_health(),
+        KeyCode::Char('P') => app.open_ape(),
+        KeyCode::Char('E') => app.open_encoding(),
+        KeyCode::Char('I') => app.open_integrity(),
+        KeyCode::Char('V') => app.toggle_verify_writes(),
+        KeyCode::Char('w') => app.write_pending_tags(),
+        KeyCode::Char('W') => app.open_profiles(),
+        KeyCode::Char('u') => app.discard_pending_tags(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_find_replace_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.preview_find_replace(),
+        _ => handle_form_input(key, &mut app.replace_form),
+    }
+
+    Ok(())
+}
+
+fn handle_transform_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    let dialog = &mut app.transform_dialog;
+
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.preview_transform(),
+        KeyCode::Tab => dialog.next_field(),
+        KeyCode::BackTab => dialog.prev_field(),
+        _ => {
+            if let Some(input) = dialog.focused_input() {
+                handle_text_input(key, input);
+            } else {
+                match key.code {
+                    KeyCode::Char('j') => dialog.move_cursor(1, 0),
+                    KeyCode::Down => dialog.move_cursor(1, 0),
+                    KeyCode::Char('k') => dialog.move_cursor(-1, 0),
+                    KeyCode::Up => dialog.move_cursor(-1, 0),
+                    KeyCode::Char('l') => dialog.move_cursor(0, 1),
+                    KeyCode::Right => dialog.move_cursor(0, 1),
+                    KeyCode::Char('h') => dialog.move_cursor(0, -1),
+                    KeyCode::Left => dialog.move_cursor(0, -1),
+                    KeyCode::Char(' ') => dialog.toggle(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_normalize_form_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.find_filename_renames(),
+        _ => handle_form_input(key, &mut app.normalize_form),
+    }
+
+    Ok(())
+}
+
+fn handle_renames_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Char('y') => app.rename_files()?,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Esc => app.state = AppState::NormalizeForm,
+        KeyCode::Char('q') => app.state = AppState::NormalizeForm,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_split_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.preview_split(),
+        _ => handle_form_input(key, &mut app.split_form),
+    }
+
+    Ok(())
+}
+
+fn handle_length_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.preview_length(),
+        _ => handle_form_input(key, &mut app.length_form),
+    }
+
+    Ok(())
+}
+
+fn handle_copy_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.cancel_copy(),
+        KeyCode::Char('q') => app.cancel_copy(),
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Char(' ') => {
+            if let Some(dialog) = app.copy_dialog.as_mut() {
+                dialog.toggle();
+            }
+        },
+        KeyCode::Char('a') => {
+            if let Some(dialog) = app.copy_dialog.as_mut() {
+                dialog.toggle_all();
+            }
+        },
+        KeyCode::Enter => app.confirm_copy(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_templates_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    if app.confirm_template_delete {
+        app.confirm_template_delete = false;
+        if key.code == KeyCode::Char('y') {
+            app.delete_selected_template();
+        }
+        return Ok(());
+    }
+
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Char('q') => app.state = AppState::FileNavigation,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Enter => app.apply_template(),
+        KeyCode::Char('n') => app.open_template_name(),
+        KeyCode::Char('d') => app.confirm_template_delete = !app.templates.is_empty(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_template_name_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.open_templates(),
+        KeyCode::Enter => app.save_template(),
+        _ => handle_form_input(key, &mut app.template_form),
+    }
+
+    Ok(())
+}
+
+fn handle_edit_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    let dialog = &mut app.edit_dialog;
+    let suggesting = dialog.editing_value() && !dialog.suggestions.is_empty();
+
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.apply_edit(),
+        KeyCode::Tab if suggesting => dialog.accept_suggestion(),
+        KeyCode::Down if suggesting => dialog.next_suggestion(),
+        KeyCode::Up if suggesting => dialog.prev_suggestion(),
+        KeyCode::Tab => app.edit_switch_field(true),
+        KeyCode::Down => app.edit_switch_field(true),
+        KeyCode::BackTab => app.edit_switch_field(false),
+        KeyCode::Up => app.edit_switch_field(false),
+        _ => {
+            handle_form_input(key, &mut dialog.form);
+            app.refresh_suggestions();
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_check_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    app.status = None;
+
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Char('q') => app.state = AppState::FileNavigation,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Enter => app.jump_to_finding()?,
+        KeyCode::Char('f') => app.fix_finding(),
+        KeyCode::Char('F') => app.fix_all_findings(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_integrity_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Char('q') => app.state = AppState::FileNavigation,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Enter => app.jump_to_integrity_finding()?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_health_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Char('q') => app.state = AppState::FileNavigation,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Enter => app.jump_to_health_issue()?,
+        KeyCode::Char('s') => {
+            if let Some(report) = app.health.as_mut() {
+                report.cycle_sort();
+            }
+        },
         _ => {}
     }
 
     Ok(())
 }
+
+fn handle_ape_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    let view = match app.ape.as_mut() {
+        Some(view) => view,
+        None => return Ok(()),
+    };
+
+    if view.confirm {
+        match key.code {
+            KeyCode::Char('y') => app.remove_ape_tags(),
+            _ => view.confirm = false,
+        }
+        return Ok(());
+    }
+
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Char('q') => app.state = AppState::FileNavigation,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Char('m') => app.preview_ape_migration(false),
+        KeyCode::Char('M') => app.preview_ape_migration(true),
+        KeyCode::Char('X') => view.confirm = true,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_encoding_form_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.find_mojibake(),
+        _ => handle_form_input(key, &mut app.encoding_form),
+    }
+
+    Ok(())
+}
+
+fn handle_encoding_review_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    let review = match app.encoding_review.as_mut() {
+        Some(review) => review,
+        None => return Ok(()),
+    };
+
+    match key.code {
+        KeyCode::Esc => app.state = AppState::EncodingForm,
+        KeyCode::Char('q') => app.state = AppState::EncodingForm,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Char('l') => review.next_candidate(),
+        KeyCode::Right => review.next_candidate(),
+        KeyCode::Char('h') => review.prev_candidate(),
+        KeyCode::Left => review.prev_candidate(),
+        KeyCode::Char(' ') => review.toggle_accept(),
+        KeyCode::Char('a') => review.apply_choice_to_all(),
+        KeyCode::Enter => app.preview_encoding(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_merge_field_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.find_merge_clusters(),
+        _ => handle_form_input(key, &mut app.merge_form),
+    }
+
+    Ok(())
+}
+
+fn handle_merge_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::MergeField,
+        KeyCode::Char('q') => app.state = AppState::MergeField,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Char(' ') => {
+            if let Some(view) = app.merge_view.as_mut() {
+                view.toggle_canonical();
+            }
+        },
+        KeyCode::Enter => app.preview_merge(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_duplicates_form_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.find_duplicates(),
+        _ => handle_form_input(key, &mut app.duplicates_form),
+    }
+
+    Ok(())
+}
+
+fn handle_duplicates_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    let view = match app.duplicates.as_mut() {
+        Some(view) => view,
+        None => return Ok(()),
+    };
+
+    if view.confirm {
+        match key.code {
+            KeyCode::Char('y') => app.remove_duplicates()?,
+            _ => view.confirm = false,
+        }
+        return Ok(());
+    }
+
+    match key.code {
+        KeyCode::Esc => app.state = AppState::DuplicatesForm,
+        KeyCode::Char('q') => app.state = AppState::DuplicatesForm,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Char(' ') => view.toggle_keep(),
+        KeyCode::Char('D') => view.confirm = !view.files_to_remove().is_empty(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_replaygain_form_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Enter => app.start_replaygain(),
+        _ => handle_form_input(key, &mut app.replaygain_form),
+    }
+
+    Ok(())
+}
+
+fn handle_replaygain_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    if key.code == KeyCode::Esc {
+        app.cancel_replaygain();
+    }
+
+    Ok(())
+}
+
+fn handle_bpm_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    if key.code == KeyCode::Esc {
+        app.cancel_bpm();
+    }
+
+    Ok(())
+}
+
+fn handle_bpm_review_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    let review = match app.bpm_review.as_mut() {
+        Some(review) => review,
+        None => return Ok(()),
+    };
+
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Char('q') => app.state = AppState::FileNavigation,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Char(' ') => review.toggle_accept(),
+        KeyCode::Char('t') => review.toggle_tenths(),
+        KeyCode::Char('h') => review.halve(),
+        KeyCode::Char('d') => review.double(),
+        KeyCode::Enter => app.preview_bpm(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_profiles_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.state = AppState::FileNavigation,
+        KeyCode::Char('q') => app.state = AppState::FileNavigation,
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Enter => app.write_with_selected_profile(),
+        KeyCode::Char('d') => app.toggle_default_profile(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_write_report_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Char('y') => app.confirm_write(),
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Esc => app.cancel_write(),
+        KeyCode::Char('q') => app.cancel_write(),
+        KeyCode::Char('n') => app.cancel_write(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_preview_events(
+    key: KeyEvent,
+    app: &mut App
+) -> DynResult<()> {
+    match key.code {
+        KeyCode::Esc => app.close_preview(),
+        KeyCode::Char('q') => app.close_preview(),
+        KeyCode::Char('j') => app.list_down(),
+        KeyCode::Down => app.list_down(),
+        KeyCode::Char('k') => app.list_up(),
+        KeyCode::Up => app.list_up(),
+        KeyCode::Char('g') => app.jump_to_list_top(),
+        KeyCode::Home => app.jump_to_list_top(),
+        KeyCode::Char('G') => app.jump_to_list_bottom(),
+        KeyCode::End => app.jump_to_list_bottom(),
+        KeyCode::Char('y') => app.apply_preview(),
+        KeyCode::Enter => app.apply_preview(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_form_input(key: KeyEvent, form: &mut InputForm) {
+    match key.code {
+        KeyCode::Tab => form.next_field(),
+        KeyCode::Down => form.next_field(),
+        KeyCode::BackTab => form.prev_field(),
+        KeyCode::Up => form.prev_field(),
+        _ => {
+            form.error = None;
+            handle_text_input(key, form.focused_input());
+        }
+    }
+}
+
+fn handle_text_input(key: KeyEvent, input: &mut TextInput) {
+    match key.code {
+        KeyCode::Char(c) => input.insert(c),
+        KeyCode::Backspace => input.backspace(),
+        KeyCode::Delete => input.delete(),
+        KeyCode::Left => input.left(),
+        KeyCode::Right => input.right(),
+        KeyCode::Home => input.home(),
+        KeyCode::End => input.end(),
+        _ => {}
+    }
+}
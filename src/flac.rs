@@ -0,0 +1,260 @@
+use std::{
+    fs::{
+        self,
+        OpenOptions,
+    },
+    io::{
+        self,
+        Error,
+        ErrorKind,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    path::Path,
+};
+
+use crate::backend::*;
+use crate::mpeg::*;
+use crate::vorbis::*;
+
+const PADDING: u8 = 1;
+const VORBIS_COMMENT: u8 = 4;
+const PICTURE: u8 = 6;
+
+// Padding left for future edits when the metadata has to grow
+const NEW_PADDING: usize = 4096;
+const MAX_BLOCK_LEN: usize = 0xFF_FFFF;
+
+struct Block<'a> {
+    kind: u8,
+    body: &'a [u8],
+}
+
+// The metadata blocks of a FLAC stream, which may be preceded by ID3v2 tags
+struct Metadata<'a> {
+    start: usize,
+    blocks: Vec<Block<'a>>,
+    audio_offset: usize,
+}
+
+fn read_metadata(data: &[u8]) -> Option<Metadata<'_>> {
+    let start = leading_id3v2_tags(data)
+        .last()
+        .map(|(offset, len)| offset + len)
+        .unwrap_or(0);
+    if data.get(start..start + 4)? != b"fLaC" {
+        return None;
+    }
+
+    let mut blocks = Vec::new();
+    let mut pos = start + 4;
+    loop {
+        let header = data.get(pos..pos + 4)?;
+        let is_last = header[0] & 0x80 != 0;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let body = data.get(pos + 4..pos + 4 + len)?;
+        blocks.push(Block { kind: header[0] & 0x7F, body });
+        pos += 4 + len;
+        if is_last {
+            break;
+        }
+    }
+
+    Some(Metadata { start, blocks, audio_offset: pos })
+}
+
+fn invalid_flac() -> Error {
+    Error::new(ErrorKind::InvalidData, "Invalid FLAC metadata")
+}
+
+fn read_flac(path: &Path) -> io::Result<Option<TagModel>> {
+    let data = fs::read(path)?;
+    let metadata = read_metadata(&data).ok_or_else(invalid_flac)?;
+
+    let comments = metadata.blocks
+        .iter()
+        .find(|block| block.kind == VORBIS_COMMENT)
+        .and_then(|block| parse_comments(block.body));
+    let pictures = metadata.blocks
+        .iter()
+        .filter(|block| block.kind == PICTURE)
+        .filter_map(|block| parse_picture(block.body))
+        .collect::<Vec<Cover>>();
+    if comments.is_none() && pictures.is_empty() {
+        return Ok(None);
+    }
+
+    let fields = comments.map(|comments| comments.fields).unwrap_or_default();
+    Ok(Some(comments_to_model(&fields, pictures)))
+}
+
+// Replaces the Vorbis comment and picture blocks. When the new metadata fits
+// in the space of the old (including its padding) it is written in place,
+// otherwise the whole file is rewritten with fresh padding.
+fn write_flac(model: &TagModel, path: &Path) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let metadata = read_metadata(&data).ok_or_else(invalid_flac)?;
+
+    let old_comments = metadata.blocks
+        .iter()
+        .find(|block| block.kind == VORBIS_COMMENT)
+        .and_then(|block| parse_comments(block.body));
+    let (fields, pictures) = model_to_comments(model);
+    let comments = Comments {
+        vendor: old_comments.map(|comments| comments.vendor).unwrap_or_else(|| "rime".to_string()),
+        fields,
+    };
+
+    let mut blocks = metadata.blocks
+        .iter()
+        .filter(|block| ![PADDING, VORBIS_COMMENT, PICTURE].contains(&block.kind))
+        .map(|block| (block.kind, block.body.to_vec()))
+        .collect::<Vec<(u8, Vec<u8>)>>();
+    blocks.push((VORBIS_COMMENT, serialize_comments(&comments)));
+    for picture in pictures.iter() {
+        // Unchanged pictures keep their original block, dimensions included
+        let existing = metadata.blocks
+            .iter()
+            .filter(|block| block.kind == PICTURE)
+            .find(|block| parse_picture(block.body).as_ref() == Some(picture));
+        match existing {
+            Some(block) => blocks.push((PICTURE, block.body.to_vec())),
+            None => blocks.push((PICTURE, serialize_picture(picture))),
+        }
+    }
+    if blocks.iter().any(|(_, body)| body.len() > MAX_BLOCK_LEN) {
+        return Err(Error::new(ErrorKind::InvalidInput, "Metadata block too large for FLAC"));
+    }
+
+    let old_len = metadata.audio_offset - metadata.start - 4;
+    let new_len = blocks.iter().map(|(_, body)| 4 + body.len()).sum::<usize>();
+    let in_place = new_len == old_len || new_len + 4 <= old_len;
+    if new_len != old_len {
+        let padding = if in_place { old_len - new_len - 4 } else { NEW_PADDING };
+        blocks.push((PADDING, vec![0; padding]));
+    }
+
+    let mut encoded = b"fLaC".to_vec();
+    let num_blocks = blocks.len();
+    for (i, (kind, body)) in blocks.into_iter().enumerate() {
+        let last = if i + 1 == num_blocks { 0x80 } else { 0 };
+        encoded.push(kind | last);
+        encoded.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        encoded.extend(body);
+    }
+
+    if in_place {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(metadata.start as u64))?;
+        file.write_all(&encoded)?;
+    } else {
+        let mut contents = data[..metadata.start].to_vec();
+        contents.extend(encoded);
+        contents.extend(&data[metadata.audio_offset..]);
+        fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+pub struct FlacBackend;
+
+impl TagBackend for FlacBackend {
+    fn read(&self, path: &Path) -> io::Result<Option<TagModel>> {
+        read_flac(path)
+    }
+
+    fn write(&self, model: &TagModel, path: &Path) -> io::Result<()> {
+        write_flac(model, path)
+    }
+}
+
+// Where the FLAC frames start, after all metadata
+pub fn flac_audio_offset(data: &[u8]) -> Option<usize> {
+    read_metadata(data).map(|metadata| metadata.audio_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+    use crate::testutil::*;
+
+    const AUDIO: &[u8] = b"\xff\xf8not really flac frames";
+
+    fn flac_file(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut data = b"fLaC".to_vec();
+        for (i, (kind, body)) in blocks.iter().enumerate() {
+            let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+            data.push(kind | last);
+            data.extend(&(body.len() as u32).to_be_bytes()[1..]);
+            data.extend(body);
+        }
+        data.extend(AUDIO);
+
+        data
+    }
+
+    fn front_cover(data: &[u8]) -> Cover {
+        Cover {
+            mime_type: "image/png".to_string(),
+            picture_type: 3,
+            description: String::new(),
+            data: data.to_vec(),
+        }
+    }
+
+    fn sample_flac() -> Vec<u8> {
+        let comments = Comments {
+            vendor: "test".to_string(),
+            fields: vec![("TITLE".to_string(), "Old".to_string())],
+        };
+        flac_file(&[
+            (0, vec![0; 34]),
+            (VORBIS_COMMENT, serialize_comments(&comments)),
+            (PICTURE, serialize_picture(&front_cover(b"first"))),
+            (PICTURE, serialize_picture(&front_cover(b"second"))),
+            (PADDING, vec![0; 16]),
+        ])
+    }
+
+    #[test]
+    fn pictures_of_one_type_survive_an_edit() {
+        let path = scratch_file("track.flac", &sample_flac());
+        let mut tag = read_tag(&path).unwrap();
+        assert_eq!(tag.pictures().count(), 2);
+
+        tag.set_title("New");
+        write_tag(&tag, &path).unwrap();
+
+        let model = read_flac(&path).unwrap().unwrap();
+        assert_eq!(model.first(&Field::Title), Some("New"));
+        assert_eq!(model.pictures, vec![front_cover(b"first"), front_cover(b"second")]);
+        assert!(fs::read(&path).unwrap().ends_with(AUDIO));
+    }
+
+    #[test]
+    fn growing_metadata_rewrites_the_file() {
+        let path = scratch_file("track.flac", &sample_flac());
+        let mut tag = read_tag(&path).unwrap();
+        tag.set_title("A title much longer than the padding left in the file");
+        write_tag(&tag, &path).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let metadata = read_metadata(&data).unwrap();
+        assert_eq!(&data[metadata.audio_offset..], AUDIO);
+        assert_eq!(metadata.blocks.last().unwrap().body.len(), NEW_PADDING);
+    }
+
+    #[test]
+    fn truncated_metadata_is_rejected() {
+        let data = sample_flac();
+        assert!(read_metadata(&data[..20]).is_none());
+
+        let path = scratch_file("track.flac", &data[..20]);
+        assert_eq!(read_flac(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(write_flac(&TagModel::new(TagFormat::Vorbis), &path).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::list::*;
+use crate::mpeg::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HealthIssue {
+    pub path: PathBuf,
+    pub severity: Severity,
+    pub problem: &'static str,
+    pub detail: String,
+}
+
+pub fn check_file(path: &Path) -> Vec<HealthIssue> {
+    let issues = match fs::read(path) {
+        Ok(data) => check_stream(&data),
+        Err(err) => vec![(Severity::Error, "Unreadable", err.to_string())],
+    };
+
+    issues
+        .into_iter()
+        .map(|(severity, problem, detail)| HealthIssue {
+            path: path.to_path_buf(),
+            severity,
+            problem,
+            detail,
+        })
+        .collect()
+}
+
+fn check_stream(data: &[u8]) -> Vec<(Severity, &'static str, String)> {
+    let mut issues = Vec::new();
+
+    let tags = leading_id3v2_tags(data);
+    if tags.len() > 1 {
+        issues.push((
+            Severity::Warning,
+            "Multiple ID3v2 tags",
+            format!("{} tags at the start of the file", tags.len()),
+        ));
+    }
+
+    let scan = scan_frames(data);
+    if scan.frames.is_empty() {
+        issues.push((Severity::Error, "No audio", "no MPEG frames found".to_string()));
+        return issues;
+    }
+
+    let (start, end) = audio_range(data);
+    let mut junk = scan.junk.as_slice();
+    if let Some((from, to)) = junk.first().filter(|(from, _)| *from == start) {
+        issues.push((
+            Severity::Warning,
+            "Junk before audio",
+            format!("{} bytes at offset {}", to - from, from),
+        ));
+        junk = &junk[1..];
+    }
+    if let Some((from, to)) = junk.last().filter(|(_, to)| *to == end) {
+        issues.push((
+            Severity::Warning,
+            "Junk after audio",
+            format!("{} bytes at offset {}", to - from, from),
+        ));
+        junk = &junk[..junk.len() - 1];
+    }
+    if let Some((first, _)) = junk.first() {
+        let skipped = junk.iter().map(|(from, to)| to - from).sum::<usize>();
+        let detail = if junk.len() == 1 {
+            format!("{} bytes of junk skipped at offset {}", skipped, first)
+        } else {
+            format!(
+                "{} bytes of junk skipped in {} places, first at offset {}",
+                skipped, junk.len(), first
+            )
+        };
+        issues.push((Severity::Error, "Sync lost", detail));
+    }
+
+    for (from, to) in scan.junk.iter() {
+        for offset in find_id3v2_headers(data, *from, *to) {
+            issues.push((
+                Severity::Warning,
+                "Misplaced ID3v2 tag",
+                format!("tag header at offset {}", offset),
+            ));
+        }
+    }
+
+    if let Some((offset, expected, actual)) = scan.truncated {
+        issues.push((
+            Severity::Error,
+            "Truncated frame",
+            format!("last frame at offset {} has {} of {} bytes", offset, actual, expected),
+        ));
+    }
+
+    let (pos, header) = scan.frames[0];
+    if let Some(vbr_header) = VbrHeader::parse(&data[pos..pos + header.frame_len()], &header) {
+        let found = scan.frames.len() - 1;
+        if let Some(frames) = vbr_header.frames.filter(|frames| *frames as usize != found) {
+            issues.push((
+                Severity::Warning,
+                "Frame count mismatch",
+                format!("header says {} frames, found {}", frames, found),
+            ));
+        }
+    }
+
+    issues
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HealthSort {
+    File,
+    Severity,
+    Problem,
+}
+
+impl HealthSort {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HealthSort::File => "file",
+            HealthSort::Severity => "severity",
+            HealthSort::Problem => "problem",
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            HealthSort::File => HealthSort::Severity,
+            HealthSort::Severity => HealthSort::Problem,
+            HealthSort::Problem => HealthSort::File,
+        }
+    }
+}
+
+pub struct HealthReport {
+    pub issues: GenericList<HealthIssue>,
+    pub sort: HealthSort,
+    pub num_files: usize,
+}
+
+impl HealthReport {
+    pub fn new(issues: Vec<HealthIssue>, num_files: usize) -> Self {
+        let mut report = Self {
+            issues: GenericList::from(issues),
+            sort: HealthSort::File,
+            num_files,
+        };
+        report.sort_issues();
+
+        report
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.sort_issues();
+    }
+
+    fn sort_issues(&mut self) {
+        let sort = self.sort;
+        self.issues.items.sort_by(|a, b| {
+            let by_file = a.path.cmp(&b.path);
+            match sort {
+                HealthSort::File => by_file.then(a.severity.cmp(&b.severity)),
+                HealthSort::Severity => a.severity.cmp(&b.severity).then(by_file),
+                HealthSort::Problem => a.problem.cmp(b.problem).then(by_file),
+            }
+        });
+        self.issues.select_first();
+    }
+}
@@ -0,0 +1,114 @@
+pub struct TextInput {
+    pub text: String,
+    cursor: usize,
+}
+
+impl TextInput {
+    pub fn new() -> Self {
+        Self {
+            text: String::new(),
+            cursor: 0,
+        }
+    }
+
+    pub fn from(text: String) -> Self {
+        let cursor = text.chars().count();
+
+        Self { text, cursor }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let index = self.byte_index(self.cursor);
+        self.text.insert(index, c);
+        self.cursor += 1;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let index = self.byte_index(self.cursor);
+            self.text.remove(index);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            let index = self.byte_index(self.cursor);
+            self.text.remove(index);
+        }
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = std::cmp::min(self.cursor + 1, self.text.chars().count());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(cursor)
+            .map(|(i, _)| i)
+            .unwrap_or(self.text.len())
+    }
+}
+
+// A popup made of labelled single-line inputs, one of which has focus
+pub struct InputForm {
+    pub title: &'static str,
+    pub help: &'static str,
+    pub labels: Vec<&'static str>,
+    pub inputs: Vec<TextInput>,
+    pub focus: usize,
+    pub error: Option<String>,
+}
+
+impl InputForm {
+    pub fn new(
+        title: &'static str,
+        help: &'static str,
+        fields: &[(&'static str, &str)]
+    ) -> Self {
+        Self {
+            title,
+            help,
+            labels: fields.iter().map(|(label, _)| *label).collect(),
+            inputs: fields
+                .iter()
+                .map(|(_, value)| TextInput::from(value.to_string()))
+                .collect(),
+            focus: 0,
+            error: None,
+        }
+    }
+
+    pub fn value(&self, index: usize) -> &str {
+        &self.inputs[index].text
+    }
+
+    pub fn focused_input(&mut self) -> &mut TextInput {
+        &mut self.inputs[self.focus]
+    }
+
+    pub fn next_field(&mut self) {
+        self.focus = (self.focus + 1) % self.inputs.len();
+    }
+
+    pub fn prev_field(&mut self) {
+        self.focus = (self.focus + self.inputs.len() - 1) % self.inputs.len();
+    }
+}
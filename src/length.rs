@@ -0,0 +1,128 @@
+use id3::Tag;
+use std::path::PathBuf;
+
+use crate::input::*;
+use crate::mpeg::*;
+use crate::preview::*;
+use crate::tags::*;
+
+pub fn length_form() -> InputForm {
+    InputForm::new(
+        "Fill Length (TLEN)",
+        "Flag existing values off by more than this many ms | Enter: preview",
+        &[("Tolerance", "1000")]
+    )
+}
+
+// Sets TLEN to the duration of the MPEG audio in milliseconds, warning about
+// files whose existing TLEN was further off than the tolerance
+pub fn fill_lengths(
+    tags: &[(PathBuf, Tag)],
+    form: &InputForm
+) -> Result<Vec<FileDiff>, String> {
+    let tolerance = form.value(0)
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| "Tolerance must be a number of milliseconds".to_string())?;
+
+    let diffs = tags.iter()
+        .map(|(path, tag)| {
+            let length = match read_stream_info(path) {
+                Ok(info) => (info.duration * 1000.0).round() as u64,
+                Err(err) => {
+                    return FileDiff {
+                        path: path.clone(),
+                        changes: Vec::new(),
+                        warning: Some(err.to_string()),
+                    };
+                }
+            };
+
+            let old = text_value(tag, "TLEN");
+            let warning = old.as_ref().and_then(|old| {
+                match old.trim().parse::<u64>() {
+                    Ok(old) if old.abs_diff(length) > tolerance => {
+                        Some(format!("TLEN was off by {} ms", old.abs_diff(length)))
+                    },
+                    Ok(_) => None,
+                    Err(_) => Some(format!("TLEN '{}' is not a number", old)),
+                }
+            });
+
+            let new = length.to_string();
+            let changes = if old.as_deref() == Some(new.as_str()) {
+                Vec::new()
+            } else {
+                vec![FrameChange { id: "TLEN".to_string(), old, new }]
+            };
+
+            FileDiff { path: path.clone(), changes, warning }
+        })
+        .collect();
+
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+    use crate::testutil::*;
+
+    // 100 frames of 1152 samples at 44.1 kHz
+    const LENGTH_MS: &str = "2612";
+
+    fn track(tlen: Option<&str>) -> (PathBuf, Tag) {
+        let mut tag = Tag::new();
+        if let Some(tlen) = tlen {
+            tag.set_text("TLEN", tlen);
+        }
+        (scratch_file("track.mp3", &mpeg_frames(100)), tag)
+    }
+
+    fn form(tolerance: &str) -> InputForm {
+        let mut form = length_form();
+        form.inputs[0] = TextInput::from(tolerance.to_string());
+
+        form
+    }
+
+    #[test]
+    fn lengths_are_filled_from_the_audio() {
+        let diffs = fill_lengths(&[track(None), track(Some(LENGTH_MS))], &form("1000")).unwrap();
+
+        assert_eq!(diffs[0].changes[0].new, LENGTH_MS);
+        assert_eq!(diffs[0].warning, None);
+        assert!(diffs[1].changes.is_empty());
+    }
+
+    #[test]
+    fn only_lengths_off_by_more_than_the_tolerance_are_flagged() {
+        let tracks = [track(Some("2000")), track(Some("1000")), track(Some("about 3s"))];
+        let diffs = fill_lengths(&tracks, &form("1000")).unwrap();
+
+        assert_eq!(diffs[0].warning, None);
+        assert_eq!(diffs[1].warning.as_deref(), Some("TLEN was off by 1612 ms"));
+        assert_eq!(diffs[2].warning.as_deref(), Some("TLEN 'about 3s' is not a number"));
+        assert!(diffs.iter().all(|diff| diff.changes[0].new == LENGTH_MS));
+
+        let diffs = fill_lengths(&tracks[..1], &form("0")).unwrap();
+        assert_eq!(diffs[0].warning.as_deref(), Some("TLEN was off by 612 ms"));
+    }
+
+    #[test]
+    fn files_without_mpeg_audio_are_left_alone() {
+        let wav = (scratch_file("track.wav", &mpeg_frames(100)), Tag::new());
+        let diffs = fill_lengths(&[wav], &form("1000")).unwrap();
+
+        assert!(diffs[0].changes.is_empty());
+        assert_eq!(diffs[0].warning.as_deref(), Some("Not an MPEG stream"));
+    }
+
+    #[test]
+    fn the_tolerance_must_be_a_whole_number() {
+        assert!(fill_lengths(&[], &form("-5")).is_err());
+        assert!(fill_lengths(&[], &form("1.5")).is_err());
+    }
+}
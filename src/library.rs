@@ -0,0 +1,196 @@
+use id3::Tag;
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::config::*;
+use crate::merge::*;
+use crate::tags::*;
+
+const CACHE_FILE: &str = "library";
+
+// Text frame values of every file whose tag has been read, used to suggest
+// completions while editing. Stored in the cache directory so values from
+// earlier sessions are suggested too.
+pub struct Library {
+    files: HashMap<PathBuf, Vec<(String, String)>>,
+    dirty: bool,
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Self {
+            files: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    // Files that no longer exist are dropped, so deleted tracks stop being
+    // suggested
+    pub fn load() -> Self {
+        let contents = cache_file(CACHE_FILE)
+            .and_then(fs::read_to_string)
+            .unwrap_or_default();
+        let mut library = Self::from_lines(&contents);
+        let before = library.files.len();
+        library.files.retain(|path, _| path.exists());
+        library.dirty = library.files.len() != before;
+
+        library
+    }
+
+    pub fn save(&mut self) -> Result<(), io::Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        fs::write(cache_file(CACHE_FILE)?, self.to_lines())?;
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    // One line per frame value: the frame id, the value and the path,
+    // separated by tabs. Values spanning lines can't be stored and are
+    // never useful as completions anyway.
+    fn from_lines(contents: &str) -> Self {
+        let mut library = Self::new();
+        for line in contents.lines() {
+            let fields = line.splitn(3, '\t').collect::<Vec<&str>>();
+            if let [id, value, path] = fields[..] {
+                library.files
+                    .entry(PathBuf::from(path))
+                    .or_default()
+                    .push((id.to_string(), value.to_string()));
+            }
+        }
+
+        library
+    }
+
+    fn to_lines(&self) -> String {
+        let mut paths = self.files.keys().collect::<Vec<&PathBuf>>();
+        paths.sort();
+
+        let mut contents = String::new();
+        for path in paths {
+            for (id, value) in &self.files[path] {
+                if value.contains(['\t', '\n', '\r']) {
+                    continue;
+                }
+                contents.push_str(&format!("{}\t{}\t{}\n", id, value, path.display()));
+            }
+        }
+
+        contents
+    }
+
+    pub fn record(&mut self, path: &Path, tag: &Tag) {
+        let values = text_frames(tag, &FrameFilter::AllText);
+        if self.files.get(path) != Some(&values) {
+            self.files.insert(path.to_path_buf(), values);
+            self.dirty = true;
+        }
+    }
+
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        if let Some(values) = self.files.remove(from) {
+            self.files.insert(to.to_path_buf(), values);
+            self.dirty = true;
+        }
+    }
+
+    // Values of the given frame that start with (or failing that, contain)
+    // the input, ignoring case and diacritics, ranked by how many files use
+    // them. Tags in the overrides replace whatever was recorded for their
+    // paths.
+    pub fn suggestions(
+        &self,
+        id: &str,
+        input: &str,
+        overrides: &[(PathBuf, Tag)]
+    ) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut count = |values: &[(String, String)]| {
+            for (frame_id, value) in values {
+                if frame_id == id {
+                    *counts.entry(value.clone()).or_insert(0) += 1;
+                }
+            }
+        };
+
+        for (path, values) in self.files.iter() {
+            if !overrides.iter().any(|(other, _)| other == path) {
+                count(values);
+            }
+        }
+        for (_, tag) in overrides {
+            count(&text_frames(tag, &FrameFilter::AllText));
+        }
+
+        // A differently accented spelling of the input is still worth
+        // suggesting, only the exact value is left out
+        let folded = fold(input);
+        let mut suggestions = counts
+            .into_iter()
+            .filter(|(value, _)| value != input && fold(value).contains(&folded))
+            .collect::<Vec<(String, usize)>>();
+        suggestions.sort_by(|(a, a_count), (b, b_count)| {
+            let a_prefix = fold(a).starts_with(&folded);
+            let b_prefix = fold(b).starts_with(&folded);
+            b_prefix.cmp(&a_prefix)
+                .then(b_count.cmp(a_count))
+                .then(a.cmp(b))
+        });
+
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+
+    fn artist(name: &str) -> Tag {
+        let mut tag = Tag::new();
+        tag.set_artist(name);
+        tag
+    }
+
+    #[test]
+    fn suggestions_fold_diacritics() {
+        let mut library = Library::new();
+        library.record(Path::new("a.mp3"), &artist("Beyoncé"));
+        library.record(Path::new("b.mp3"), &artist("Beyoncé"));
+        library.record(Path::new("c.mp3"), &artist("Beyonce"));
+
+        let suggestions = library.suggestions("TPE1", "beyonce", &[]);
+        assert_eq!(suggestions[0], ("Beyoncé".to_string(), 2));
+        assert_eq!(suggestions[1], ("Beyonce".to_string(), 1));
+
+        let suggestions = library.suggestions("TPE1", "Beyonce", &[]);
+        assert_eq!(suggestions, vec![("Beyoncé".to_string(), 2)]);
+    }
+
+    #[test]
+    fn the_index_survives_a_save_and_load() {
+        let mut library = Library::new();
+        library.record(Path::new("/music/a.mp3"), &artist("Sigur Rós"));
+        let mut tag = artist("Múm");
+        tag.set_title("Green\tGrass");
+        library.record(Path::new("/music/b\tc.mp3"), &tag);
+
+        let loaded = Library::from_lines(&library.to_lines());
+        assert_eq!(loaded.suggestions("TPE1", "s", &[]), vec![("Sigur Rós".to_string(), 1)]);
+        assert_eq!(loaded.suggestions("TPE1", "m", &[]), vec![("Múm".to_string(), 1)]);
+        assert!(loaded.suggestions("TIT2", "green", &[]).is_empty());
+    }
+}
@@ -0,0 +1,409 @@
+use id3::{
+    frame::PictureType,
+    Tag,
+    TagLike,
+    Version,
+};
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::container::*;
+use crate::preview::*;
+use crate::tags::*;
+
+const REQUIRED_FRAMES: [&str; 4] = ["TIT2", "TPE1", "TALB", "TRCK"];
+const DIRECTORY_FRAMES: [&str; 3] = ["TPE2", "TALB", "TYER"];
+
+#[derive(Clone)]
+pub struct Finding {
+    pub path: PathBuf,
+    pub message: String,
+    pub fix: Option<FrameChange>,
+}
+
+impl Finding {
+    pub fn new(path: &Path, message: String) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            message,
+            fix: None,
+        }
+    }
+
+    fn with_fix(mut self, fix: FrameChange) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+}
+
+// A file to check: its tag including pending edits, and the version of the
+// ID3 tag on disk (None if the file has no tag at all). FLAC, Ogg and MP4
+// files are read into ID3v2.4 tags, so their version means nothing.
+pub struct LintFile {
+    pub path: PathBuf,
+    pub tag: Tag,
+    pub version: Option<Version>,
+}
+
+pub fn lint(files: &[LintFile]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for file in files {
+        check_required_frames(file, &mut findings);
+        check_front_cover(file, &mut findings);
+    }
+    check_tag_versions(files, &mut findings);
+
+    for dir_files in group_by(files, |file| file.path.parent().map(Path::to_path_buf)).values() {
+        check_directory_frames(dir_files, &mut findings);
+        check_track_totals(dir_files, &mut findings);
+
+        let albums = group_by(dir_files.iter().copied(), |file| text_value(&file.tag, "TALB"));
+        for album_files in albums.values() {
+            check_track_numbers(album_files, &mut findings);
+        }
+    }
+
+    findings.sort_by(|a, b| a.path.cmp(&b.path));
+    findings
+}
+
+fn check_required_frames(file: &LintFile, findings: &mut Vec<Finding>) {
+    for id in REQUIRED_FRAMES {
+        let missing = text_value(&file.tag, id)
+            .map(|value| value.trim().is_empty())
+            .unwrap_or(true);
+        if missing {
+            findings.push(Finding::new(
+                &file.path,
+                format!("Missing {}", frame_name(id).to_lowercase())
+            ));
+        }
+    }
+}
+
+fn check_front_cover(file: &LintFile, findings: &mut Vec<Finding>) {
+    let has_cover = file.tag
+        .pictures()
+        .any(|picture| picture.picture_type == PictureType::CoverFront);
+    if !has_cover {
+        findings.push(Finding::new(&file.path, "Missing front cover".to_string()));
+    }
+}
+
+fn check_tag_versions(files: &[LintFile], findings: &mut Vec<Finding>) {
+    let files = files
+        .iter()
+        .filter(|file| Container::from_path(&file.path).is_some_and(|container| container.has_id3()))
+        .collect::<Vec<&LintFile>>();
+    let counts = count_values(files.iter().map(|file| file.version.map(version_name)));
+    if counts.len() < 2 {
+        return;
+    }
+
+    let (usual, _) = most_common(&counts);
+    for file in files {
+        let version = file.version.map(version_name);
+        if version != usual {
+            findings.push(Finding::new(
+                &file.path,
+                format!(
+                    "Tag version {} differs from the rest of the selection ({})",
+                    version.unwrap_or("<none>"),
+                    usual.unwrap_or("<none>")
+                )
+            ));
+        }
+    }
+}
+
+// Album level frames should agree for every file in a directory; suggest the
+// most common value as the fix
+fn check_directory_frames(files: &[&LintFile], findings: &mut Vec<Finding>) {
+    for id in DIRECTORY_FRAMES {
+        let counts = count_values(files.iter().map(|file| directory_value(&file.tag, id)));
+        if counts.len() < 2 {
+            continue;
+        }
+
+        let (usual, _) = most_common(&counts);
+        for file in files {
+            let value = directory_value(&file.tag, id);
+            if value == usual {
+                continue;
+            }
+
+            let finding = Finding::new(
+                &file.path,
+                format!(
+                    "{} '{}' differs from the rest of the directory",
+                    frame_name(id),
+                    value.clone().unwrap_or_default()
+                )
+            );
+            let finding = match &usual {
+                Some(usual) => finding.with_fix(FrameChange {
+                    id: id.to_string(),
+                    old: value,
+                    new: usual.clone(),
+                }),
+                None => finding,
+            };
+            findings.push(finding);
+        }
+    }
+}
+
+// The year is compared as a year, whether it's in TYER or at the start of a
+// TDRC date
+fn directory_value(tag: &Tag, id: &str) -> Option<String> {
+    match id {
+        "TYER" => year_value(tag),
+        id => text_value(tag, id),
+    }
+}
+
+fn check_track_totals(files: &[&LintFile], findings: &mut Vec<Finding>) {
+    let count = files.len() as u32;
+    for file in files {
+        let track = match file.tag.track() {
+            Some(track) => track,
+            None => continue,
+        };
+
+        if let Some(total) = file.tag.total_tracks() {
+            if total == count {
+                continue;
+            }
+
+            let finding = Finding::new(
+                &file.path,
+                format!("Track total {} doesn't match {} files in the directory", total, count)
+            );
+            // Only suggest a total that the track number fits in
+            let finding = if track <= count {
+                finding.with_fix(FrameChange {
+                    id: "TRCK".to_string(),
+                    old: text_value(&file.tag, "TRCK"),
+                    new: format!("{}/{}", track, count),
+                })
+            } else {
+                finding
+            };
+            findings.push(finding);
+        }
+    }
+}
+
+fn check_track_numbers(files: &[&LintFile], findings: &mut Vec<Finding>) {
+    let mut tracks: BTreeMap<(u32, u32), Vec<&LintFile>> = BTreeMap::new();
+    for file in files {
+        if let Some(track) = file.tag.track() {
+            let disc = file.tag.disc().unwrap_or(1);
+            tracks.entry((disc, track)).or_default().push(file);
+        }
+    }
+
+    for ((_, track), files) in tracks.iter() {
+        if files.len() > 1 {
+            for file in files {
+                findings.push(Finding::new(
+                    &file.path,
+                    format!("Track {} is used by {} files in this album", track, files.len())
+                ));
+            }
+        }
+    }
+
+    // Gaps are reported on the file that follows them
+    let mut previous: Option<(u32, u32)> = None;
+    for ((disc, track), files) in tracks.iter() {
+        let expected = match previous {
+            Some((prev_disc, prev_track)) if prev_disc == *disc => prev_track + 1,
+            _ => 1,
+        };
+        if *track > expected {
+            let missing = if *track - expected == 1 {
+                format!("{}", expected)
+            } else {
+                format!("{}-{}", expected, track - 1)
+            };
+            findings.push(Finding::new(
+                &files[0].path,
+                format!("Track numbers skip {} before track {}", missing, track)
+            ));
+        }
+        previous = Some((*disc, *track));
+    }
+}
+
+fn group_by<'a, K: Ord>(
+    files: impl IntoIterator<Item = &'a LintFile>,
+    key: impl Fn(&LintFile) -> K
+) -> BTreeMap<K, Vec<&'a LintFile>> {
+    let mut groups: BTreeMap<K, Vec<&LintFile>> = BTreeMap::new();
+    for file in files {
+        groups.entry(key(file)).or_default().push(file);
+    }
+
+    groups
+}
+
+fn count_values<T: Eq + std::hash::Hash>(
+    values: impl Iterator<Item = T>
+) -> HashMap<T, usize> {
+    let mut counts = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+
+    counts
+}
+
+fn most_common<T: Clone + Ord>(counts: &HashMap<T, usize>) -> (T, usize) {
+    counts
+        .iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+        .map(|(value, count)| (value.clone(), *count))
+        .unwrap()
+}
+
+fn version_name(version: Version) -> &'static str {
+    match version {
+        Version::Id3v22 => "ID3v2.2",
+        Version::Id3v23 => "ID3v2.3",
+        Version::Id3v24 => "ID3v2.4",
+    }
+}
+
+fn frame_name(id: &str) -> &'static str {
+    match id {
+        "TIT2" => "Title",
+        "TPE1" => "Artist",
+        "TPE2" => "Album artist",
+        "TALB" => "Album",
+        "TRCK" => "Track number",
+        "TYER" => "Year",
+        _ => "Frame",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, version: Version, frames: &[(&str, &str)]) -> LintFile {
+        let mut tag = Tag::with_version(version);
+        for (id, value) in frames {
+            tag.set_text(*id, *value);
+        }
+
+        LintFile { path: PathBuf::from(path), tag, version: Some(version) }
+    }
+
+    fn messages(findings: &[Finding], path: &str) -> Vec<String> {
+        findings
+            .iter()
+            .filter(|finding| finding.path == Path::new(path))
+            .map(|finding| finding.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn only_id3_files_have_their_versions_compared() {
+        let files = [
+            file("a/1.mp3", Version::Id3v23, &[]),
+            file("a/2.mp3", Version::Id3v23, &[]),
+            file("a/3.flac", Version::Id3v24, &[]),
+            file("a/4.mp3", Version::Id3v24, &[]),
+        ];
+        let findings = lint(&files);
+
+        let differs = |path| messages(&findings, path).iter().any(|message| message.starts_with("Tag version"));
+        assert!(!differs("a/3.flac"));
+        assert!(differs("a/4.mp3"));
+        assert!(!differs("a/1.mp3"));
+    }
+
+    #[test]
+    fn years_compare_across_tag_versions() {
+        let files = [
+            file("a/1.mp3", Version::Id3v23, &[("TYER", "1999")]),
+            file("a/2.mp3", Version::Id3v24, &[("TDRC", "1999-05-01")]),
+            file("a/3.flac", Version::Id3v24, &[("TDRC", "2001")]),
+        ];
+        let findings = lint(&files);
+
+        let year = |path| findings
+            .iter()
+            .filter(|finding| finding.path == Path::new(path) && finding.message.starts_with("Year"))
+            .collect::<Vec<&Finding>>();
+        assert!(year("a/1.mp3").is_empty());
+        assert!(year("a/2.mp3").is_empty());
+        let fix = year("a/3.flac")[0].fix.as_ref().unwrap();
+        assert_eq!(fix.old.as_deref(), Some("2001"));
+        assert_eq!(fix.new, "1999");
+    }
+
+    #[test]
+    fn album_level_frames_are_compared_per_directory() {
+        let files = [
+            file("a/1.mp3", Version::Id3v24, &[("TALB", "Low"), ("TPE2", "Bowie")]),
+            file("a/2.mp3", Version::Id3v24, &[("TALB", "Low"), ("TPE2", "Bowie")]),
+            file("a/3.mp3", Version::Id3v24, &[("TALB", "Low"), ("TPE2", "David Bowie")]),
+            file("b/1.mp3", Version::Id3v24, &[("TALB", "Heroes"), ("TPE2", "Bowie")]),
+        ];
+        let findings = lint(&files);
+
+        assert!(messages(&findings, "a/3.mp3")
+            .contains(&"Album artist 'David Bowie' differs from the rest of the directory".to_string()));
+        let fix = findings
+            .iter()
+            .find(|finding| finding.path == Path::new("a/3.mp3") && finding.fix.is_some())
+            .and_then(|finding| finding.fix.as_ref())
+            .unwrap();
+        assert_eq!((fix.id.as_str(), fix.new.as_str()), ("TPE2", "Bowie"));
+        assert!(!messages(&findings, "b/1.mp3").iter().any(|message| message.contains("differs")));
+    }
+
+    #[test]
+    fn track_numbers_are_checked_for_gaps_and_duplicates() {
+        let files = [
+            file("a/1.mp3", Version::Id3v24, &[("TALB", "Low"), ("TRCK", "1/4")]),
+            file("a/2.mp3", Version::Id3v24, &[("TALB", "Low"), ("TRCK", "1/4")]),
+            file("a/3.mp3", Version::Id3v24, &[("TALB", "Low"), ("TRCK", "4/4")]),
+            file("a/4.mp3", Version::Id3v24, &[("TALB", "Low")]),
+        ];
+        let findings = lint(&files);
+
+        assert!(messages(&findings, "a/1.mp3").contains(&"Track 1 is used by 2 files in this album".to_string()));
+        assert!(messages(&findings, "a/3.mp3").contains(&"Track numbers skip 2-3 before track 4".to_string()));
+        assert!(messages(&findings, "a/4.mp3").contains(&"Missing track number".to_string()));
+        assert!(!messages(&findings, "a/1.mp3").iter().any(|message| message.starts_with("Track total")));
+    }
+
+    #[test]
+    fn track_totals_are_only_fixed_when_the_track_fits() {
+        let files = [
+            file("a/1.mp3", Version::Id3v24, &[("TRCK", "1/3")]),
+            file("a/2.mp3", Version::Id3v24, &[("TRCK", "5/3")]),
+        ];
+        let findings = lint(&files);
+
+        let total = |path| findings
+            .iter()
+            .find(|finding| finding.path == Path::new(path) && finding.message.starts_with("Track total"))
+            .unwrap();
+        assert_eq!(total("a/1.mp3").message, "Track total 3 doesn't match 2 files in the directory");
+        assert_eq!(total("a/1.mp3").fix.as_ref().unwrap().new, "1/2");
+        assert!(total("a/2.mp3").fix.is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use tui::widgets::ListState;
 
 pub struct GenericList<T> {
@@ -34,10 +35,35 @@ impl<T: Clone> GenericList<T> {
     }
 
     pub fn get_selected(&self) -> Option<T> {
-        if let Some(i) = self.state.selected() {
-            Some(self.items[i].clone())
-        } else {
-            None
+        self.state.selected().map(|i| self.items[i].clone())
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.is_empty() {
+            let index = self.get_index().unwrap();
+            let index = cmp::min(index + 1, self.len() - 1);
+            self.select(Some(index));
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.is_empty() {
+            let index = self.get_index().unwrap();
+            let index = index.saturating_sub(1);
+            self.select(Some(index));
+        }
+    }
+
+    pub fn select_first(&mut self) {
+        if !self.is_empty() {
+            self.select(Some(0));
+        }
+    }
+
+    pub fn select_last(&mut self) {
+        if !self.is_empty() {
+            let index = self.len() - 1;
+            self.select(Some(index));
         }
     }
 }
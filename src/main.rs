@@ -31,9 +31,43 @@ use tui::{
 };
 
 mod app;
+mod ape;
+mod audiohash;
+mod backend;
+mod bpm;
+mod clipboard;
+mod config;
+mod container;
+mod decode;
+mod duplicates;
+mod edit;
 mod events;
+mod flac;
+mod health;
+mod input;
+mod length;
+mod library;
+mod lint;
 mod list;
+mod merge;
+mod mojibake;
+mod mp4;
+mod mpeg;
+mod normalize;
+mod ogg;
+mod preview;
+mod profiles;
+mod replace;
+mod replaygain;
+mod split;
+mod tags;
+mod templates;
+#[cfg(test)]
+mod testutil;
+mod transform;
 mod ui;
+mod vorbis;
+mod worker;
 
 use app::*;
 use events::*;
@@ -49,7 +83,7 @@ fn main() -> DynResult<()> {
     terminal.clear()?;
 
     let app = App::default()?;
-    let tick_rate = Duration::from_millis(1000);
+    let tick_rate = Duration::from_millis(250);
     let res = run_app(&mut terminal, app, tick_rate);
 
     terminal.show_cursor()?;
@@ -72,6 +106,7 @@ fn run_app<B: Backend>(
 
     loop {
         if app.quit {
+            let _ = app.library.save();
             break;
         }
 
@@ -0,0 +1,198 @@
+use id3::Tag;
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+};
+use unicode_normalization::{
+    char::is_combining_mark,
+    UnicodeNormalization,
+};
+
+use crate::list::*;
+use crate::preview::*;
+use crate::tags::*;
+
+// Different spellings of what is probably the same value
+pub struct Cluster {
+    pub spellings: Vec<(String, Vec<PathBuf>)>,
+    pub canonical: Option<usize>,
+}
+
+impl Cluster {
+    pub fn num_files(&self) -> usize {
+        self.spellings.iter().map(|(_, paths)| paths.len()).sum()
+    }
+}
+
+#[derive(Clone)]
+pub enum MergeLine {
+    Cluster(usize),
+    Spelling(usize, usize),
+}
+
+pub struct MergeView {
+    pub id: String,
+    pub clusters: Vec<Cluster>,
+    pub lines: GenericList<MergeLine>,
+}
+
+impl MergeView {
+    pub fn new(id: String, tags: &[(PathBuf, Tag)]) -> Self {
+        let clusters = find_clusters(&id, tags);
+
+        let mut lines = Vec::new();
+        for (i, cluster) in clusters.iter().enumerate() {
+            lines.push(MergeLine::Cluster(i));
+            for j in 0..cluster.spellings.len() {
+                lines.push(MergeLine::Spelling(i, j));
+            }
+        }
+
+        Self {
+            id,
+            clusters,
+            lines: GenericList::from(lines),
+        }
+    }
+
+    // Makes the highlighted spelling the canonical one for its cluster, or
+    // leaves the cluster alone if it already was
+    pub fn toggle_canonical(&mut self) {
+        if let Some(MergeLine::Spelling(i, j)) = self.lines.get_selected() {
+            let cluster = &mut self.clusters[i];
+            if cluster.canonical == Some(j) {
+                cluster.canonical = None;
+            } else {
+                cluster.canonical = Some(j);
+            }
+        }
+    }
+
+    pub fn diffs(&self) -> Vec<FileDiff> {
+        let mut diffs = Vec::new();
+        for cluster in self.clusters.iter() {
+            let canonical = match cluster.canonical {
+                Some(canonical) => &cluster.spellings[canonical].0,
+                None => continue,
+            };
+
+            for (spelling, paths) in cluster.spellings.iter() {
+                if spelling == canonical {
+                    continue;
+                }
+                for path in paths {
+                    diffs.push(FileDiff {
+                        path: path.clone(),
+                        changes: vec![FrameChange {
+                            id: self.id.clone(),
+                            old: Some(spelling.clone()),
+                            new: canonical.clone(),
+                        }],
+                        warning: None,
+                    });
+                }
+            }
+        }
+
+        diffs
+    }
+}
+
+fn find_clusters(id: &str, tags: &[(PathBuf, Tag)]) -> Vec<Cluster> {
+    let mut groups: BTreeMap<String, BTreeMap<String, Vec<PathBuf>>> = BTreeMap::new();
+    for (path, tag) in tags {
+        if let Some(value) = text_value(tag, id) {
+            let key = normalize_key(&value);
+            if key.is_empty() {
+                continue;
+            }
+            groups
+                .entry(key)
+                .or_default()
+                .entry(value)
+                .or_default()
+                .push(path.clone());
+        }
+    }
+
+    groups
+        .into_values()
+        .filter(|spellings| spellings.len() > 1)
+        .map(|spellings| {
+            let mut spellings = spellings.into_iter().collect::<Vec<(String, Vec<PathBuf>)>>();
+            spellings.sort_by(|(a, a_paths), (b, b_paths)| {
+                b_paths.len().cmp(&a_paths.len()).then(a.cmp(b))
+            });
+
+            // Most common first, but the user picks the canonical spelling
+            Cluster { spellings, canonical: None }
+        })
+        .collect()
+}
+
+// Folds case and diacritics, so "Beyoncé" and "beyonce" compare equal
+pub fn fold(value: &str) -> String {
+    value
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+// Folds case, diacritics, punctuation and a leading or trailing "The" so that
+// "The Beatles", "Beatles, The" and "the beatles" share a key
+pub fn normalize_key(value: &str) -> String {
+    let folded = fold(value)
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>();
+
+    let mut words = folded.split_whitespace().collect::<Vec<&str>>();
+    if words.len() > 1 && words[0] == "the" {
+        words.remove(0);
+    } else if words.len() > 1 && words[words.len() - 1] == "the" {
+        words.pop();
+    }
+
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+
+    fn artists(names: &[&str]) -> Vec<(PathBuf, Tag)> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let mut tag = Tag::new();
+                tag.set_artist(*name);
+                (PathBuf::from(format!("{}.mp3", i)), tag)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clusters_wait_for_a_canonical_choice() {
+        let tags = artists(&["The Beatles", "Beatles, The", "The Beatles", "Björk", "Bjork"]);
+        let mut view = MergeView::new("TPE1".to_string(), &tags);
+        assert_eq!(view.clusters.len(), 2);
+        assert!(view.clusters.iter().all(|cluster| cluster.canonical.is_none()));
+        assert!(view.diffs().is_empty());
+
+        // Choose "Bjork" for the Björk cluster only
+        let line = view.lines.items.iter().position(|line| match line {
+            MergeLine::Spelling(i, j) => view.clusters[*i].spellings[*j].0 == "Bjork",
+            _ => false,
+        });
+        view.lines.select(line);
+        view.toggle_canonical();
+
+        let diffs = view.diffs();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].changes[0].new, "Bjork");
+    }
+}
@@ -0,0 +1,415 @@
+use encoding_rs::{
+    Encoding,
+    BIG5,
+    EUC_JP,
+    EUC_KR,
+    GBK,
+    ISO_8859_2,
+    KOI8_R,
+    SHIFT_JIS,
+    UTF_8,
+    WINDOWS_1250,
+    WINDOWS_1251,
+    WINDOWS_1252,
+    WINDOWS_1253,
+    WINDOWS_1257,
+};
+use id3::Tag;
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use crate::input::*;
+use crate::list::*;
+use crate::preview::*;
+use crate::tags::*;
+
+// Legacy encodings whose bytes commonly end up stored as Latin-1 text, in
+// the order candidates with the same score are offered
+const ENCODINGS: [&Encoding; 12] = [
+    UTF_8,
+    WINDOWS_1251,
+    KOI8_R,
+    WINDOWS_1250,
+    ISO_8859_2,
+    WINDOWS_1253,
+    WINDOWS_1257,
+    SHIFT_JIS,
+    EUC_JP,
+    GBK,
+    BIG5,
+    EUC_KR,
+];
+
+// How much more plausible the best candidate must be than the stored text
+// for a value to count as likely mojibake
+const LIKELY_MARGIN: f64 = 0.75;
+const LIKELY_SCORE: f64 = 0.5;
+
+pub fn encoding_form() -> InputForm {
+    InputForm::new(
+        "Repair Text Encoding",
+        "Frames: IDs or * | Library: blank for the selection, or a directory to scan",
+        &[("Frames", "*"), ("Library", "")]
+    )
+}
+
+// The bytes a legacy encoder wrote, recovered from text that was decoded as
+// Latin-1 (or Windows-1252) along the way. None when the text has no
+// non-ASCII characters or couldn't have come from single bytes.
+fn legacy_bytes(text: &str) -> Option<Vec<u8>> {
+    let bytes = if text.chars().all(|c| (c as u32) <= 0xFF) {
+        text.chars().map(|c| c as u8).collect::<Vec<u8>>()
+    } else {
+        let (bytes, _, had_errors) = WINDOWS_1252.encode(text);
+        if had_errors {
+            return None;
+        }
+        bytes.to_vec()
+    };
+
+    if bytes.is_ascii() {
+        None
+    } else {
+        Some(bytes)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Thai,
+    // Han and kana together, since Japanese text mixes them
+    Cjk,
+    Hangul,
+    Other,
+}
+
+fn script(c: char) -> Script {
+    match c as u32 {
+        0x00..=0x24F | 0x1E00..=0x1EFF => Script::Latin,
+        0x370..=0x3FF => Script::Greek,
+        0x400..=0x52F => Script::Cyrillic,
+        0x590..=0x5FF => Script::Hebrew,
+        0x600..=0x6FF => Script::Arabic,
+        0xE00..=0xE7F => Script::Thai,
+        0x3040..=0x30FF | 0x31F0..=0x31FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xFF66..=0xFF9F => {
+            Script::Cjk
+        },
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+        _ => Script::Other,
+    }
+}
+
+// Punctuation that turns up in real titles: dashes, curly quotes, ellipses,
+// CJK and fullwidth punctuation and the katakana middle dot
+fn is_common_punctuation(c: char) -> bool {
+    matches!(c as u32, 0x2010..=0x205F | 0x3000..=0x303F | 0x30FB | 0xFF01..=0xFF65)
+}
+
+// Scores how much text looks like real writing rather than mojibake, from 1
+// (every non-ASCII character is a plausible letter) down to negative values.
+// Mojibake shows up as control characters and stray symbols, words made
+// almost entirely of accented Latin letters, words mixing scripts, and case
+// changing in the middle of a word.
+pub fn plausibility(text: &str) -> f64 {
+    let mut num_non_ascii = 0;
+    let mut good = 0.0;
+    let mut bad = 0.0;
+
+    for c in text.chars().filter(|c| !c.is_ascii()) {
+        num_non_ascii += 1;
+        if c.is_control() || c == '\u{FFFD}' || ('\u{E000}'..='\u{F8FF}').contains(&c) {
+            bad += 2.0;
+        } else if c.is_whitespace() || is_common_punctuation(c) {
+            good += 1.0;
+        } else if !c.is_alphanumeric() {
+            bad += 1.0;
+        }
+    }
+    if num_non_ascii == 0 {
+        return 1.0;
+    }
+
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let letters = word.chars().collect::<Vec<char>>();
+        let foreign = letters.iter().filter(|c| !c.is_ascii()).count();
+        if foreign == 0 {
+            continue;
+        }
+
+        let mut scripts = letters.iter().filter(|c| !c.is_ascii()).map(|c| script(*c));
+        let first = scripts.next().unwrap();
+        let has_ascii = letters.iter().any(|c| c.is_ascii_alphabetic());
+        let case_flips = letters
+            .windows(2)
+            .any(|pair| pair[0].is_lowercase() && pair[1].is_uppercase());
+
+        let plausible = if scripts.any(|other| other != first) || first == Script::Other {
+            false
+        } else {
+            match first {
+                // Accents are the exception in Latin script words
+                Script::Latin => letters.len() < 3 || foreign * 2 <= letters.len(),
+                // Other scripts rarely share a word with Latin letters
+                _ => !has_ascii,
+            }
+        };
+        if plausible && !case_flips {
+            good += foreign as f64;
+        } else {
+            bad += foreign as f64;
+        }
+    }
+
+    (good - bad) / num_non_ascii as f64
+}
+
+#[derive(Clone)]
+pub struct Candidate {
+    pub encoding: &'static Encoding,
+    pub text: String,
+    pub score: f64,
+}
+
+// Every legacy encoding the text's bytes decode cleanly under, most
+// plausible first
+pub fn candidates(text: &str) -> Vec<Candidate> {
+    let bytes = match legacy_bytes(text) {
+        Some(bytes) => bytes,
+        None => return Vec::new(),
+    };
+
+    let mut candidates = ENCODINGS
+        .iter()
+        .filter_map(|encoding| {
+            let decoded = encoding.decode_without_bom_handling_and_without_replacement(&bytes)?;
+            if decoded == text {
+                return None;
+            }
+            Some(Candidate {
+                encoding,
+                text: decoded.to_string(),
+                score: plausibility(&decoded),
+            })
+        })
+        .collect::<Vec<Candidate>>();
+    // A stable sort keeps the ENCODINGS order between equal scores
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    candidates
+}
+
+#[derive(Clone)]
+pub struct EncodingRow {
+    pub path: PathBuf,
+    pub key: String,
+    pub old: String,
+    pub candidates: Vec<Candidate>,
+    pub choice: usize,
+    pub likely: bool,
+    pub accepted: bool,
+}
+
+impl EncodingRow {
+    pub fn chosen(&self) -> &Candidate {
+        &self.candidates[self.choice]
+    }
+}
+
+// Frame values of the tag the filter accepts that could be re-read under a
+// legacy encoding. Comments and TXXX frames are included with "*".
+pub fn encoding_rows(path: &Path, tag: &Tag, filter: &FrameFilter) -> Vec<EncodingRow> {
+    frame_key_values(tag)
+        .into_iter()
+        .filter(|(key, _)| {
+            let id = key.split(':').next().unwrap_or_default();
+            match filter {
+                FrameFilter::AllText => id != "RVA2",
+                FrameFilter::Only(ids) => ids.iter().any(|other| other == id),
+            }
+        })
+        .filter_map(|(key, old)| {
+            let candidates = candidates(&old);
+            let best = candidates.first()?.score;
+            let likely = best >= LIKELY_SCORE && best - plausibility(&old) >= LIKELY_MARGIN;
+
+            Some(EncodingRow {
+                path: path.to_path_buf(),
+                key,
+                old,
+                candidates,
+                choice: 0,
+                likely,
+                accepted: likely,
+            })
+        })
+        .collect()
+}
+
+pub struct EncodingReview {
+    pub rows: GenericList<EncodingRow>,
+}
+
+impl EncodingReview {
+    pub fn new(rows: Vec<EncodingRow>) -> Self {
+        Self { rows: GenericList::from(rows) }
+    }
+
+    fn update_selected(&mut self, update: impl FnOnce(&mut EncodingRow)) {
+        if let Some(index) = self.rows.get_index() {
+            if let Some(row) = self.rows.items.get_mut(index) {
+                update(row);
+            }
+        }
+    }
+
+    pub fn toggle_accept(&mut self) {
+        self.update_selected(|row| row.accepted = !row.accepted);
+    }
+
+    pub fn next_candidate(&mut self) {
+        self.update_selected(|row| {
+            row.choice = (row.choice + 1) % row.candidates.len();
+            row.accepted = true;
+        });
+    }
+
+    pub fn prev_candidate(&mut self) {
+        self.update_selected(|row| {
+            row.choice = (row.choice + row.candidates.len() - 1) % row.candidates.len();
+            row.accepted = true;
+        });
+    }
+
+    // Uses the highlighted row's encoding for every row it can decode, since
+    // a whole album or library usually shares one legacy encoding
+    pub fn apply_choice_to_all(&mut self) {
+        let selected = self.rows.get_index().and_then(|index| self.rows.items.get(index));
+        let encoding = match selected {
+            Some(row) => row.chosen().encoding,
+            None => return,
+        };
+
+        for row in self.rows.items.iter_mut() {
+            if let Some(index) = row.candidates.iter().position(|other| other.encoding == encoding) {
+                row.choice = index;
+                row.accepted = true;
+            }
+        }
+    }
+
+    pub fn num_accepted(&self) -> usize {
+        self.rows.items.iter().filter(|row| row.accepted).count()
+    }
+
+    pub fn diffs(&self) -> Vec<FileDiff> {
+        let mut diffs: Vec<FileDiff> = Vec::new();
+        for row in self.rows.items.iter().filter(|row| row.accepted) {
+            let change = FrameChange {
+                id: row.key.clone(),
+                old: Some(row.old.clone()),
+                new: row.chosen().text.clone(),
+            };
+            match diffs.iter_mut().find(|diff| diff.path == row.path) {
+                Some(diff) => diff.changes.push(change),
+                None => diffs.push(FileDiff {
+                    path: row.path.clone(),
+                    changes: vec![change],
+                    warning: None,
+                }),
+            }
+        }
+
+        diffs
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+
+    // Text as it reads after its legacy bytes were taken for Windows-1252
+    fn mojibake(text: &str, encoding: &'static Encoding) -> String {
+        let (bytes, _, had_errors) = encoding.encode(text);
+        assert!(!had_errors);
+        WINDOWS_1252.decode_without_bom_handling(&bytes).0.to_string()
+    }
+
+    fn title_row(title: &str) -> Option<EncodingRow> {
+        let mut tag = Tag::new();
+        tag.set_title(title);
+        encoding_rows(Path::new("a.mp3"), &tag, &FrameFilter::AllText).into_iter().next()
+    }
+
+    #[test]
+    fn legacy_bytes_are_recovered_from_latin1_text() {
+        assert_eq!(legacy_bytes("Plain ASCII"), None);
+        assert_eq!(legacy_bytes("Ãœber"), Some(vec![0xC3, 0x9C, 0x62, 0x65, 0x72]));
+        // U+0081 has no Windows-1252 character and survives as a C1 control
+        assert_eq!(legacy_bytes("\u{81}"), Some(vec![0x81]));
+        // Characters no single byte encoding produced
+        assert_eq!(legacy_bytes("Кино"), None);
+    }
+
+    #[test]
+    fn mojibake_is_flagged_and_repaired() {
+        let fixtures = [
+            ("Кино — Группа крови", WINDOWS_1251),
+            ("Звезда по имени Солнце", KOI8_R),
+            ("千と千尋の神隠し", SHIFT_JIS),
+            ("月亮代表我的心", GBK),
+            ("Sigur Rós – Hoppípolla", UTF_8),
+        ];
+        for (title, encoding) in fixtures {
+            let garbled = mojibake(title, encoding);
+            let row = title_row(&garbled).unwrap();
+            assert!(row.likely, "{} wasn't flagged", garbled);
+            // Chinese bytes also decode as plausible Japanese, so the right
+            // text only has to be among the best candidates
+            let best = row.chosen().score;
+            assert!(row.candidates.iter().any(|candidate| candidate.text == title && candidate.score == best));
+            assert!(plausibility(&garbled) < plausibility(title));
+        }
+    }
+
+    #[test]
+    fn correctly_encoded_titles_are_not_flagged() {
+        let titles = [
+            "Björk – Jóga",
+            "Déjà vu",
+            "Motörhead",
+            "Ça plane pour moi",
+            "Straße",
+            "Crème brûlée",
+            "Ænima",
+            "Żywiec",
+            // Scripts mixed between words, not within them
+            "Кино feat. Björk",
+            "東京事変 – Tokyo",
+        ];
+        for title in titles {
+            let likely = title_row(title).is_some_and(|row| row.likely);
+            assert!(!likely, "{} was flagged", title);
+        }
+    }
+
+    #[test]
+    fn plausibility_penalises_mixed_scripts_and_symbols() {
+        assert_eq!(plausibility("Кино"), 1.0);
+        assert_eq!(plausibility("東京事変"), 1.0);
+        assert_eq!(plausibility("“Heroes” … live"), 1.0);
+        assert!(plausibility("Kинo") < 0.0);
+        assert!(plausibility("ÊÈÍÎ") < 0.0);
+        assert!(plausibility("Ã©tÃ©") < 0.0);
+        assert!(plausibility("caf\u{FFFD}") < 0.0);
+    }
+}
@@ -0,0 +1,793 @@
+use std::{
+    fs,
+    io::{
+        self,
+        Error,
+        ErrorKind,
+    },
+    path::Path,
+};
+
+use crate::backend::*;
+
+const ITUNES_MEAN: &str = "com.apple.iTunes";
+// Padding left for future edits when the moov atom has to grow
+const NEW_PADDING: usize = 2048;
+
+// Data atom type indicators
+const TYPE_UTF8: u32 = 1;
+const TYPE_JPEG: u32 = 13;
+const TYPE_PNG: u32 = 14;
+const TYPE_BMP: u32 = 27;
+const TYPE_INTEGER: u32 = 21;
+
+// iTunes-style items and the logical fields they are read as. tmpo and cpil
+// hold integers, the rest text.
+const ITEM_FIELDS: [(&[u8; 4], Field); 16] = [
+    (b"\xa9nam", Field::Title),
+    (b"\xa9ART", Field::Artist),
+    (b"\xa9alb", Field::Album),
+    (b"aART", Field::AlbumArtist),
+    (b"\xa9wrt", Field::Composer),
+    (b"\xa9gen", Field::Genre),
+    (b"\xa9day", Field::Date),
+    (b"\xa9too", Field::Encoder),
+    (b"\xa9grp", Field::Grouping),
+    (b"cprt", Field::Copyright),
+    (b"sonm", Field::TitleSort),
+    (b"soar", Field::ArtistSort),
+    (b"soal", Field::AlbumSort),
+    (b"soaa", Field::AlbumArtistSort),
+    (b"tmpo", Field::Bpm),
+    (b"cpil", Field::Compilation),
+];
+
+// Fields with no iTunes atom, kept in freeform items under the names other
+// taggers use for them
+const FREEFORM_FIELDS: [(&str, Field); 11] = [
+    ("ISRC", Field::Isrc),
+    ("ENCODEDBY", Field::EncodedBy),
+    ("LABEL", Field::Publisher),
+    ("CONDUCTOR", Field::Conductor),
+    ("REMIXER", Field::Remixer),
+    ("LYRICIST", Field::Lyricist),
+    ("SUBTITLE", Field::Subtitle),
+    ("LANGUAGE", Field::Language),
+    ("MOOD", Field::Mood),
+    ("ORIGINALDATE", Field::OriginalDate),
+    ("LENGTH", Field::Length),
+];
+
+// Items rime reads into fields, and so rewrites from them. Any other item
+// (and freeform items outside the iTunes namespace) is kept as it is.
+const MANAGED_ITEMS: [&[u8; 4]; 4] = [b"\xa9cmt", b"trkn", b"disk", b"covr"];
+
+#[derive(Clone, Copy)]
+struct Atom {
+    kind: [u8; 4],
+    start: usize,
+    header_len: usize,
+    end: usize,
+}
+
+impl Atom {
+    fn body<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.start + self.header_len..self.end]
+    }
+
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+// The atoms laid out one after another between start and end
+fn parse_atoms(data: &[u8], start: usize, end: usize) -> Option<Vec<Atom>> {
+    let mut atoms = Vec::new();
+    let mut pos = start;
+    while pos + 8 <= end {
+        let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap();
+        let (header_len, len) = match size {
+            0 => (8, end - pos),
+            1 => {
+                let size = u64::from_be_bytes(data.get(pos + 8..pos + 16)?.try_into().unwrap());
+                (16, size as usize)
+            },
+            size => (8, size),
+        };
+        if len < header_len || pos + len > end {
+            return None;
+        }
+        atoms.push(Atom { kind, start: pos, header_len, end: pos + len });
+        pos += len;
+    }
+
+    Some(atoms)
+}
+
+fn children(data: &[u8], atom: &Atom) -> Option<Vec<Atom>> {
+    // meta is a full box, with version and flags before its children
+    let skip = if &atom.kind == b"meta" { 4 } else { 0 };
+    parse_atoms(data, atom.start + atom.header_len + skip, atom.end)
+}
+
+fn find(atoms: &[Atom], kind: &[u8; 4]) -> Option<Atom> {
+    atoms.iter().find(|atom| &atom.kind == kind).copied()
+}
+
+fn encode_atom(kind: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    atom.extend(kind);
+    atom.extend(body);
+
+    atom
+}
+
+fn invalid_mp4() -> Error {
+    Error::new(ErrorKind::InvalidData, "Unsupported or invalid MP4 file")
+}
+
+// Top level atoms, then moov > udta > meta > ilst where each exists
+struct Layout {
+    top: Vec<Atom>,
+    moov: Atom,
+    udta: Option<Atom>,
+    meta: Option<Atom>,
+    ilst: Option<Atom>,
+}
+
+fn read_layout(data: &[u8]) -> Option<Layout> {
+    let top = parse_atoms(data, 0, data.len())?;
+    find(&top, b"ftyp")?;
+    let moov = find(&top, b"moov")?;
+    let udta = find(&children(data, &moov)?, b"udta");
+    let meta = udta.and_then(|udta| find(&children(data, &udta)?, b"meta"));
+    let ilst = meta.and_then(|meta| find(&children(data, &meta)?, b"ilst"));
+
+    Some(Layout { top, moov, udta, meta, ilst })
+}
+
+// The type indicator and payload of each data atom in an item
+fn item_data<'a>(data: &'a [u8], item: &Atom) -> Vec<(u32, &'a [u8])> {
+    children(data, item)
+        .unwrap_or_default()
+        .iter()
+        .filter(|atom| &atom.kind == b"data")
+        .map(|atom| atom.body(data))
+        .filter(|body| body.len() >= 8)
+        .map(|body| (u32::from_be_bytes(body[..4].try_into().unwrap()) & 0xFF_FFFF, &body[8..]))
+        .collect()
+}
+
+fn freeform_name(data: &[u8], item: &Atom) -> Option<(String, String)> {
+    let atoms = children(data, item)?;
+    let text = |kind: &[u8; 4]| {
+        let body = find(&atoms, kind)?.body(data);
+        body.get(4..).map(|text| String::from_utf8_lossy(text).to_string())
+    };
+
+    Some((text(b"mean")?, text(b"name")?))
+}
+
+fn integer(payload: &[u8]) -> Option<u64> {
+    if payload.is_empty() || payload.len() > 8 {
+        return None;
+    }
+
+    Some(payload.iter().fold(0, |value, byte| (value << 8) | *byte as u64))
+}
+
+// A number and total, either of which may be zero for unknown
+fn number_pair(payload: &[u8]) -> Option<(u16, u16)> {
+    let number = u16::from_be_bytes(payload.get(2..4)?.try_into().unwrap());
+    let total = payload
+        .get(4..6)
+        .map(|total| u16::from_be_bytes(total.try_into().unwrap()))
+        .unwrap_or(0);
+
+    Some((number, total))
+}
+
+// MP4 covers have no picture type or description, so all of them are read as
+// front covers
+fn covers(data: &[u8], item: &Atom) -> Vec<Cover> {
+    item_data(data, item)
+        .iter()
+        .map(|(kind, payload)| {
+            let mime_type = match *kind {
+                TYPE_PNG => "image/png",
+                TYPE_BMP => "image/bmp",
+                _ => "image/jpeg",
+            };
+            Cover {
+                mime_type: mime_type.to_string(),
+                picture_type: 3,
+                description: String::new(),
+                data: payload.to_vec(),
+            }
+        })
+        .collect()
+}
+
+fn read_mp4(path: &Path) -> io::Result<Option<TagModel>> {
+    let data = fs::read(path)?;
+    let layout = read_layout(&data).ok_or_else(invalid_mp4)?;
+    let ilst = match layout.ilst {
+        Some(ilst) => ilst,
+        None => return Ok(None),
+    };
+    let items = children(&data, &ilst).ok_or_else(invalid_mp4)?;
+
+    let mut model = TagModel::new(TagFormat::Mp4);
+    for item in items.iter() {
+        if !is_managed(&data, item) {
+            model.native.push(NativeFrame::Mp4(data[item.start..item.end].to_vec()));
+            continue;
+        }
+
+        let values = item_data(&data, item);
+        let texts = values
+            .iter()
+            .filter(|(kind, _)| *kind == TYPE_UTF8)
+            .map(|(_, payload)| String::from_utf8_lossy(payload).to_string());
+
+        match &item.kind {
+            b"----" => {
+                if let Some((_, name)) = freeform_name(&data, item) {
+                    let field = FREEFORM_FIELDS
+                        .iter()
+                        .find(|(other, _)| other.eq_ignore_ascii_case(&name))
+                        .map(|(_, field)| field.clone())
+                        .unwrap_or(Field::Custom(name));
+                    for text in texts {
+                        model.add(field.clone(), text);
+                    }
+                }
+            },
+            b"\xa9cmt" => {
+                for text in texts {
+                    model.add(Field::Comment, text);
+                }
+            },
+            b"trkn" | b"disk" => {
+                let (number, total) = if &item.kind == b"trkn" {
+                    (Field::Track, Field::TrackTotal)
+                } else {
+                    (Field::Disc, Field::DiscTotal)
+                };
+                let pair = values.first().and_then(|(_, payload)| number_pair(payload));
+                if let Some((value, count)) = pair {
+                    if value != 0 {
+                        model.add(number, value.to_string());
+                    }
+                    if count != 0 {
+                        model.add(total, count.to_string());
+                    }
+                }
+            },
+            b"covr" => model.pictures.extend(covers(&data, item)),
+            b"tmpo" | b"cpil" => {
+                let field = if &item.kind == b"tmpo" { Field::Bpm } else { Field::Compilation };
+                if let Some(value) = values.first().and_then(|(_, payload)| integer(payload)) {
+                    model.add(field, value.to_string());
+                }
+            },
+            kind => {
+                if let Some((_, field)) = ITEM_FIELDS.iter().find(|(item, _)| *item == kind) {
+                    for text in texts {
+                        model.add(field.clone(), text);
+                    }
+                }
+            },
+        }
+    }
+
+    Ok(Some(model))
+}
+
+fn data_atom(kind: u32, payload: &[u8]) -> Vec<u8> {
+    let mut body = kind.to_be_bytes().to_vec();
+    body.extend([0; 4]);
+    body.extend(payload);
+
+    encode_atom(b"data", &body)
+}
+
+fn text_item(kind: &[u8], values: &[String]) -> Vec<u8> {
+    let body = values
+        .iter()
+        .flat_map(|value| data_atom(TYPE_UTF8, value.as_bytes()))
+        .collect::<Vec<u8>>();
+
+    encode_atom(kind, &body)
+}
+
+fn freeform_item(name: &str, values: &[String]) -> Vec<u8> {
+    let mut mean = vec![0; 4];
+    mean.extend(ITUNES_MEAN.as_bytes());
+    let mut name_body = vec![0; 4];
+    name_body.extend(name.as_bytes());
+
+    let mut body = encode_atom(b"mean", &mean);
+    body.extend(encode_atom(b"name", &name_body));
+    for value in values {
+        body.extend(data_atom(TYPE_UTF8, value.as_bytes()));
+    }
+
+    encode_atom(b"----", &body)
+}
+
+fn number_pair_item(kind: &[u8], number: Option<&str>, total: Option<&str>, len: usize) -> Option<Vec<u8>> {
+    let parse = |value: Option<&str>| value.and_then(|value| value.trim().parse::<u16>().ok()).unwrap_or(0);
+    let (number, total) = (parse(number), parse(total));
+    if number == 0 && total == 0 {
+        return None;
+    }
+
+    let mut payload = vec![0; len];
+    payload[2..4].copy_from_slice(&number.to_be_bytes());
+    payload[4..6].copy_from_slice(&total.to_be_bytes());
+
+    Some(encode_atom(kind, &data_atom(0, &payload)))
+}
+
+// The ilst items for the fields and pictures of a model. An existing covr
+// item is used as it is, when given.
+fn model_items(model: &TagModel, covr: Option<Vec<u8>>) -> Vec<Vec<u8>> {
+    let mut items = Vec::new();
+    items.extend(number_pair_item(
+        b"trkn",
+        model.first(&Field::Track),
+        model.first(&Field::TrackTotal),
+        8
+    ));
+    items.extend(number_pair_item(
+        b"disk",
+        model.first(&Field::Disc),
+        model.first(&Field::DiscTotal),
+        6
+    ));
+
+    for (field, values) in model.fields.iter() {
+        let first = values.first().map(|value| value.trim()).unwrap_or_default();
+        match field {
+            Field::Bpm => {
+                if let Ok(bpm) = first.parse::<f64>() {
+                    let bpm = bpm.round().clamp(0.0, u16::MAX as f64) as u16;
+                    items.push(encode_atom(b"tmpo", &data_atom(TYPE_INTEGER, &bpm.to_be_bytes())));
+                }
+            },
+            Field::Compilation => {
+                let compilation = (first == "1") as u8;
+                items.push(encode_atom(b"cpil", &data_atom(TYPE_INTEGER, &[compilation])));
+            },
+            // The recording time takes the place of the year when a tag has both
+            Field::Date if model.values(&Field::RecordingDate).is_some() => {},
+            Field::RecordingDate => items.push(text_item(b"\xa9day", values)),
+            Field::Comment => items.push(text_item(b"\xa9cmt", values)),
+            Field::Custom(description) => items.push(freeform_item(description, values)),
+            field => {
+                if let Some((kind, _)) = ITEM_FIELDS.iter().find(|(_, other)| other == field) {
+                    items.push(text_item(*kind, values));
+                } else if let Some((name, _)) = FREEFORM_FIELDS.iter().find(|(_, other)| other == field) {
+                    items.push(freeform_item(name, values));
+                }
+            },
+        }
+    }
+
+    let covers = model.pictures
+        .iter()
+        .flat_map(|picture| {
+            let kind = match picture.mime_type.as_str() {
+                "image/png" => TYPE_PNG,
+                "image/bmp" => TYPE_BMP,
+                _ => TYPE_JPEG,
+            };
+            data_atom(kind, &picture.data)
+        })
+        .collect::<Vec<u8>>();
+    match covr {
+        Some(covr) => items.push(covr),
+        None if !covers.is_empty() => items.push(encode_atom(b"covr", &covers)),
+        None => {},
+    }
+
+    items
+}
+
+fn is_managed(data: &[u8], item: &Atom) -> bool {
+    if &item.kind == b"----" {
+        return freeform_name(data, item).map(|(mean, _)| mean == ITUNES_MEAN).unwrap_or(false);
+    }
+
+    MANAGED_ITEMS.contains(&&item.kind)
+        || ITEM_FIELDS.iter().any(|(kind, _)| **kind == item.kind)
+}
+
+// Replaces the child of the given kind in a container's body (or appends it),
+// copying the other children as they are
+fn replace_child(data: &[u8], children: &[Atom], kind: &[u8; 4], new: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    let mut replaced = false;
+    for child in children {
+        if &child.kind == kind && !replaced {
+            body.extend(new);
+            replaced = true;
+        } else {
+            body.extend(&data[child.start..child.end]);
+        }
+    }
+    if !replaced {
+        body.extend(new);
+    }
+
+    body
+}
+
+// Writes the model into moov > udta > meta > ilst. Size changes are absorbed by
+// a free atom inside meta or straight after moov where there is room;
+// otherwise everything after moov moves and the chunk offsets in stco/co64
+// are updated to match.
+fn write_mp4(model: &TagModel, path: &Path) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let layout = read_layout(&data).ok_or_else(invalid_mp4)?;
+
+    // Unmanaged items come from the model when it was read from an MP4 file,
+    // and otherwise are kept from the file being written
+    let mut unmanaged = Vec::new();
+    if model.format == TagFormat::Mp4 {
+        for native in model.native.iter() {
+            if let NativeFrame::Mp4(item) = native {
+                unmanaged.push(item.clone());
+            }
+        }
+    } else if let Some(ilst) = layout.ilst {
+        for item in children(&data, &ilst).ok_or_else(invalid_mp4)? {
+            if !is_managed(&data, &item) {
+                unmanaged.push(data[item.start..item.end].to_vec());
+            }
+        }
+    }
+
+    // Unchanged covers keep their original item, type indicators included
+    let covr = layout.ilst
+        .and_then(|ilst| find(&children(&data, &ilst)?, b"covr"))
+        .filter(|covr| !model.pictures.is_empty() && covers(&data, covr) == model.pictures)
+        .map(|covr| data[covr.start..covr.end].to_vec());
+
+    let mut items = model_items(model, covr).concat();
+    for item in unmanaged {
+        // A numeric genre would contradict a new text genre
+        let replaced_genre = item.get(4..8) == Some(b"gnre") && model.values(&Field::Genre).is_some();
+        if !replaced_genre {
+            items.extend(item);
+        }
+    }
+    let ilst = encode_atom(b"ilst", &items);
+    let growth = ilst.len() as isize - layout.ilst.map(|ilst| ilst.len()).unwrap_or(0) as isize;
+
+    let meta = match layout.meta {
+        Some(meta) => {
+            let meta_children = children(&data, &meta).ok_or_else(invalid_mp4)?;
+            // Let the padding inside meta take up the change where it can
+            let free_len = meta_children
+                .iter()
+                .find(|child| &child.kind == b"free")
+                .map(|free| free.len() as isize - growth)
+                .filter(|len| *len == 0 || *len >= 8);
+
+            let mut body = data
+                .get(meta.start + meta.header_len..meta.start + meta.header_len + 4)
+                .ok_or_else(invalid_mp4)?
+                .to_vec();
+            let mut free_done = false;
+            for child in meta_children.iter() {
+                match (&child.kind, free_len) {
+                    (b"free", Some(len)) if !free_done => {
+                        if len > 0 {
+                            body.extend(encode_atom(b"free", &vec![0; len as usize - 8]));
+                        }
+                        free_done = true;
+                    },
+                    (b"ilst", _) => body.extend(&ilst),
+                    _ => body.extend(&data[child.start..child.end]),
+                }
+            }
+            if layout.ilst.is_none() {
+                body.extend(&ilst);
+            }
+            encode_atom(b"meta", &body)
+        },
+        None => {
+            let mut hdlr = vec![0; 8];
+            hdlr.extend(b"mdirappl");
+            hdlr.extend([0; 9]);
+
+            let mut body = vec![0; 4];
+            body.extend(encode_atom(b"hdlr", &hdlr));
+            body.extend(ilst);
+            encode_atom(b"meta", &body)
+        },
+    };
+
+    let udta = match layout.udta {
+        Some(udta) => {
+            let udta_children = children(&data, &udta).ok_or_else(invalid_mp4)?;
+            encode_atom(b"udta", &replace_child(&data, &udta_children, b"meta", &meta))
+        },
+        None => encode_atom(b"udta", &meta),
+    };
+    let moov_children = children(&data, &layout.moov).ok_or_else(invalid_mp4)?;
+    let mut moov = encode_atom(b"moov", &replace_child(&data, &moov_children, b"udta", &udta));
+
+    let delta = moov.len() as isize - layout.moov.len() as isize;
+    let moov_index = layout.top.iter().position(|atom| atom.start == layout.moov.start).unwrap();
+    let is_last = moov_index + 1 == layout.top.len();
+    let mut rest = layout.moov.end;
+    let mut padding = 0;
+    // Nothing needs to move when moov ends the file
+    if delta != 0 && !is_last {
+        let next_free = layout.top
+            .get(moov_index + 1)
+            .filter(|atom| &atom.kind == b"free" && atom.header_len == 8);
+        let free_len = next_free.map(|free| free.len() as isize).unwrap_or(0) - delta;
+        if free_len == 0 || free_len >= 8 {
+            // The following free atom (or a new one) fills the difference
+            rest += next_free.map(|free| free.len()).unwrap_or(0);
+            padding = free_len as usize;
+        } else {
+            if layout.top.iter().any(|atom| &atom.kind == b"moof") {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "Not enough padding to grow a fragmented MP4 file"
+                ));
+            }
+            padding = NEW_PADDING;
+            let shift = delta + NEW_PADDING as isize;
+            let root = parse_atoms(&moov, 0, moov.len()).ok_or_else(invalid_mp4)?;
+            shift_chunk_offsets(&mut moov, &root, layout.moov.end, shift)?;
+        }
+    }
+
+    let mut contents = data[..layout.moov.start].to_vec();
+    contents.extend(moov);
+    if padding > 0 {
+        contents.extend(encode_atom(b"free", &vec![0; padding - 8]));
+    }
+    contents.extend(&data[rest..]);
+    fs::write(path, contents)?;
+
+    Ok(())
+}
+
+// Moves every chunk offset that points past the old end of moov
+fn shift_chunk_offsets(
+    moov: &mut [u8],
+    atoms: &[Atom],
+    moov_end: usize,
+    shift: isize
+) -> io::Result<()> {
+    for atom in atoms {
+        match &atom.kind {
+            b"moov" | b"trak" | b"mdia" | b"minf" | b"stbl" => {
+                let inner = children(moov, atom).ok_or_else(invalid_mp4)?;
+                shift_chunk_offsets(moov, &inner, moov_end, shift)?;
+            },
+            b"stco" | b"co64" => {
+                let width = if &atom.kind == b"stco" { 4 } else { 8 };
+                let body = atom.start + atom.header_len;
+                let count = moov.get(body + 4..body + 8).ok_or_else(invalid_mp4)?;
+                let count = u32::from_be_bytes(count.try_into().unwrap()) as usize;
+                // The table has to fit in the atom, or other atoms would be
+                // rewritten as offsets
+                count
+                    .checked_mul(width)
+                    .filter(|len| body + 8 + len <= atom.end)
+                    .ok_or_else(invalid_mp4)?;
+                for i in 0..count {
+                    let pos = body + 8 + i * width;
+                    let entry = moov.get_mut(pos..pos + width).ok_or_else(invalid_mp4)?;
+                    let offset = entry.iter().fold(0u64, |value, byte| (value << 8) | *byte as u64);
+                    if offset < moov_end as u64 {
+                        continue;
+                    }
+
+                    let shifted = (offset as i64 + shift as i64) as u64;
+                    if width == 4 {
+                        let shifted = u32::try_from(shifted).map_err(|_| Error::new(
+                            ErrorKind::Unsupported,
+                            "Chunk offset no longer fits in stco"
+                        ))?;
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    } else {
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Mp4Backend;
+
+impl TagBackend for Mp4Backend {
+    fn read(&self, path: &Path) -> io::Result<Option<TagModel>> {
+        read_mp4(path)
+    }
+
+    fn write(&self, model: &TagModel, path: &Path) -> io::Result<()> {
+        write_mp4(model, path)
+    }
+}
+
+// The contents of every mdat atom, which writing tags never touches
+pub fn mp4_audio(data: &[u8]) -> Option<Vec<u8>> {
+    let top = parse_atoms(data, 0, data.len())?;
+    let audio = top
+        .iter()
+        .filter(|atom| &atom.kind == b"mdat")
+        .flat_map(|atom| atom.body(data).iter().copied())
+        .collect();
+
+    Some(audio)
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+    use crate::testutil::*;
+
+    const AUDIO: &[u8] = b"not really aac frames";
+
+    fn covr() -> Vec<u8> {
+        let mut body = data_atom(TYPE_JPEG, b"first");
+        body.extend(data_atom(TYPE_PNG, b"second"));
+        body.extend(data_atom(TYPE_BMP, b"third"));
+
+        encode_atom(b"covr", &body)
+    }
+
+    // ftyp, then moov with one chunk offset and an ilst, then mdat. A free
+    // atom of the given size follows moov when it isn't zero.
+    fn sample_mp4(free: usize) -> Vec<u8> {
+        let ftyp = encode_atom(b"ftyp", b"M4A \0\0\0\0");
+        let build = |offset: u32| {
+            let mut stco = vec![0; 4];
+            stco.extend(1u32.to_be_bytes());
+            stco.extend(offset.to_be_bytes());
+            let stbl = encode_atom(b"stbl", &encode_atom(b"stco", &stco));
+            let trak = encode_atom(b"trak", &encode_atom(b"mdia", &encode_atom(b"minf", &stbl)));
+
+            let mut ilst = text_item(b"\xa9nam", &["Old".to_string()]);
+            ilst.extend(covr());
+            let mut meta = vec![0; 4];
+            meta.extend(encode_atom(b"ilst", &ilst));
+            let udta = encode_atom(b"udta", &encode_atom(b"meta", &meta));
+
+            let mut moov = trak;
+            moov.extend(udta);
+            encode_atom(b"moov", &moov)
+        };
+        let free = if free > 0 { encode_atom(b"free", &vec![0; free - 8]) } else { Vec::new() };
+        let moov_len = build(0).len();
+        let offset = ftyp.len() + moov_len + free.len() + 8;
+
+        let mut data = ftyp;
+        data.extend(build(offset as u32));
+        data.extend(free);
+        data.extend(encode_atom(b"mdat", AUDIO));
+
+        data
+    }
+
+    // Where the single stco entry points
+    fn chunk_offset(data: &[u8]) -> usize {
+        let pos = data.windows(4).position(|window| window == b"stco").unwrap();
+        u32::from_be_bytes(data[pos + 12..pos + 16].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn unchanged_covers_keep_their_item() {
+        let path = scratch_file("track.m4a", &sample_mp4(0));
+        let mut tag = read_tag(&path).unwrap();
+        assert_eq!(tag.pictures().count(), 3);
+
+        tag.set_title("New");
+        write_tag(&tag, &path).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let model = read_mp4(&path).unwrap().unwrap();
+        assert_eq!(model.first(&Field::Title), Some("New"));
+        assert_eq!(model.pictures.len(), 3);
+        assert!(data.windows(covr().len()).any(|window| window == covr()));
+    }
+
+    #[test]
+    fn new_covers_are_all_written() {
+        let path = scratch_file("track.m4a", &sample_mp4(0));
+        let mut model = read_mp4(&path).unwrap().unwrap();
+        model.pictures.remove(0);
+        write_mp4(&model, &path).unwrap();
+
+        let pictures = read_mp4(&path).unwrap().unwrap().pictures;
+        assert_eq!(pictures, model.pictures);
+        assert_eq!(pictures[1].mime_type, "image/bmp");
+    }
+
+    #[test]
+    fn growing_moov_shifts_chunk_offsets() {
+        let path = scratch_file("track.m4a", &sample_mp4(0));
+        let mut model = read_mp4(&path).unwrap().unwrap();
+        model.fields = vec![(Field::Title, vec!["A much longer title than before".to_string()])];
+        write_mp4(&model, &path).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let offset = chunk_offset(&data);
+        assert_eq!(&data[offset..offset + AUDIO.len()], AUDIO);
+        assert_eq!(mp4_audio(&data).unwrap(), AUDIO);
+    }
+
+    #[test]
+    fn padding_after_moov_absorbs_growth() {
+        let original = sample_mp4(64);
+        let path = scratch_file("track.m4a", &original);
+        let mut model = read_mp4(&path).unwrap().unwrap();
+        model.fields = vec![(Field::Title, vec!["Longer title".to_string()])];
+        write_mp4(&model, &path).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), original.len());
+        assert_eq!(chunk_offset(&data), chunk_offset(&original));
+        assert!(data.ends_with(AUDIO));
+    }
+
+    #[test]
+    fn fields_without_an_atom_become_freeform_items() {
+        let path = scratch_file("track.m4a", &sample_mp4(0));
+        let mut model = read_mp4(&path).unwrap().unwrap();
+        model.add(Field::Publisher, "Label".to_string());
+        model.add(Field::Isrc, "GBAYE0000001".to_string());
+        model.add(Field::Custom("SOURCE".to_string()), "Vinyl".to_string());
+        write_mp4(&model, &path).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        assert!(data.windows(5).any(|window| window == b"LABEL"));
+        let read = read_mp4(&path).unwrap().unwrap();
+        assert_eq!(read.first(&Field::Publisher), Some("Label"));
+        assert_eq!(read.first(&Field::Isrc), Some("GBAYE0000001"));
+        assert_eq!(read.first(&Field::Custom("SOURCE".to_string())), Some("Vinyl"));
+    }
+
+    #[test]
+    fn damaged_chunk_offset_tables_are_rejected() {
+        let mut data = sample_mp4(0);
+        let pos = data.windows(4).position(|window| window == b"stco").unwrap();
+        data[pos + 8..pos + 12].copy_from_slice(&2u32.to_be_bytes());
+        let path = scratch_file("track.m4a", &data);
+
+        let mut model = read_mp4(&path).unwrap().unwrap();
+        model.fields = vec![(Field::Title, vec!["A much longer title than before".to_string()])];
+        assert_eq!(write_mp4(&model, &path).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), data);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let data = sample_mp4(0);
+        let path = scratch_file("track.m4a", &data[..data.len() - 4]);
+        assert_eq!(read_mp4(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(write_mp4(&TagModel::new(TagFormat::Mp4), &path).is_err());
+
+        // An atom claiming to be smaller than its own header
+        let mut data = data;
+        data[3] = 4;
+        let path = scratch_file("track.m4a", &data);
+        assert_eq!(read_mp4(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
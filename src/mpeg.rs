@@ -0,0 +1,619 @@
+use std::{
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::container::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MpegVersion {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layer {
+    Layer1,
+    Layer2,
+    Layer3,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChannelMode {
+    Stereo,
+    JointStereo,
+    DualChannel,
+    Mono,
+}
+
+#[derive(Clone, Copy)]
+pub struct FrameHeader {
+    pub version: MpegVersion,
+    pub layer: Layer,
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub padding: bool,
+    pub channel_mode: ChannelMode,
+}
+
+const BITRATES_V1: [[u32; 15]; 3] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+];
+
+const BITRATES_V2: [[u32; 15]; 3] = [
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+impl FrameHeader {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+
+        let version = match (bytes[1] >> 3) & 0x03 {
+            0 => MpegVersion::Mpeg25,
+            2 => MpegVersion::Mpeg2,
+            3 => MpegVersion::Mpeg1,
+            _ => return None,
+        };
+        let layer = match (bytes[1] >> 1) & 0x03 {
+            1 => Layer::Layer3,
+            2 => Layer::Layer2,
+            3 => Layer::Layer1,
+            _ => return None,
+        };
+
+        // Free format (0) and the reserved index (15) aren't supported
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 {
+            return None;
+        }
+        let layer_index = match layer {
+            Layer::Layer1 => 0,
+            Layer::Layer2 => 1,
+            Layer::Layer3 => 2,
+        };
+        let bitrate = match version {
+            MpegVersion::Mpeg1 => BITRATES_V1[layer_index][bitrate_index],
+            _ => BITRATES_V2[layer_index][bitrate_index],
+        };
+
+        let sample_rate = match ((bytes[2] >> 2) & 0x03, version) {
+            (0, MpegVersion::Mpeg1) => 44100,
+            (1, MpegVersion::Mpeg1) => 48000,
+            (2, MpegVersion::Mpeg1) => 32000,
+            (0, MpegVersion::Mpeg2) => 22050,
+            (1, MpegVersion::Mpeg2) => 24000,
+            (2, MpegVersion::Mpeg2) => 16000,
+            (0, MpegVersion::Mpeg25) => 11025,
+            (1, MpegVersion::Mpeg25) => 12000,
+            (2, MpegVersion::Mpeg25) => 8000,
+            _ => return None,
+        };
+
+        let channel_mode = match bytes[3] >> 6 {
+            0 => ChannelMode::Stereo,
+            1 => ChannelMode::JointStereo,
+            2 => ChannelMode::DualChannel,
+            _ => ChannelMode::Mono,
+        };
+
+        Some(Self {
+            version,
+            layer,
+            bitrate,
+            sample_rate,
+            padding: (bytes[2] >> 1) & 0x01 == 1,
+            channel_mode,
+        })
+    }
+
+    pub fn samples(&self) -> u32 {
+        match (self.layer, self.version) {
+            (Layer::Layer1, _) => 384,
+            (Layer::Layer2, _) => 1152,
+            (Layer::Layer3, MpegVersion::Mpeg1) => 1152,
+            (Layer::Layer3, _) => 576,
+        }
+    }
+
+    // Size of the layer 3 side information that follows the header
+    pub fn side_info_len(&self) -> usize {
+        match (self.version, self.channel_mode) {
+            (MpegVersion::Mpeg1, ChannelMode::Mono) => 17,
+            (MpegVersion::Mpeg1, _) => 32,
+            (_, ChannelMode::Mono) => 9,
+            (_, _) => 17,
+        }
+    }
+
+    pub fn frame_len(&self) -> usize {
+        let padding = self.padding as u32;
+        let len = match self.layer {
+            Layer::Layer1 => (12 * self.bitrate * 1000 / self.sample_rate + padding) * 4,
+            _ => self.samples() / 8 * self.bitrate * 1000 / self.sample_rate + padding,
+        };
+
+        len as usize
+    }
+}
+
+// Where the MPEG audio lives in a file, between any leading ID3v2 tag and
+// any trailing APE or ID3v1 tags
+pub fn audio_range(data: &[u8]) -> (usize, usize) {
+    // Some files carry several ID3v2 tags back to back
+    let start = leading_id3v2_tags(data)
+        .last()
+        .map(|(offset, len)| offset + len)
+        .unwrap_or(0);
+
+    let mut end = data.len();
+    if end >= start + 128 && &data[end - 128..end - 125] == b"TAG" {
+        end -= 128;
+    }
+    if end >= start + 32 && &data[end - 32..end - 24] == b"APETAGEX" {
+        let footer = &data[end - 32..end];
+        let size = u32::from_le_bytes(footer[12..16].try_into().unwrap()) as usize;
+        let has_header = footer[23] & 0x80 != 0;
+        let total = size + if has_header { 32 } else { 0 };
+        end = end.saturating_sub(total).max(start);
+    }
+
+    (start.min(end), end)
+}
+
+// Finds the first frame header at or after pos that is followed by another
+// valid header, which rules out most false syncs inside junk data
+pub fn find_frame(data: &[u8], mut pos: usize, end: usize) -> Option<(usize, FrameHeader)> {
+    while pos + 4 <= end {
+        if let Some(header) = FrameHeader::parse(&data[pos..end]) {
+            let next = pos + header.frame_len();
+            if next == end || (next + 4 <= end && FrameHeader::parse(&data[next..end]).is_some()) {
+                return Some((pos, header));
+            }
+        }
+        pos += 1;
+    }
+
+    None
+}
+
+// The result of walking every frame in the audio range
+pub struct FrameScan {
+    pub frames: Vec<(usize, FrameHeader)>,
+    // Byte ranges that had to be skipped to find the next frame
+    pub junk: Vec<(usize, usize)>,
+    // Offset, expected length and actual length of a cut off last frame
+    pub truncated: Option<(usize, usize, usize)>,
+}
+
+// Walks every frame in the audio range, resyncing past any junk between
+// frames
+pub fn scan_frames(data: &[u8]) -> FrameScan {
+    let (start, end) = audio_range(data);
+    let mut scan = FrameScan { frames: Vec::new(), junk: Vec::new(), truncated: None };
+
+    let mut pos = match find_frame(data, start, end) {
+        Some((pos, _)) => pos,
+        None => return scan,
+    };
+    if pos > start {
+        scan.junk.push((start, pos));
+    }
+
+    while pos < end {
+        let header = FrameHeader::parse(&data[pos..end]);
+        if let Some(header) = header {
+            if pos + header.frame_len() <= end {
+                scan.frames.push((pos, header));
+                pos += header.frame_len();
+                continue;
+            }
+        }
+
+        match find_frame(data, pos + 1, end) {
+            Some((next, _)) => {
+                scan.junk.push((pos, next));
+                pos = next;
+            },
+            None => {
+                match header {
+                    Some(header) => scan.truncated = Some((pos, header.frame_len(), end - pos)),
+                    None => scan.junk.push((pos, end)),
+                }
+                break;
+            },
+        }
+    }
+
+    scan
+}
+
+pub fn walk_frames(data: &[u8]) -> Vec<(usize, FrameHeader)> {
+    scan_frames(data).frames
+}
+
+// Offset and length of each ID3v2 tag at the start of the file
+pub fn leading_id3v2_tags(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut tags = Vec::new();
+    let mut start = 0;
+    while data.len() >= start + 10 && &data[start..start + 3] == b"ID3" {
+        let size = synchsafe(&data[start + 6..start + 10]) as usize;
+        let footer = if data[start + 5] & 0x10 != 0 { 10 } else { 0 };
+        tags.push((start, 10 + size + footer));
+        start += 10 + size + footer;
+    }
+
+    tags
+}
+
+// Offsets of anything that looks like an ID3v2 header within the range
+pub fn find_id3v2_headers(data: &[u8], start: usize, end: usize) -> Vec<usize> {
+    let end = end.min(data.len());
+    (start..end.saturating_sub(9))
+        .filter(|pos| {
+            let bytes = &data[*pos..*pos + 10];
+            &bytes[..3] == b"ID3"
+                && (2..=4).contains(&bytes[3])
+                && bytes[4] != 0xFF
+                && bytes[6..10].iter().all(|byte| byte & 0x80 == 0)
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum VbrHeaderKind {
+    Xing,
+    Info,
+    Vbri,
+}
+
+// The Xing/Info (written by LAME and most encoders) or VBRI (Fraunhofer)
+// header stored in place of audio in the first frame
+pub struct VbrHeader {
+    pub kind: VbrHeaderKind,
+    pub frames: Option<u32>,
+    pub lame: Option<LameTag>,
+}
+
+pub struct LameTag {
+    pub version: String,
+    pub preset: Option<String>,
+    pub delay: u32,
+    pub padding: u32,
+}
+
+impl VbrHeader {
+    pub fn parse(frame: &[u8], header: &FrameHeader) -> Option<Self> {
+        let xing = 4 + header.side_info_len();
+        if frame.len() >= xing + 8
+            && (&frame[xing..xing + 4] == b"Xing" || &frame[xing..xing + 4] == b"Info")
+        {
+            let kind = if &frame[xing..xing + 4] == b"Xing" {
+                VbrHeaderKind::Xing
+            } else {
+                VbrHeaderKind::Info
+            };
+            let flags = read_u32(&frame[xing + 4..])?;
+            let mut pos = xing + 8;
+            let mut field = |flag: u32, len: usize| -> Option<Option<u32>> {
+                if flags & flag == 0 {
+                    return Some(None);
+                }
+                let value = read_u32(frame.get(pos..)?);
+                pos += len;
+                Some(value)
+            };
+            let frames = field(0x01, 4)?;
+            field(0x02, 4)?;
+            field(0x04, 100)?;
+            field(0x08, 4)?;
+
+            return Some(Self {
+                kind,
+                frames,
+                lame: frame.get(pos..).and_then(LameTag::parse),
+            });
+        }
+
+        if frame.len() >= 54 && &frame[36..40] == b"VBRI" {
+            return Some(Self {
+                kind: VbrHeaderKind::Vbri,
+                frames: read_u32(&frame[50..]),
+                lame: None,
+            });
+        }
+
+        None
+    }
+}
+
+impl LameTag {
+    fn parse(tag: &[u8]) -> Option<Self> {
+        if tag.len() < 36 || !tag[..4].iter().all(|c| c.is_ascii_uppercase()) {
+            return None;
+        }
+
+        let version = String::from_utf8_lossy(&tag[..9])
+            .trim_end_matches(|c: char| c == '\0' || c.is_whitespace())
+            .to_string();
+        let delay = ((tag[21] as u32) << 4) | (tag[22] as u32 >> 4);
+        let padding = ((tag[22] as u32 & 0x0F) << 8) | tag[23] as u32;
+        let preset = lame_preset(u16::from_be_bytes([tag[26], tag[27]]) & 0x07FF);
+
+        Some(Self { version, preset, delay, padding })
+    }
+}
+
+fn lame_preset(value: u16) -> Option<String> {
+    let name = match value {
+        0 => return None,
+        8..=320 => format!("ABR {}", value),
+        410..=500 if value.is_multiple_of(10) => format!("V{}", (500 - value) / 10),
+        1000 => "r3mix".to_string(),
+        1001 => "standard".to_string(),
+        1002 => "extreme".to_string(),
+        1003 => "insane".to_string(),
+        1004 => "standard fast".to_string(),
+        1005 => "extreme fast".to_string(),
+        1006 => "medium".to_string(),
+        1007 => "medium fast".to_string(),
+        _ => format!("unknown ({})", value),
+    };
+
+    Some(name)
+}
+
+// Whether the frame carries a Xing/Info or VBRI header instead of audio
+pub fn is_info_frame(data: &[u8], pos: usize, header: &FrameHeader) -> bool {
+    VbrHeader::parse(&data[pos..pos + header.frame_len()], header).is_some()
+}
+
+pub struct StreamInfo {
+    pub version: MpegVersion,
+    pub layer: Layer,
+    pub sample_rate: u32,
+    pub channel_mode: ChannelMode,
+    pub bitrate: u32,
+    pub vbr: bool,
+    pub duration: f64,
+    pub frame_count: usize,
+    pub vbr_header: Option<VbrHeader>,
+}
+
+impl StreamInfo {
+    pub fn format(&self) -> String {
+        let version = match self.version {
+            MpegVersion::Mpeg1 => "1",
+            MpegVersion::Mpeg2 => "2",
+            MpegVersion::Mpeg25 => "2.5",
+        };
+        let layer = match self.layer {
+            Layer::Layer1 => "I",
+            Layer::Layer2 => "II",
+            Layer::Layer3 => "III",
+        };
+
+        format!("MPEG-{} Layer {}", version, layer)
+    }
+
+    pub fn channels(&self) -> &'static str {
+        match self.channel_mode {
+            ChannelMode::Stereo => "Stereo",
+            ChannelMode::JointStereo => "Joint Stereo",
+            ChannelMode::DualChannel => "Dual Channel",
+            ChannelMode::Mono => "Mono",
+        }
+    }
+
+    pub fn lame(&self) -> Option<&LameTag> {
+        self.vbr_header.as_ref().and_then(|header| header.lame.as_ref())
+    }
+
+    pub fn bitrate_mode(&self) -> &'static str {
+        if self.vbr { "VBR" } else { "CBR" }
+    }
+
+    // Read-only values for %field% patterns, see STREAM_FIELDS
+    pub fn field(&self, name: &str) -> Option<String> {
+        let value = match name.to_lowercase().as_str() {
+            "format" => self.format(),
+            "bitrate" => self.bitrate.to_string(),
+            "bitratemode" => self.bitrate_mode().to_string(),
+            "samplerate" => self.sample_rate.to_string(),
+            "channels" => self.channels().to_string(),
+            "duration" => {
+                let seconds = self.duration.round() as u64;
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            },
+            "length" => ((self.duration * 1000.0).round() as u64).to_string(),
+            "frames" => self.frame_count.to_string(),
+            "lame" => self.lame().map(|lame| lame.version.clone()).unwrap_or_default(),
+            "preset" => self.lame().and_then(|lame| lame.preset.clone()).unwrap_or_default(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+// Stream properties that patterns can read but never write
+pub const STREAM_FIELDS: [&str; 10] = [
+    "format",
+    "bitrate",
+    "bitratemode",
+    "samplerate",
+    "channels",
+    "duration",
+    "length",
+    "frames",
+    "lame",
+    "preset",
+];
+
+pub fn is_stream_field(name: &str) -> bool {
+    STREAM_FIELDS.contains(&name.to_lowercase().as_str())
+}
+
+// Only MPEG files are read: scanning PCM or FLAC data for frame syncs finds
+// false ones, and reads every byte of a large WAV file for nothing
+pub fn read_stream_info(path: &Path) -> Result<StreamInfo, io::Error> {
+    if Container::from_path(path) != Some(Container::Mpeg) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Not an MPEG stream"));
+    }
+
+    let data = fs::read(path)?;
+    stream_info(&data)
+        .ok_or(io::Error::new(io::ErrorKind::InvalidData, "No MPEG audio found"))
+}
+
+pub fn stream_info(data: &[u8]) -> Option<StreamInfo> {
+    let mut frames = walk_frames(data);
+    let mut vbr_header = None;
+    if let Some((pos, header)) = frames.first() {
+        vbr_header = VbrHeader::parse(&data[*pos..*pos + header.frame_len()], header);
+        if vbr_header.is_some() {
+            frames.remove(0);
+        }
+    }
+
+    let first = frames.first().map(|(_, header)| *header)?;
+    let samples = frames
+        .iter()
+        .map(|(_, header)| header.samples() as u64)
+        .sum::<u64>();
+    let bytes = frames
+        .iter()
+        .map(|(_, header)| header.frame_len() as u64)
+        .sum::<u64>();
+
+    // Encoder delay and padding from the LAME tag give the gapless length
+    let gap = vbr_header
+        .as_ref()
+        .and_then(|header| header.lame.as_ref())
+        .map(|lame| (lame.delay + lame.padding) as u64)
+        .filter(|gap| *gap < samples)
+        .unwrap_or(0);
+    let duration = (samples - gap) as f64 / first.sample_rate as f64;
+    let bitrate = if samples > 0 {
+        (bytes as f64 * 8.0 * first.sample_rate as f64 / samples as f64 / 1000.0).round() as u32
+    } else {
+        first.bitrate
+    };
+    let vbr = frames.iter().any(|(_, header)| header.bitrate != first.bitrate);
+
+    Some(StreamInfo {
+        version: first.version,
+        layer: first.layer,
+        sample_rate: first.sample_rate,
+        channel_mode: first.channel_mode,
+        bitrate,
+        vbr,
+        duration,
+        frame_count: frames.len(),
+        vbr_header,
+    })
+}
+
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+fn synchsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, byte| (acc << 7) | (*byte as u32 & 0x7F))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    // A first frame carrying a Xing header that counts the frames after it
+    fn xing_frame(frames: u32) -> Vec<u8> {
+        let mut frame = mpeg_frames(1);
+        frame[4..4 + 32].fill(0);
+        frame[36..40].copy_from_slice(b"Xing");
+        frame[40..44].copy_from_slice(&1u32.to_be_bytes());
+        frame[44..48].copy_from_slice(&frames.to_be_bytes());
+        frame[48..].fill(0);
+
+        frame
+    }
+
+    #[test]
+    fn headers_are_parsed() {
+        let header = FrameHeader::parse(&[0xFF, 0xFB, 0x90, 0x00]).unwrap();
+        assert!(header.version == MpegVersion::Mpeg1 && header.layer == Layer::Layer3);
+        assert_eq!((header.bitrate, header.sample_rate), (128, 44100));
+        assert_eq!(header.frame_len(), MPEG_FRAME_LEN);
+
+        // Reserved version, free format bitrate and reserved sample rate
+        for bytes in [[0xFF, 0xEB, 0x90, 0x00], [0xFF, 0xFB, 0x00, 0x00], [0xFF, 0xFB, 0x9C, 0x00]] {
+            assert!(FrameHeader::parse(&bytes).is_none());
+        }
+        assert!(FrameHeader::parse(&[0xFF, 0xFB]).is_none());
+    }
+
+    #[test]
+    fn tags_around_the_audio_are_skipped() {
+        let mut data = b"ID3\x04\0\0\0\0\0\x05hello".to_vec();
+        data.extend(mpeg_frames(3));
+        let audio_end = data.len();
+        data.extend(b"APETAGEX");
+        data.extend(2000u32.to_le_bytes());
+        data.extend(32u32.to_le_bytes());
+        data.extend([0; 16]);
+        data.extend(b"TAG");
+        data.resize(data.len() + 125, 0);
+
+        assert_eq!(audio_range(&data), (15, audio_end));
+        let scan = scan_frames(&data);
+        assert_eq!(scan.frames.len(), 3);
+        assert!(scan.junk.is_empty() && scan.truncated.is_none());
+    }
+
+    #[test]
+    fn junk_and_truncation_are_found() {
+        let mut data = b"junk".to_vec();
+        data.extend(mpeg_frames(2));
+        data.extend(b"more junk");
+        data.extend(mpeg_frames(2));
+        data.truncate(data.len() - 100);
+
+        let scan = scan_frames(&data);
+        assert_eq!(scan.frames.len(), 3);
+        assert_eq!(scan.junk, vec![(0, 4), (4 + 2 * MPEG_FRAME_LEN, 13 + 2 * MPEG_FRAME_LEN)]);
+        assert_eq!(scan.truncated, Some((13 + 3 * MPEG_FRAME_LEN, MPEG_FRAME_LEN, MPEG_FRAME_LEN - 100)));
+    }
+
+    #[test]
+    fn stream_info_skips_the_xing_frame() {
+        let mut data = xing_frame(10);
+        data.extend(mpeg_frames(10));
+
+        let info = stream_info(&data).unwrap();
+        assert_eq!((info.frame_count, info.bitrate, info.vbr), (10, 128, false));
+        assert!((info.duration - 10.0 * 1152.0 / 44100.0).abs() < 1e-9);
+        let vbr_header = info.vbr_header.unwrap();
+        assert!(vbr_header.kind == VbrHeaderKind::Xing && vbr_header.frames == Some(10));
+
+        assert!(stream_info(b"no frames in here at all").is_none());
+        assert!(stream_info(&xing_frame(0)).is_none());
+    }
+
+    #[test]
+    fn only_mpeg_files_have_stream_info() {
+        let frames = mpeg_frames(3);
+        assert!(read_stream_info(&scratch_file("track.mp3", &frames)).is_ok());
+
+        let err = read_stream_info(&scratch_file("track.wav", &frames)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(read_stream_info(&scratch_file("track.flac", &frames)).is_err());
+    }
+}
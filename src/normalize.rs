@@ -0,0 +1,167 @@
+use std::{
+    fs,
+    io,
+    path::PathBuf,
+};
+use unicode_normalization::{
+    is_nfc,
+    is_nfd,
+    UnicodeNormalization,
+};
+
+use crate::input::*;
+
+pub fn normalize_form() -> InputForm {
+    InputForm::new(
+        "Normalise Filenames",
+        "NFC: composed, as most software expects | NFD: decomposed, as macOS writes",
+        &[("Form", "NFC")]
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalForm {
+    Nfc,
+    Nfd,
+}
+
+impl NormalForm {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "nfc" => Some(NormalForm::Nfc),
+            "nfd" => Some(NormalForm::Nfd),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NormalForm::Nfc => "NFC",
+            NormalForm::Nfd => "NFD",
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            NormalForm::Nfc => text.nfc().collect(),
+            NormalForm::Nfd => text.nfd().collect(),
+        }
+    }
+}
+
+// The form text is in, or None if it mixes composed and decomposed
+// characters. Text that is both (plain ASCII, for one) counts as NFC.
+pub fn normal_form(text: &str) -> Option<NormalForm> {
+    if is_nfc(text) {
+        Some(NormalForm::Nfc)
+    } else if is_nfd(text) {
+        Some(NormalForm::Nfd)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl Rename {
+    pub fn describe(&self) -> String {
+        let name = |path: &PathBuf| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        let (old, new) = (name(&self.from), name(&self.to));
+        let form = normal_form(&old).map(|form| form.name()).unwrap_or("mixed");
+
+        // The names look the same, so show what changes underneath
+        format!(
+            "{} ({}, {} code points -> {})",
+            new, form, old.chars().count(), new.chars().count()
+        )
+    }
+}
+
+// Only the file name is normalised, not the directories above it
+pub fn find_renames(paths: &[PathBuf], form: NormalForm) -> Vec<Rename> {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            let normalized = form.apply(name);
+            if normalized == name {
+                return None;
+            }
+
+            Some(Rename { from: path.clone(), to: path.with_file_name(normalized) })
+        })
+        .collect()
+}
+
+// Filesystems that normalise names themselves, such as macOS's, see both
+// spellings as the same file, which is fine to rename over
+pub fn rename_file(rename: &Rename) -> io::Result<()> {
+    if rename.to.exists() && fs::canonicalize(&rename.to)? != fs::canonicalize(&rename.from)? {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", rename.to.display())
+        ));
+    }
+
+    fs::rename(&rename.from, &rename.to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    const COMPOSED: &str = "Bj\u{F6}rk - J\u{F3}ga.mp3";
+    const DECOMPOSED: &str = "Bjo\u{308}rk - Jo\u{301}ga.mp3";
+    const MIXED: &str = "Bj\u{F6}rk - Jo\u{301}ga.mp3";
+
+    #[test]
+    fn names_are_classified_by_form() {
+        assert_eq!(normal_form("Low.mp3"), Some(NormalForm::Nfc));
+        assert_eq!(normal_form(COMPOSED), Some(NormalForm::Nfc));
+        assert_eq!(normal_form(DECOMPOSED), Some(NormalForm::Nfd));
+        assert_eq!(normal_form(MIXED), None);
+    }
+
+    #[test]
+    fn only_names_in_another_form_are_renamed() {
+        let dir = PathBuf::from("Mu\u{308}m");
+        let paths = [COMPOSED, DECOMPOSED, MIXED, "Low.mp3"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect::<Vec<PathBuf>>();
+
+        let renames = find_renames(&paths, NormalForm::Nfc);
+        assert_eq!(renames.len(), 2);
+        assert_eq!(renames[0].from, paths[1]);
+        assert_eq!(renames[1].from, paths[2]);
+        // The directory keeps its decomposed name
+        assert!(renames.iter().all(|rename| rename.to == dir.join(COMPOSED)));
+        assert_eq!(renames[1].describe(), format!("{} (mixed, 17 code points -> 16)", COMPOSED));
+
+        let renames = find_renames(&paths, NormalForm::Nfd);
+        assert_eq!(renames.len(), 2);
+        assert_eq!(renames[0].describe(), format!("{} (NFC, 16 code points -> 18)", DECOMPOSED));
+    }
+
+    #[test]
+    fn renames_never_overwrite_another_file() {
+        let from = scratch_file(DECOMPOSED, b"decomposed");
+        let rename = Rename { from: from.clone(), to: from.with_file_name(COMPOSED) };
+        rename_file(&rename).unwrap();
+        assert_eq!(fs::read(&rename.to).unwrap(), b"decomposed");
+
+        fs::write(&from, b"another file").unwrap();
+        let err = rename_file(&rename).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&rename.to).unwrap(), b"decomposed");
+    }
+}
@@ -0,0 +1,497 @@
+use std::{
+    fs,
+    io::{
+        self,
+        Error,
+        ErrorKind,
+    },
+    path::Path,
+};
+
+use crate::backend::*;
+use crate::vorbis::*;
+
+const CONTINUED: u8 = 0x01;
+const PICTURE_FIELD: &str = "METADATA_BLOCK_PICTURE";
+// Granule position of a page on which no packet ends
+const NO_GRANULE: u64 = u64::MAX;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Codec {
+    Vorbis,
+    Opus,
+}
+
+impl Codec {
+    fn detect(packet: &[u8]) -> Option<Self> {
+        if packet.starts_with(b"\x01vorbis") {
+            Some(Codec::Vorbis)
+        } else if packet.starts_with(b"OpusHead") {
+            Some(Codec::Opus)
+        } else {
+            None
+        }
+    }
+
+    // Identification, comment and (for Vorbis) setup
+    fn num_headers(&self) -> usize {
+        match self {
+            Codec::Vorbis => 3,
+            Codec::Opus => 2,
+        }
+    }
+
+    fn comment_magic(&self) -> &'static [u8] {
+        match self {
+            Codec::Vorbis => b"\x03vorbis",
+            Codec::Opus => b"OpusTags",
+        }
+    }
+}
+
+struct Page<'a> {
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    lacing: &'a [u8],
+    body: &'a [u8],
+}
+
+impl Page<'_> {
+    fn encode(&self) -> Vec<u8> {
+        encode_page(self.header_type, self.granule, self.serial, self.sequence, self.lacing, self.body)
+    }
+}
+
+fn parse_pages(data: &[u8]) -> Option<Vec<Page<'_>>> {
+    let mut pages = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data.get(pos..pos + 27)?;
+        if &header[..4] != b"OggS" || header[4] != 0 {
+            return None;
+        }
+        let num_segments = header[26] as usize;
+        let lacing = data.get(pos + 27..pos + 27 + num_segments)?;
+        let body_len = lacing.iter().map(|len| *len as usize).sum::<usize>();
+        let body_start = pos + 27 + num_segments;
+        let body = data.get(body_start..body_start + body_len)?;
+
+        pages.push(Page {
+            header_type: header[5],
+            granule: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+            serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+            sequence: u32::from_le_bytes(header[18..22].try_into().unwrap()),
+            lacing,
+            body,
+        });
+        pos = body_start + body_len;
+    }
+
+    Some(pages)
+}
+
+fn encode_page(
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    lacing: &[u8],
+    body: &[u8]
+) -> Vec<u8> {
+    let mut page = b"OggS\0".to_vec();
+    page.push(header_type);
+    page.extend(granule.to_le_bytes());
+    page.extend(serial.to_le_bytes());
+    page.extend(sequence.to_le_bytes());
+    page.extend([0; 4]);
+    page.push(lacing.len() as u8);
+    page.extend(lacing);
+    page.extend(body);
+
+    let crc = ogg_crc(&page);
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+
+    page
+}
+
+// CRC-32 with polynomial 0x04C11DB7, unreflected and without a final xor, as
+// used in Ogg page headers
+fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for byte in data {
+        crc ^= (*byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+        }
+    }
+
+    crc
+}
+
+// The header packets of the first logical stream, and the index of the page
+// they end on. Audio must start on a fresh page after the headers.
+struct Headers {
+    codec: Codec,
+    serial: u32,
+    packets: Vec<Vec<u8>>,
+    last_page: usize,
+}
+
+fn read_headers(pages: &[Page]) -> Option<Headers> {
+    let serial = pages.first()?.serial;
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    let mut num_headers = None;
+
+    for (index, page) in pages.iter().enumerate().filter(|(_, page)| page.serial == serial) {
+        let mut pos = 0;
+        for (segment, len) in page.lacing.iter().enumerate() {
+            packet.extend(&page.body[pos..pos + *len as usize]);
+            pos += *len as usize;
+            if *len == 255 {
+                continue;
+            }
+
+            if packets.is_empty() {
+                num_headers = Some(Codec::detect(&packet)?.num_headers());
+            }
+            packets.push(std::mem::take(&mut packet));
+            if Some(packets.len()) == num_headers {
+                // Anything else on the last header page can't be re-paged
+                if segment + 1 != page.lacing.len() {
+                    return None;
+                }
+                return Some(Headers {
+                    codec: Codec::detect(&packets[0])?,
+                    serial,
+                    packets,
+                    last_page: index,
+                });
+            }
+        }
+    }
+
+    None
+}
+
+fn invalid_ogg() -> Error {
+    Error::new(ErrorKind::InvalidData, "Unsupported or invalid Ogg stream")
+}
+
+fn comment_body(headers: &Headers) -> Option<&[u8]> {
+    headers.packets[1].strip_prefix(headers.codec.comment_magic())
+}
+
+fn read_ogg(path: &Path) -> io::Result<Option<TagModel>> {
+    let data = fs::read(path)?;
+    let pages = parse_pages(&data).ok_or_else(invalid_ogg)?;
+    let headers = read_headers(&pages).ok_or_else(invalid_ogg)?;
+    let comments = comment_body(&headers)
+        .and_then(parse_comments)
+        .ok_or_else(invalid_ogg)?;
+
+    let mut fields = Vec::new();
+    let mut pictures = Vec::new();
+    for (key, value) in comments.fields {
+        if key.eq_ignore_ascii_case(PICTURE_FIELD) {
+            pictures.extend(base64_decode(&value).and_then(|data| parse_picture(&data)));
+        } else {
+            fields.push((key, value));
+        }
+    }
+    if fields.is_empty() && pictures.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(comments_to_model(&fields, pictures)))
+}
+
+// Rebuilds the comment packet and re-pages the headers after the
+// identification packet. Later pages of the stream are renumbered, with their
+// checksums recomputed; their contents, and every page of other logical
+// streams, are copied as they are.
+fn write_ogg(model: &TagModel, path: &Path) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let pages = parse_pages(&data).ok_or_else(invalid_ogg)?;
+    let headers = read_headers(&pages).ok_or_else(invalid_ogg)?;
+    let vendor = comment_body(&headers)
+        .and_then(parse_comments)
+        .map(|comments| comments.vendor)
+        .ok_or_else(invalid_ogg)?;
+    // The identification packet must be alone on the first page
+    let first_lacing = pages[0].lacing;
+    if first_lacing.last() == Some(&255) || first_lacing.iter().rev().skip(1).any(|len| *len != 255) {
+        return Err(invalid_ogg());
+    }
+
+    let (mut fields, pictures) = model_to_comments(model);
+    for picture in pictures.iter() {
+        fields.push((PICTURE_FIELD.to_string(), base64_encode(&serialize_picture(picture))));
+    }
+    let mut comment_packet = headers.codec.comment_magic().to_vec();
+    comment_packet.extend(serialize_comments(&Comments { vendor, fields }));
+    if headers.codec == Codec::Vorbis {
+        // Framing bit
+        comment_packet.push(1);
+    }
+
+    let mut header_packets = vec![comment_packet];
+    header_packets.extend(headers.packets[2..].iter().cloned());
+
+    // Pages of other logical streams among the headers, such as their
+    // beginning of stream pages, which must stay ahead of any other page
+    let mut contents = pages[0].encode();
+    for page in pages[1..=headers.last_page].iter().filter(|page| page.serial != headers.serial) {
+        contents.extend(page.encode());
+    }
+    let header_pages = paginate(&header_packets, headers.serial, 1);
+    let next_sequence = 1 + header_pages.len() as u32;
+    for page in header_pages {
+        contents.extend(page);
+    }
+
+    let old_next_sequence = pages[headers.last_page].sequence.wrapping_add(1);
+    for page in pages[headers.last_page + 1..].iter() {
+        if page.serial == headers.serial {
+            let sequence = page.sequence
+                .wrapping_sub(old_next_sequence)
+                .wrapping_add(next_sequence);
+            contents.extend(encode_page(
+                page.header_type,
+                page.granule,
+                page.serial,
+                sequence,
+                page.lacing,
+                page.body
+            ));
+        } else {
+            contents.extend(page.encode());
+        }
+    }
+    fs::write(path, contents)?;
+
+    Ok(())
+}
+
+pub struct OggBackend;
+
+impl TagBackend for OggBackend {
+    fn read(&self, path: &Path) -> io::Result<Option<TagModel>> {
+        read_ogg(path)
+    }
+
+    fn write(&self, model: &TagModel, path: &Path) -> io::Result<()> {
+        write_ogg(model, path)
+    }
+}
+
+// Splits packets into pages of at most 255 lacing values each
+fn paginate(packets: &[Vec<u8>], serial: u32, first_sequence: u32) -> Vec<Vec<u8>> {
+    let mut pages = Vec::new();
+    let mut lacing = Vec::new();
+    let mut body = Vec::new();
+    let mut continued = false;
+    let mut packet_ended = false;
+
+    let mut flush = |lacing: &mut Vec<u8>, body: &mut Vec<u8>, continued: bool, packet_ended: bool| {
+        let granule = if packet_ended { 0 } else { NO_GRANULE };
+        let header_type = if continued { CONTINUED } else { 0 };
+        let sequence = first_sequence + pages.len() as u32;
+        pages.push(encode_page(header_type, granule, serial, sequence, lacing, body));
+        lacing.clear();
+        body.clear();
+    };
+
+    for packet in packets {
+        let mut values = vec![255u8; packet.len() / 255];
+        values.push((packet.len() % 255) as u8);
+
+        let mut pos = 0;
+        for (i, value) in values.iter().enumerate() {
+            if lacing.len() == 255 {
+                flush(&mut lacing, &mut body, continued, packet_ended);
+                continued = i > 0;
+                packet_ended = false;
+            }
+            lacing.push(*value);
+            body.extend(&packet[pos..pos + *value as usize]);
+            pos += *value as usize;
+        }
+        packet_ended = true;
+    }
+    if !lacing.is_empty() {
+        flush(&mut lacing, &mut body, continued, packet_ended);
+    }
+
+    pages
+}
+
+// Everything after the header pages, granule positions included
+pub fn ogg_audio(data: &[u8]) -> Option<Vec<u8>> {
+    let pages = parse_pages(data)?;
+    let headers = read_headers(&pages)?;
+
+    let mut audio = Vec::new();
+    for page in pages[headers.last_page + 1..].iter() {
+        audio.extend(page.granule.to_le_bytes());
+        audio.extend(page.body);
+    }
+
+    Some(audio)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut bits = 0u32;
+    let mut num_bits = 0;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let value = BASE64.iter().position(|other| *other == c)? as u32;
+        bits = (bits << 6) | value;
+        num_bits += 6;
+        if num_bits >= 8 {
+            num_bits -= 8;
+            decoded.push((bits >> num_bits) as u8);
+            bits &= (1 << num_bits) - 1;
+        }
+    }
+
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+    use crate::testutil::*;
+
+    const BEGINNING: u8 = 0x02;
+
+    fn front_cover(data: &[u8]) -> Cover {
+        Cover {
+            mime_type: "image/jpeg".to_string(),
+            picture_type: 3,
+            description: String::new(),
+            data: data.to_vec(),
+        }
+    }
+
+    // A Vorbis stream with two front covers, multiplexed with a second
+    // logical stream whose beginning page sits among the Vorbis headers
+    fn sample_ogg() -> Vec<u8> {
+        let fields = vec![
+            ("TITLE".to_string(), "Old".to_string()),
+            (PICTURE_FIELD.to_string(), base64_encode(&serialize_picture(&front_cover(b"first")))),
+            (PICTURE_FIELD.to_string(), base64_encode(&serialize_picture(&front_cover(b"second")))),
+        ];
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend(serialize_comments(&Comments { vendor: "test".to_string(), fields }));
+        comment.push(1);
+        let ident = b"\x01vorbis identification".to_vec();
+        let setup = vec![5; 300];
+
+        let mut data = encode_page(BEGINNING, 0, 1, 0, &[ident.len() as u8], &ident);
+        data.extend(encode_page(BEGINNING, 0, 2, 0, &[10], b"OtherHead!"));
+        for page in paginate(&[comment, setup], 1, 1) {
+            data.extend(page);
+        }
+        data.extend(encode_page(0, 1000, 1, 3, &[5], b"audio"));
+        data.extend(encode_page(0, 500, 2, 1, &[5], b"video"));
+
+        data
+    }
+
+    fn stream_pages(data: &[u8], serial: u32) -> Vec<Vec<u8>> {
+        parse_pages(data)
+            .unwrap()
+            .iter()
+            .filter(|page| page.serial == serial)
+            .map(|page| page.body.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn pages_reencode_to_the_same_bytes() {
+        let data = sample_ogg();
+        let encoded = parse_pages(&data)
+            .unwrap()
+            .iter()
+            .flat_map(|page| page.encode())
+            .collect::<Vec<u8>>();
+        assert_eq!(encoded, data);
+    }
+
+    #[test]
+    fn edits_keep_pictures_and_other_streams() {
+        let original = sample_ogg();
+        let path = scratch_file("track.ogg", &original);
+        let mut tag = read_tag(&path).unwrap();
+        assert_eq!(tag.pictures().count(), 2);
+
+        tag.set_title("A new title");
+        write_tag(&tag, &path).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let model = read_ogg(&path).unwrap().unwrap();
+        assert_eq!(model.first(&Field::Title), Some("A new title"));
+        assert_eq!(model.pictures, vec![front_cover(b"first"), front_cover(b"second")]);
+        assert_eq!(stream_pages(&data, 2), stream_pages(&original, 2));
+        assert_eq!(ogg_audio(&data), ogg_audio(&original));
+
+        // Beginning of stream pages still come first
+        let pages = parse_pages(&data).unwrap();
+        assert!(pages[..2].iter().all(|page| page.header_type & BEGINNING != 0));
+    }
+
+    #[test]
+    fn long_comments_span_pages() {
+        let packet = vec![7; 255 * 300];
+        let pages = paginate(std::slice::from_ref(&packet), 1, 1).concat();
+        let parsed = parse_pages(&pages).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].header_type, CONTINUED);
+        assert_eq!(parsed.iter().flat_map(|page| page.body.to_vec()).collect::<Vec<u8>>(), packet);
+    }
+
+    #[test]
+    fn damaged_streams_are_rejected() {
+        let mut data = sample_ogg();
+        data.truncate(data.len() - 3);
+        assert!(parse_pages(&data).is_none());
+
+        let path = scratch_file("track.ogg", &data);
+        assert_eq!(read_ogg(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(write_ogg(&TagModel::new(TagFormat::Vorbis), &path).is_err());
+        assert_eq!(fs::read(&path).unwrap(), data);
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        for len in 0..8 {
+            let data = (0..len).map(|i| i as u8 * 37).collect::<Vec<u8>>();
+            assert_eq!(base64_decode(&base64_encode(&data)).unwrap(), data);
+        }
+        assert_eq!(base64_encode(b"rime"), "cmltZQ==");
+    }
+}
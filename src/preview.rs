@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use crate::app::AppState;
+use crate::backend::*;
+use crate::list::*;
+
+#[derive(Clone)]
+pub struct FrameChange {
+    pub id: String,
+    pub old: Option<String>,
+    pub new: String,
+}
+
+pub struct FileDiff {
+    pub path: PathBuf,
+    pub changes: Vec<FrameChange>,
+    pub warning: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum PreviewLine {
+    File(PathBuf, Option<String>),
+    Change(FrameChange),
+}
+
+// A per-file diff of proposed frame changes, shown to the user before the
+// changes are staged as pending edits
+pub struct Preview {
+    pub title: String,
+    pub diffs: Vec<FileDiff>,
+    pub lines: GenericList<PreviewLine>,
+    pub return_state: AppState,
+}
+
+impl Preview {
+    pub fn new(
+        title: String,
+        mut diffs: Vec<FileDiff>,
+        return_state: AppState
+    ) -> Self {
+        for diff in diffs.iter_mut() {
+            let lost = diff.changes
+                .iter()
+                .filter(|change| !can_store_key(&diff.path, &change.id))
+                .map(|change| change.id.as_str())
+                .collect::<Vec<&str>>();
+            if !lost.is_empty() {
+                let note = format!("{} can't be stored in this file's format", lost.join(", "));
+                diff.warning = Some(match diff.warning.take() {
+                    Some(warning) => format!("{}; {}", warning, note),
+                    None => note,
+                });
+            }
+        }
+        diffs.retain(|diff| !diff.changes.is_empty() || diff.warning.is_some());
+        diffs.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut lines = Vec::new();
+        for diff in diffs.iter() {
+            lines.push(PreviewLine::File(diff.path.clone(), diff.warning.clone()));
+            for change in diff.changes.iter() {
+                lines.push(PreviewLine::Change(change.clone()));
+            }
+        }
+
+        Self {
+            title,
+            diffs,
+            lines: GenericList::from(lines),
+            return_state,
+        }
+    }
+
+    pub fn num_changed_files(&self) -> usize {
+        self.diffs
+            .iter()
+            .filter(|diff| !diff.changes.is_empty())
+            .count()
+    }
+}
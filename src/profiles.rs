@@ -0,0 +1,557 @@
+use id3::{
+    frame::Picture,
+    v1,
+    Content,
+    Encoding,
+    Frame,
+    Tag,
+    TagLike,
+    Version,
+};
+use std::{
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::backend::*;
+use crate::config::*;
+use crate::container::*;
+use crate::lint::*;
+
+const PROFILE_EXTENSION: &str = "profile";
+const DEFAULT_PROFILE_FILE: &str = "default_profile";
+const ID3V1_LEN: usize = 128;
+
+// Frames only defined by ID3v2.4 that have no ID3v2.3 equivalent. iTunes
+// writes the sort order frames (TSOA, TSOP, TSOT) to v2.3 tags as well, so
+// they are kept.
+const V24_ONLY_FRAMES: [&str; 9] = [
+    "TDEN", "TDRL", "TDTG", "TIPL", "TMCL", "TMOO", "TPRO", "TSST", "RVA2",
+];
+// Frames ID3v2.4 dropped
+const V23_ONLY_FRAMES: [&str; 7] = ["TDAT", "TIME", "TRDA", "TSIZ", "RVAD", "EQUA", "IPLS"];
+// Date frames renamed between the versions, as (v2.3, v2.4) IDs
+const DATE_FRAMES: [(&str, &str); 2] = [("TYER", "TDRC"), ("TORY", "TDOR")];
+
+// How ID3 tags are written: tag version, text encoding, whether an ID3v1.1
+// tag is added to MP3s and the largest cover art in pixels. Built-in
+// profiles can be replaced, and others added, by files in the profiles
+// config directory.
+#[derive(Clone)]
+pub struct WriteProfile {
+    pub name: String,
+    pub version: Version,
+    pub encoding: Encoding,
+    pub id3v1: bool,
+    pub max_art: Option<u32>,
+}
+
+impl WriteProfile {
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self {
+                name: "modern".to_string(),
+                version: Version::Id3v24,
+                encoding: Encoding::UTF8,
+                id3v1: false,
+                max_art: None,
+            },
+            Self {
+                name: "legacy".to_string(),
+                version: Version::Id3v23,
+                encoding: Encoding::UTF16,
+                id3v1: true,
+                max_art: Some(500),
+            },
+        ]
+    }
+
+    // A profile file has one "key=value" pair per line, for example
+    //   version=2.3
+    //   encoding=utf-16
+    //   id3v1=yes
+    //   max_art=500
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut profile = Self {
+            name,
+            version: Version::Id3v24,
+            encoding: Encoding::UTF8,
+            id3v1: false,
+            max_art: None,
+        };
+
+        let contents = fs::read_to_string(path)?;
+        for line in contents.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#')) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("Not a key=value line: {}", line)))?;
+            let value = value.trim().to_lowercase();
+            match key.trim().to_lowercase().as_str() {
+                "version" => {
+                    profile.version = match value.as_str() {
+                        "2.3" => Version::Id3v23,
+                        "2.4" => Version::Id3v24,
+                        _ => return Err(invalid(format!("Unknown version {}", value))),
+                    };
+                },
+                "encoding" => {
+                    profile.encoding = match value.as_str() {
+                        "latin-1" | "latin1" | "iso-8859-1" => Encoding::Latin1,
+                        "utf-16" | "utf16" => Encoding::UTF16,
+                        "utf-8" | "utf8" => Encoding::UTF8,
+                        _ => return Err(invalid(format!("Unknown encoding {}", value))),
+                    };
+                },
+                "id3v1" => profile.id3v1 = matches!(value.as_str(), "yes" | "true" | "1"),
+                "max_art" => {
+                    profile.max_art = match value.as_str() {
+                        "" | "none" => None,
+                        value => Some(value
+                            .parse::<u32>()
+                            .map_err(|_| invalid(format!("max_art must be pixels, not {}", value)))?),
+                    };
+                },
+                other => return Err(invalid(format!("Unknown setting {}", other))),
+            }
+        }
+
+        if profile.encoding == Encoding::UTF8 && profile.version == Version::Id3v23 {
+            return Err(invalid("ID3v2.3 tags can't be UTF-8".to_string()));
+        }
+
+        Ok(profile)
+    }
+
+    // e.g. "ID3v2.3, UTF-16, ID3v1.1, art up to 500px"
+    pub fn describe(&self) -> String {
+        let mut parts = vec![
+            match self.version {
+                Version::Id3v22 => "ID3v2.2",
+                Version::Id3v23 => "ID3v2.3",
+                Version::Id3v24 => "ID3v2.4",
+            }.to_string(),
+            match self.encoding {
+                Encoding::Latin1 => "Latin-1",
+                Encoding::UTF16 => "UTF-16",
+                Encoding::UTF16BE => "UTF-16BE",
+                Encoding::UTF8 => "UTF-8",
+            }.to_string(),
+        ];
+        if self.id3v1 {
+            parts.push("ID3v1.1".to_string());
+        }
+        if let Some(max_art) = self.max_art {
+            parts.push(format!("art up to {}px", max_art));
+        }
+
+        parts.join(", ")
+    }
+
+    // The tag as it will be written under the profile, and everything that
+    // couldn't be carried over as it is
+    pub fn convert(&self, tag: &Tag, container: Container) -> (Tag, Vec<String>) {
+        let mut converted = Tag::with_version(self.version);
+        let mut issues = Vec::new();
+
+        for frame in tag.frames() {
+            let id = frame.id();
+            let mut frame = frame.clone();
+
+            if let Some(new_id) = self.renamed_date_frame(id) {
+                let text = frame.content().text().unwrap_or_default();
+                if tag.get(new_id).is_some() {
+                    issues.push(format!("{} left out, the tag already has {}", id, new_id));
+                    continue;
+                }
+                // v2.3 dates are years only
+                let text = if self.version == Version::Id3v23 && text.chars().count() > 4 {
+                    issues.push(format!("{} written as {}, without the month and day", id, new_id));
+                    text.chars().take(4).collect()
+                } else {
+                    text.to_string()
+                };
+                frame = Frame::text(new_id, text);
+            } else if self.is_unsupported(id) {
+                issues.push(format!("{} left out, {} has no such frame", id, self.version_name()));
+                continue;
+            }
+
+            // Reading a v2.3 tag splits text on '/', which in "3/12" or
+            // "AC/DC" was never a separator
+            if let (Content::Text(text), Version::Id3v24, Version::Id3v23) =
+                (frame.content(), self.version, tag.version())
+            {
+                if text.contains('\0') {
+                    frame = Frame::text(id, text.replace('\0', "/"));
+                }
+            }
+
+            // ID3v2.3 has no multiple values. The encoder joins them with '/'
+            // (and splits on '/' when reading v2.3 tags back), so only values
+            // from v2.4 tags are affected.
+            if let (Content::Text(text), Version::Id3v23, Version::Id3v24) =
+                (frame.content(), self.version, tag.version())
+            {
+                if text.contains('\0') {
+                    issues.push(format!("{}: multiple values joined with '/'", id));
+                }
+            }
+
+            if let (Content::Picture(picture), Some(max_art)) = (frame.content(), self.max_art) {
+                match image_size(&picture.data) {
+                    Some((width, height)) if width.max(height) > max_art => {
+                        issues.push(format!(
+                            "{}: {}x{} {} is larger than {}px, left out",
+                            id, width, height, picture_name(picture), max_art
+                        ));
+                        continue;
+                    },
+                    Some(_) => {},
+                    None => issues.push(format!(
+                        "{}: size of {} unknown, kept", id, picture_name(picture)
+                    )),
+                }
+            }
+
+            // add_frame keeps one picture per type and one RVA2 per tag
+            let frame = self.encode_frame(frame, &mut issues);
+            if matches!(frame.content(), Content::Picture(_) | Content::Unknown(_)) {
+                converted.extend([frame]);
+            } else {
+                converted.add_frame(frame);
+            }
+        }
+
+        if self.id3v1 && container == Container::Mpeg {
+            let (_, v1_issues) = id3v1_bytes(&converted);
+            issues.extend(v1_issues);
+        }
+
+        (converted, issues)
+    }
+
+    fn version_name(&self) -> &'static str {
+        match self.version {
+            Version::Id3v23 => "ID3v2.3",
+            _ => "ID3v2.4",
+        }
+    }
+
+    fn renamed_date_frame(&self, id: &str) -> Option<&'static str> {
+        DATE_FRAMES.iter().find_map(|(v23, v24)| match self.version {
+            Version::Id3v23 if *v24 == id => Some(*v23),
+            Version::Id3v24 if *v23 == id => Some(*v24),
+            _ => None,
+        })
+    }
+
+    fn is_unsupported(&self, id: &str) -> bool {
+        match self.version {
+            Version::Id3v23 => V24_ONLY_FRAMES.contains(&id),
+            _ => V23_ONLY_FRAMES.contains(&id),
+        }
+    }
+
+    // Text that Latin-1 can't hold is written as UTF-16 instead, which every
+    // ID3v2 reader understands
+    fn encode_frame(&self, frame: Frame, issues: &mut Vec<String>) -> Frame {
+        let text = match frame_text(&frame) {
+            Some(text) => text,
+            None => return frame,
+        };
+
+        if self.encoding == Encoding::Latin1 && text.chars().any(|c| c as u32 > 0xFF) {
+            issues.push(format!("{}: characters outside Latin-1, written as UTF-16", frame.id()));
+            return frame.set_encoding(Some(Encoding::UTF16));
+        }
+
+        frame.set_encoding(Some(self.encoding))
+    }
+}
+
+// The text of frames whose content carries a text encoding
+fn frame_text(frame: &Frame) -> Option<String> {
+    let text = match frame.content() {
+        Content::Text(text) => text.clone(),
+        Content::Comment(comment) => format!("{}{}", comment.description, comment.text),
+        Content::ExtendedText(extended) => format!("{}{}", extended.description, extended.value),
+        Content::Lyrics(lyrics) => format!("{}{}", lyrics.description, lyrics.text),
+        Content::Picture(picture) => picture.description.clone(),
+        Content::ExtendedLink(link) => link.description.clone(),
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+fn picture_name(picture: &Picture) -> String {
+    picture.picture_type.to_string().to_lowercase()
+}
+
+// Width and height of a JPEG or PNG image, read from its header
+pub fn image_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+        return Some((width, height));
+    }
+
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        // Start of frame markers, other than DHT, JPG and DAC
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            let height = u16::from_be_bytes(data.get(pos + 5..pos + 7)?.try_into().ok()?);
+            let width = u16::from_be_bytes(data.get(pos + 7..pos + 9)?.try_into().ok()?);
+            return Some((width as u32, height as u32));
+        }
+        pos += 2 + len;
+    }
+
+    None
+}
+
+// An ID3v1.1 tag for the ID3v2 tag: 30 characters of title, artist and
+// album, a 4 digit year, a 28 character comment, the track number and a
+// genre from the ID3v1 list
+pub fn id3v1_bytes(tag: &Tag) -> (Vec<u8>, Vec<String>) {
+    let mut issues = Vec::new();
+    let mut bytes = b"TAG".to_vec();
+
+    let mut field = |id: &str, text: &str, len: usize| {
+        let chars = text.chars().collect::<Vec<char>>();
+        if chars.len() > len {
+            issues.push(format!("ID3v1: {} cut to {} characters", id, len));
+        }
+        if chars.iter().any(|c| *c as u32 > 0xFF) {
+            issues.push(format!("ID3v1: {} has characters outside Latin-1", id));
+        }
+        let mut field = chars
+            .iter()
+            .take(len)
+            .map(|c| if (*c as u32) <= 0xFF { *c as u8 } else { b'?' })
+            .collect::<Vec<u8>>();
+        field.resize(len, 0);
+        field
+    };
+
+    let year = tag
+        .get("TYER")
+        .or_else(|| tag.get("TDRC"))
+        .and_then(|frame| frame.content().text())
+        .unwrap_or_default();
+    let comment = tag
+        .comments()
+        .find(|comment| comment.description.is_empty())
+        .map(|comment| comment.text.clone())
+        .unwrap_or_default();
+    bytes.extend(field("TIT2", tag.title().unwrap_or_default(), 30));
+    bytes.extend(field("TPE1", tag.artist().unwrap_or_default(), 30));
+    bytes.extend(field("TALB", tag.album().unwrap_or_default(), 30));
+    bytes.extend(field("TYER", &year.chars().take(4).collect::<String>(), 4));
+    bytes.extend(field("COMM", &comment, 28));
+
+    let track = tag.track().unwrap_or(0);
+    if track > 255 {
+        issues.push(format!("ID3v1: track {} doesn't fit", track));
+    }
+    bytes.push(0);
+    bytes.push(if track > 255 { 0 } else { track as u8 });
+
+    let genre = tag.genre_parsed().unwrap_or_default();
+    let genre_id = (0..=u8::MAX).find(|id| {
+        let v1 = v1::Tag { genre_id: *id, ..v1::Tag::new() };
+        v1.genre().map(|name| name.eq_ignore_ascii_case(&genre)).unwrap_or(false)
+    });
+    if genre_id.is_none() && !genre.is_empty() {
+        issues.push(format!("ID3v1: genre {} isn't in the ID3v1 list", genre));
+    }
+    bytes.push(genre_id.unwrap_or(255));
+
+    (bytes, issues)
+}
+
+// Replaces the ID3v1 tag at the end of an MP3, or adds one
+fn write_id3v1(tag: &Tag, path: &Path) -> io::Result<()> {
+    let mut data = fs::read(path)?;
+    let end = data.len();
+    if end >= ID3V1_LEN && &data[end - ID3V1_LEN..end - ID3V1_LEN + 3] == b"TAG" {
+        data.truncate(end - ID3V1_LEN);
+    }
+    data.extend(id3v1_bytes(tag).0);
+
+    fs::write(path, data)
+}
+
+// Writes a tag already converted for the profile. ID3 tags are written as
+// they are, so that the frame encodings survive; other formats have no use
+// for a profile and go through their backend as usual.
+pub fn write_tag_as(tag: &Tag, path: &Path, profile: Option<&WriteProfile>) -> io::Result<()> {
+    let container = Container::from_path(path);
+    let profile = match (profile, container) {
+        (Some(profile), Some(container)) if container.has_id3() => profile,
+        _ => return write_tag(tag, path),
+    };
+
+    write_id3_tag(tag, path)?;
+    if profile.id3v1 && container == Some(Container::Mpeg) {
+        write_id3v1(tag, path)?;
+    }
+
+    Ok(())
+}
+
+fn profiles_dir() -> Result<PathBuf, io::Error> {
+    config_subdir("profiles")
+}
+
+// Built-in profiles followed by the user's own, in name order. A profile
+// file named after a built-in profile replaces it.
+pub fn list_profiles() -> Result<Vec<WriteProfile>, io::Error> {
+    let mut paths = fs::read_dir(profiles_dir()?)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().map(|ext| ext == PROFILE_EXTENSION).unwrap_or(false)
+        })
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+
+    let mut profiles = WriteProfile::builtin();
+    for path in paths {
+        let profile = WriteProfile::load(&path).map_err(|err| {
+            io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+        })?;
+        match profiles.iter_mut().find(|other| other.name == profile.name) {
+            Some(other) => *other = profile,
+            None => profiles.push(profile),
+        }
+    }
+
+    Ok(profiles)
+}
+
+pub fn find_profile(name: &str) -> Result<WriteProfile, io::Error> {
+    list_profiles()?
+        .into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or(io::Error::new(io::ErrorKind::NotFound, format!("No profile named {}", name)))
+}
+
+pub fn load_default_profile() -> Option<String> {
+    let path = config_dir()?.join(DEFAULT_PROFILE_FILE);
+    let name = fs::read_to_string(path).ok()?.trim().to_string();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+pub fn save_default_profile(name: Option<&str>) -> Result<(), io::Error> {
+    let dir = config_dir()
+        .ok_or(io::Error::new(io::ErrorKind::NotFound, "No config directory"))?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(DEFAULT_PROFILE_FILE);
+    match name {
+        Some(name) => fs::write(path, format!("{}\n", name)),
+        None if path.exists() => fs::remove_file(path),
+        None => Ok(()),
+    }
+}
+
+// Everything the profile would change or leave out, for each file about to
+// be written
+pub fn profile_report(profile: &WriteProfile, tags: &[(PathBuf, Tag)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (path, tag) in tags {
+        let container = match Container::from_path(path) {
+            Some(container) if container.has_id3() => container,
+            _ => continue,
+        };
+        let (_, issues) = profile.convert(tag, container);
+        for issue in issues {
+            findings.push(Finding::new(path, issue));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::frame::PictureType;
+
+    use super::*;
+    use crate::tags::*;
+    use crate::testutil::*;
+
+    fn count(tag: &Tag, id: &str) -> usize {
+        tag.frames().filter(|frame| frame.id() == id).count()
+    }
+
+    #[test]
+    fn conversion_keeps_repeated_frames() {
+        let mut tag = Tag::with_version(Version::Id3v24);
+        set_frame_value(&mut tag, "RVA2:track", "-4.25 dB");
+        set_frame_value(&mut tag, "RVA2:album", "-5.00 dB");
+        for description in ["front", "back of the booklet"] {
+            tag.extend([Frame::with_content("APIC", Content::Picture(Picture {
+                mime_type: "image/jpeg".to_string(),
+                picture_type: PictureType::CoverFront,
+                description: description.to_string(),
+                data: vec![0; 4],
+            }))]);
+        }
+
+        let [modern, legacy] = &WriteProfile::builtin()[..] else { unreachable!() };
+        let (converted, _) = modern.convert(&tag, Container::Mpeg);
+        assert_eq!((count(&converted, "RVA2"), count(&converted, "APIC")), (2, 2));
+
+        let (converted, issues) = legacy.convert(&tag, Container::Mpeg);
+        assert_eq!((count(&converted, "RVA2"), count(&converted, "APIC")), (0, 2));
+        assert!(issues.iter().any(|issue| issue.starts_with("RVA2 left out")));
+    }
+
+    #[test]
+    fn id3v1_tags_are_replaced_not_stacked() {
+        let audio = mpeg_frames(2);
+        let path = scratch_file("track.mp3", &audio);
+        let legacy = &WriteProfile::builtin()[1];
+        let mut tag = Tag::with_version(Version::Id3v23);
+        tag.set_title("A title much longer than thirty characters");
+        tag.set_track(300);
+
+        let (bytes, issues) = id3v1_bytes(&tag);
+        assert_eq!(bytes.len(), ID3V1_LEN);
+        assert_eq!(&bytes[3..33], b"A title much longer than thirt");
+        assert_eq!((bytes[125], bytes[126], bytes[127]), (0, 0, 255));
+        assert_eq!(issues.len(), 2);
+
+        write_tag_as(&tag, &path, Some(legacy)).unwrap();
+        tag.set_title("Short");
+        write_tag_as(&tag, &path, Some(legacy)).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let (rest, v1) = data.split_at(data.len() - ID3V1_LEN);
+        assert!(rest.ends_with(&audio));
+        assert_eq!(&v1[..8], b"TAGShort");
+        assert_eq!(Tag::read_from_path(&path).unwrap().title(), Some("Short"));
+    }
+}
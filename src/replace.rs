@@ -0,0 +1,98 @@
+use id3::Tag;
+use regex::Regex;
+use std::path::PathBuf;
+
+use crate::input::*;
+use crate::preview::*;
+use crate::tags::*;
+
+pub fn replace_form() -> InputForm {
+    let mut form = InputForm::new(
+        "Find and Replace",
+        "Frames: ID list or * | Tab: next field | Enter: preview",
+        &[("Frames", "*"), ("Find", ""), ("Replace", "")]
+    );
+    form.focus = 1;
+
+    form
+}
+
+// Runs the regex over every matching text frame of every tag, collecting only
+// the values that would actually change
+pub fn find_and_replace(
+    tags: &[(PathBuf, Tag)],
+    form: &InputForm
+) -> Result<Vec<FileDiff>, regex::Error> {
+    let filter = FrameFilter::parse(form.value(0));
+    let regex = Regex::new(form.value(1))?;
+    let replacement = form.value(2);
+
+    let diffs = tags.iter()
+        .map(|(path, tag)| {
+            let changes = text_frames(tag, &filter)
+                .into_iter()
+                .filter_map(|(id, old)| {
+                    let new = regex.replace_all(&old, replacement).to_string();
+                    if new != old {
+                        Some(FrameChange { id, old: Some(old), new })
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            FileDiff { path: path.clone(), changes, warning: None }
+        })
+        .collect();
+
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+
+    fn form(frames: &str, find: &str, replace: &str) -> InputForm {
+        let mut form = replace_form();
+        form.inputs[0] = TextInput::from(frames.to_string());
+        form.inputs[1] = TextInput::from(find.to_string());
+        form.inputs[2] = TextInput::from(replace.to_string());
+
+        form
+    }
+
+    fn tags() -> Vec<(PathBuf, Tag)> {
+        let mut tag = Tag::new();
+        tag.set_artist("Beatles, The");
+        tag.set_title("Help!");
+        vec![(PathBuf::from("a.mp3"), tag)]
+    }
+
+    #[test]
+    fn capture_groups_are_substituted() {
+        let diffs = find_and_replace(&tags(), &form("*", r"^(.+), (The|A)$", "$2 $1")).unwrap();
+
+        assert_eq!(diffs[0].changes.len(), 1);
+        assert_eq!(diffs[0].changes[0].id, "TPE1");
+        assert_eq!(diffs[0].changes[0].new, "The Beatles");
+
+        let diffs = find_and_replace(&tags(), &form("TIT2", r"(?P<word>\w+)!", "${word}?")).unwrap();
+        assert_eq!(diffs[0].changes[0].new, "Help?");
+    }
+
+    #[test]
+    fn only_the_chosen_frames_change() {
+        let diffs = find_and_replace(&tags(), &form("TIT2", "e", "E")).unwrap();
+
+        assert_eq!(diffs[0].changes.len(), 1);
+        assert_eq!(diffs[0].changes[0].new, "HElp!");
+    }
+
+    #[test]
+    fn invalid_regexes_are_reported() {
+        assert!(find_and_replace(&tags(), &form("*", "(unclosed", "")).is_err());
+        assert!(find_and_replace(&tags(), &form("*", "*", "")).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+use id3::{
+    Tag,
+    Version,
+};
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use crate::decode::*;
+use crate::input::*;
+use crate::preview::*;
+use crate::tags::*;
+use crate::worker::*;
+
+// ReplayGain 2.0 targets -18 LUFS
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+pub fn replaygain_form() -> InputForm {
+    InputForm::new(
+        "ReplayGain",
+        "Album: directory/album/none | RVA2: yes/no | Enter: analyse",
+        &[("Album", "directory"), ("RVA2", "no")]
+    )
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AlbumGrouping {
+    Directory,
+    Album,
+    None,
+}
+
+impl AlbumGrouping {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "directory" | "dir" => Some(AlbumGrouping::Directory),
+            "album" => Some(AlbumGrouping::Album),
+            "none" | "" => Some(AlbumGrouping::None),
+            _ => None,
+        }
+    }
+
+    fn key(&self, path: &Path, tag: &Tag) -> Option<String> {
+        match self {
+            AlbumGrouping::Directory => {
+                path.parent().map(|parent| parent.to_string_lossy().to_string())
+            },
+            AlbumGrouping::Album => {
+                let album = text_value(tag, "TALB")?;
+                let artist = text_value(tag, "TPE2").unwrap_or_default();
+                Some(format!("{}\u{1}{}", artist, album))
+            },
+            AlbumGrouping::None => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[1] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[2] * y;
+        y
+    }
+}
+
+// The two stage K-weighting filter from ITU-R BS.1770, with coefficients
+// derived for any sample rate
+fn k_weighting(rate: u32) -> [Biquad; 2] {
+    let rate = rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+// Measures integrated loudness as in EBU R128: 400 ms blocks overlapping by
+// 75%, built from 100 ms sub-blocks of K-weighted mean square energy
+struct LoudnessMeter {
+    filters: Vec<[Biquad; 2]>,
+    sub_block_len: usize,
+    sub_block_pos: usize,
+    sub_block_sum: f64,
+    sub_blocks: Vec<f64>,
+    blocks: Vec<f64>,
+    peak: f32,
+}
+
+impl LoudnessMeter {
+    fn new(channels: usize, rate: u32) -> Self {
+        Self {
+            filters: vec![k_weighting(rate); channels],
+            sub_block_len: (rate / 10).max(1) as usize,
+            sub_block_pos: 0,
+            sub_block_sum: 0.0,
+            sub_blocks: Vec::new(),
+            blocks: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    fn add(&mut self, samples: &[f32]) {
+        let channels = self.filters.len();
+        for frame in samples.chunks_exact(channels) {
+            for (sample, filters) in frame.iter().zip(self.filters.iter_mut()) {
+                self.peak = self.peak.max(sample.abs());
+                let shelved = filters[0].process(*sample as f64);
+                let weighted = filters[1].process(shelved);
+                self.sub_block_sum += weighted * weighted;
+            }
+
+            self.sub_block_pos += 1;
+            if self.sub_block_pos == self.sub_block_len {
+                self.sub_blocks.push(self.sub_block_sum / self.sub_block_len as f64);
+                self.sub_block_pos = 0;
+                self.sub_block_sum = 0.0;
+
+                let n = self.sub_blocks.len();
+                if n >= 4 {
+                    self.blocks.push(self.sub_blocks[n - 4..].iter().sum::<f64>() / 4.0);
+                }
+            }
+        }
+    }
+}
+
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Integrated loudness with the absolute (-70 LUFS) and relative (-10 LU)
+// gates applied
+fn gated_loudness(blocks: &[f64]) -> Option<f64> {
+    let blocks = blocks
+        .iter()
+        .copied()
+        .filter(|energy| loudness(*energy) > -70.0)
+        .collect::<Vec<f64>>();
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let threshold = loudness(mean(&blocks)) - 10.0;
+    let gated = blocks
+        .into_iter()
+        .filter(|energy| loudness(*energy) > threshold)
+        .collect::<Vec<f64>>();
+    if gated.is_empty() {
+        return None;
+    }
+
+    Some(loudness(mean(&gated)))
+}
+
+pub struct TrackAnalysis {
+    blocks: Vec<f64>,
+    peak: f32,
+}
+
+fn analyse_track(path: &Path, cancel: &AtomicBool) -> Result<TrackAnalysis, String> {
+    let mut meter: Option<LoudnessMeter> = None;
+    decode_file(path, |samples, channels, rate| {
+        meter
+            .get_or_insert_with(|| LoudnessMeter::new(channels, rate))
+            .add(samples);
+        !cancel.load(Ordering::Relaxed)
+    })?;
+
+    let meter = meter.ok_or("No audio decoded".to_string())?;
+    Ok(TrackAnalysis { blocks: meter.blocks, peak: meter.peak })
+}
+
+pub struct GainJob {
+    pub job: Job<TrackAnalysis>,
+    albums: HashMap<PathBuf, String>,
+    tags: HashMap<PathBuf, Tag>,
+    rva2: bool,
+}
+
+impl GainJob {
+    pub fn start(tags: Vec<(PathBuf, Tag)>, grouping: AlbumGrouping, rva2: bool) -> Self {
+        let paths = tags.iter().map(|(path, _)| path.clone()).collect::<Vec<PathBuf>>();
+        let albums = tags
+            .iter()
+            .filter_map(|(path, tag)| Some((path.clone(), grouping.key(path, tag)?)))
+            .collect();
+
+        Self {
+            job: Job::start(paths, analyse_track),
+            albums,
+            tags: tags.into_iter().collect(),
+            rva2,
+        }
+    }
+
+    pub fn diffs(&self) -> Vec<FileDiff> {
+        let mut album_blocks: HashMap<&String, Vec<f64>> = HashMap::new();
+        let mut album_peaks: HashMap<&String, f32> = HashMap::new();
+        for (path, result) in self.job.results.iter() {
+            if let (Ok(analysis), Some(album)) = (result, self.albums.get(path)) {
+                album_blocks.entry(album).or_default().extend(analysis.blocks.iter());
+                let peak = album_peaks.entry(album).or_default();
+                *peak = peak.max(analysis.peak);
+            }
+        }
+
+        self.job.results
+            .iter()
+            .map(|(path, result)| {
+                let analysis = match result {
+                    Ok(analysis) => analysis,
+                    Err(err) => {
+                        return FileDiff {
+                            path: path.clone(),
+                            changes: Vec::new(),
+                            warning: Some(err.clone()),
+                        };
+                    }
+                };
+                let track_gain = match gated_loudness(&analysis.blocks) {
+                    Some(loudness) => REFERENCE_LOUDNESS - loudness,
+                    None => {
+                        return FileDiff {
+                            path: path.clone(),
+                            changes: Vec::new(),
+                            warning: Some("too quiet to measure".to_string()),
+                        };
+                    }
+                };
+
+                let mut values = vec![
+                    ("TXXX:REPLAYGAIN_TRACK_GAIN", format_gain(track_gain)),
+                    ("TXXX:REPLAYGAIN_TRACK_PEAK", format_peak(analysis.peak)),
+                ];
+                // ID3v2.3 has no RVA2 frame
+                let tag = self.tags.get(path);
+                let mut warning = None;
+                if self.rva2 {
+                    match tag.map(|tag| tag.version()) {
+                        Some(Version::Id3v24) | None => {
+                            values.push(("RVA2:track", format_gain(track_gain)));
+                        },
+                        Some(_) => warning = Some("RVA2 needs an ID3v2.4 tag, left out".to_string()),
+                    }
+                }
+
+                let album = self.albums.get(path);
+                let album_gain = album
+                    .and_then(|album| gated_loudness(&album_blocks[album]))
+                    .map(|loudness| REFERENCE_LOUDNESS - loudness);
+                if let (Some(album), Some(album_gain)) = (album, album_gain) {
+                    values.push(("TXXX:REPLAYGAIN_ALBUM_GAIN", format_gain(album_gain)));
+                    values.push(("TXXX:REPLAYGAIN_ALBUM_PEAK", format_peak(album_peaks[album])));
+                }
+
+                let changes = values
+                    .into_iter()
+                    .filter_map(|(key, new)| {
+                        let old = tag.and_then(|tag| frame_value_for_key(tag, key));
+                        if old.as_deref() == Some(new.as_str()) {
+                            return None;
+                        }
+                        Some(FrameChange { id: key.to_string(), old, new })
+                    })
+                    .collect();
+
+                FileDiff { path: path.clone(), changes, warning }
+            })
+            .collect()
+    }
+}
+
+fn format_gain(gain: f64) -> String {
+    format!("{:+.2} dB", gain)
+}
+
+fn format_peak(peak: f32) -> String {
+    format!("{:.6}", peak)
+}
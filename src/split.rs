@@ -0,0 +1,195 @@
+use id3::Tag;
+use regex::Regex;
+use std::path::PathBuf;
+
+use crate::input::*;
+use crate::mpeg::*;
+use crate::preview::*;
+use crate::tags::*;
+
+pub fn split_form() -> InputForm {
+    let mut form = InputForm::new(
+        "Split Field",
+        "%field% placeholders or (?P<field>...) regex | Enter: preview",
+        &[("Source", "TIT2"), ("Pattern", "%artist% - %title%")]
+    );
+    form.focus = 1;
+
+    form
+}
+
+// Turns a pattern such as "%artist% - %title%" into an anchored regex with a
+// named group per placeholder. Anything that isn't a placeholder is matched
+// literally.
+fn pattern_to_regex(pattern: &str) -> Result<Regex, String> {
+    let mut regex = String::from("^");
+    let mut last = 0;
+
+    for captures in placeholder_regex().captures_iter(pattern) {
+        let whole = captures.get(0).unwrap();
+        regex.push_str(&regex::escape(&pattern[last..whole.start()]));
+        regex.push_str(&format!("(?P<{}>.+?)", &captures[1]));
+        last = whole.end();
+    }
+    regex.push_str(&regex::escape(&pattern[last..]));
+    regex.push('$');
+
+    Regex::new(&regex).map_err(|err| err.to_string())
+}
+
+fn parse_pattern(pattern: &str) -> Result<Regex, String> {
+    if placeholder_regex().is_match(pattern) {
+        pattern_to_regex(pattern)
+    } else {
+        Regex::new(pattern).map_err(|err| err.to_string())
+    }
+}
+
+pub fn split_field(
+    tags: &[(PathBuf, Tag)],
+    form: &InputForm
+) -> Result<Vec<FileDiff>, String> {
+    let source = frame_for_field(form.value(0).trim())
+        .ok_or(format!("'{}' is not a text frame", form.value(0).trim()))?;
+    let regex = parse_pattern(form.value(1))?;
+
+    let mut targets = Vec::new();
+    for name in regex.capture_names().flatten() {
+        if is_stream_field(name) {
+            return Err(format!("'{}' is a read-only stream field", name));
+        }
+        let id = frame_for_field(name).ok_or(format!("Unknown field '{}'", name))?;
+        targets.push((name, id));
+    }
+    if targets.is_empty() {
+        return Err("Pattern has no fields to capture".to_string());
+    }
+
+    let diffs = tags.iter()
+        .map(|(path, tag)| {
+            let value = match text_frames(tag, &FrameFilter::Only(vec![source.clone()])).pop() {
+                Some((_, value)) => value,
+                None => {
+                    return FileDiff {
+                        path: path.clone(),
+                        changes: Vec::new(),
+                        warning: Some(format!("no {} frame", source)),
+                    };
+                }
+            };
+
+            let captures = match regex.captures(&value) {
+                Some(captures) => captures,
+                None => {
+                    return FileDiff {
+                        path: path.clone(),
+                        changes: Vec::new(),
+                        warning: Some("pattern did not match".to_string()),
+                    };
+                }
+            };
+
+            let changes = targets
+                .iter()
+                .filter_map(|(name, id)| {
+                    let new = captures.name(name)?.as_str().trim().to_string();
+                    let old = text_frames(tag, &FrameFilter::Only(vec![id.clone()]))
+                        .pop()
+                        .map(|(_, old)| old);
+                    if old.as_ref() != Some(&new) && !new.is_empty() {
+                        Some(FrameChange { id: id.clone(), old, new })
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            FileDiff { path: path.clone(), changes, warning: None }
+        })
+        .collect();
+
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use id3::TagLike;
+
+    use super::*;
+
+    fn titles(titles: &[&str]) -> Vec<(PathBuf, Tag)> {
+        titles
+            .iter()
+            .enumerate()
+            .map(|(i, title)| {
+                let mut tag = Tag::new();
+                tag.set_title(*title);
+                (PathBuf::from(format!("{}.mp3", i)), tag)
+            })
+            .collect()
+    }
+
+    fn form(source: &str, pattern: &str) -> InputForm {
+        let mut form = split_form();
+        form.inputs[0] = TextInput::from(source.to_string());
+        form.inputs[1] = TextInput::from(pattern.to_string());
+
+        form
+    }
+
+    fn changes(diff: &FileDiff) -> Vec<(&str, &str)> {
+        diff.changes
+            .iter()
+            .map(|change| (change.id.as_str(), change.new.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn placeholders_split_a_field_literally() {
+        let tags = titles(&["Björk - Jóga", "Massive Attack - Teardrop (Live)", "No separator"]);
+        let diffs = split_field(&tags, &form("title", "%artist% - %title%")).unwrap();
+
+        assert_eq!(changes(&diffs[0]), vec![("TPE1", "Björk"), ("TIT2", "Jóga")]);
+        assert_eq!(changes(&diffs[1]), vec![("TPE1", "Massive Attack"), ("TIT2", "Teardrop (Live)")]);
+        assert!(diffs[2].changes.is_empty());
+        assert_eq!(diffs[2].warning.as_deref(), Some("pattern did not match"));
+    }
+
+    #[test]
+    fn pattern_text_is_escaped_around_placeholders() {
+        let tags = titles(&["01. Intro [2001]", "01x Intro [2001]"]);
+        let diffs = split_field(&tags, &form("TIT2", "%track%. %title% [%year%]")).unwrap();
+
+        assert_eq!(changes(&diffs[0]), vec![("TRCK", "01"), ("TIT2", "Intro"), ("TYER", "2001")]);
+        assert!(diffs[1].changes.is_empty());
+    }
+
+    #[test]
+    fn named_groups_work_as_a_regex_pattern() {
+        let tags = titles(&["Teardrop (feat. Liz Fraser)"]);
+        let pattern = r"^(?P<title>.+?) \(feat\. (?P<composer>[^)]+)\)$";
+        let diffs = split_field(&tags, &form("TIT2", pattern)).unwrap();
+
+        assert_eq!(changes(&diffs[0]), vec![("TIT2", "Teardrop"), ("TCOM", "Liz Fraser")]);
+    }
+
+    #[test]
+    fn files_without_the_source_frame_get_a_warning() {
+        let tags = vec![(PathBuf::from("a.mp3"), Tag::new())];
+        let diffs = split_field(&tags, &form("TIT2", "%artist% - %title%")).unwrap();
+
+        assert_eq!(diffs[0].warning.as_deref(), Some("no TIT2 frame"));
+    }
+
+    #[test]
+    fn bad_patterns_are_rejected() {
+        let tags = titles(&["A - B"]);
+        let error = |source, pattern| split_field(&tags, &form(source, pattern)).err().unwrap();
+
+        assert_eq!(error("TIT2", "%artist% - %bitrate%"), "'bitrate' is a read-only stream field");
+        assert_eq!(error("TIT2", "%artist% - %mood%"), "Unknown field 'mood'");
+        assert_eq!(error("TIT2", "(.+) - (.+)"), "Pattern has no fields to capture");
+        assert_eq!(error("nothing", "%title%"), "'nothing' is not a text frame");
+        assert!(split_field(&tags, &form("TIT2", "(?P<title>")).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use id3::{
+    frame::{
+        Comment,
+        ExtendedText,
+        Unknown,
+    },
+    Content,
+    Frame,
+    Tag,
+    TagLike,
+    Version,
+};
+use regex::{
+    Captures,
+    Regex,
+};
+use std::sync::OnceLock;
+
+use crate::mpeg::*;
+
+// Which frames a bulk operation should touch, parsed from user input such as
+// "TIT2", "TIT2, TALB" or "*"
+pub enum FrameFilter {
+    AllText,
+    Only(Vec<String>),
+}
+
+impl FrameFilter {
+    pub fn parse(input: &str) -> Self {
+        let input = input.trim();
+        if input.is_empty() || input == "*" || input.eq_ignore_ascii_case("all") {
+            return FrameFilter::AllText;
+        }
+
+        let ids = input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_uppercase())
+            .collect::<Vec<String>>();
+
+        FrameFilter::Only(ids)
+    }
+
+    pub fn matches(&self, id: &str) -> bool {
+        match self {
+            FrameFilter::AllText => id.starts_with('T') && id != "TXXX",
+            FrameFilter::Only(ids) => ids.iter().any(|i| i == id),
+        }
+    }
+}
+
+// A one-line, human readable rendering of a frame's content
+pub fn frame_value(frame: &Frame) -> String {
+    match frame.content() {
+        // TODO: Handle 'USLT' frame (Unsynced Lyrics)
+        // TODO: TCON (Genre) strips out slashes, which is not ideal
+        Content::Text(text) => text.to_string(),
+        Content::Comment(comment) => comment.text.to_string(),
+        Content::ExtendedText(extended) => {
+            format!("{}: {}", extended.description, extended.value)
+        },
+        Content::Picture(picture) => {
+            // TODO: Handle empty picture description
+            format!("{} <{}>", picture.description, picture.mime_type)
+        },
+        _ => "<other>".to_string()
+    }
+}
+
+// Returns (frame id, value) for every text frame in the tag that the filter
+// accepts
+pub fn text_frames(tag: &Tag, filter: &FrameFilter) -> Vec<(String, String)> {
+    tag.frames()
+        .filter(|frame| filter.matches(frame.id()))
+        .filter_map(|frame| {
+            if let Content::Text(text) = frame.content() {
+                Some((frame.id().to_string(), text.to_string()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Named fields usable in patterns, e.g. %artist% or (?P<artist>...)
+pub const FIELD_NAMES: [(&str, &str); 13] = [
+    ("title", "TIT2"),
+    ("artist", "TPE1"),
+    ("album", "TALB"),
+    ("albumartist", "TPE2"),
+    ("track", "TRCK"),
+    ("disc", "TPOS"),
+    ("year", "TYER"),
+    ("genre", "TCON"),
+    ("composer", "TCOM"),
+    ("bpm", "TBPM"),
+    ("isrc", "TSRC"),
+    ("encoder", "TSSE"),
+    ("publisher", "TPUB"),
+];
+
+// Resolves a field name or a raw frame ID (e.g. "TIT2") to a text frame ID
+pub fn frame_for_field(name: &str) -> Option<String> {
+    let lower = name.to_lowercase();
+    if let Some((_, id)) = FIELD_NAMES.iter().find(|(field, _)| *field == lower) {
+        return Some(id.to_string());
+    }
+
+    let upper = name.to_uppercase();
+    if upper.len() == 4
+        && upper.starts_with('T')
+        && upper.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    {
+        Some(upper)
+    } else {
+        None
+    }
+}
+
+// Expands %field% placeholders with values from the given tag, or from the
+// file's stream info for stream fields such as %bitrate%. Unknown
+// placeholders are left as they are; missing values become empty.
+pub fn expand_placeholders(text: &str, tag: &Tag, stream: Option<&StreamInfo>) -> String {
+    placeholder_regex()
+        .replace_all(text, |captures: &Captures| {
+            if is_stream_field(&captures[1]) {
+                return stream
+                    .and_then(|stream| stream.field(&captures[1]))
+                    .unwrap_or_default();
+            }
+
+            match frame_for_field(&captures[1]) {
+                Some(id) if id == "TYER" => year_value(tag).unwrap_or_default(),
+                Some(id) => tag.get(&id)
+                    .and_then(|frame| frame.content().text())
+                    .map(|text| text.replace('\0', "/"))
+                    .unwrap_or_default(),
+                None => captures[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+// Whether any %field% placeholder in the text needs the file's stream info
+pub fn uses_stream_fields(text: &str) -> bool {
+    placeholder_regex()
+        .captures_iter(text)
+        .any(|captures| is_stream_field(&captures[1]))
+}
+
+// Matches a %field% placeholder, capturing the field name
+pub fn placeholder_regex() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| Regex::new(r"%(\w+)%").unwrap())
+}
+
+// Frames that can be expressed as a simple key and value: text frames keyed
+// by ID, the comment without a description as "COMM", user text frames as
+// "TXXX:<desc>" and the master volume of RVA2 frames as
+// "RVA2:<identification>"
+pub fn frame_key_values(tag: &Tag) -> Vec<(String, String)> {
+    let mut values = text_frames(tag, &FrameFilter::AllText);
+    // Described comments, such as iTunNORM, hold data rather than a comment
+    if let Some(comment) = tag.comments().find(|comment| comment.description.is_empty()) {
+        values.push(("COMM".to_string(), comment.text.clone()));
+    }
+    for extended in tag.extended_texts() {
+        values.push((format!("TXXX:{}", extended.description), extended.value.clone()));
+    }
+    for (identification, gain) in rva2_gains(tag) {
+        values.push((format!("RVA2:{}", identification), format!("{:+.2} dB", gain)));
+    }
+
+    values
+}
+
+pub fn frame_value_for_key(tag: &Tag, key: &str) -> Option<String> {
+    if is_v24_year(tag, key) {
+        return year_value(tag);
+    }
+
+    frame_key_values(tag)
+        .into_iter()
+        .find(|(other, _)| other == key)
+        .map(|(_, value)| value)
+}
+
+// Inverse of frame_key_values
+pub fn set_frame_value(tag: &mut Tag, key: &str, value: &str) {
+    if let Some(identification) = key.strip_prefix("RVA2:") {
+        let gain = value
+            .trim()
+            .trim_end_matches("dB")
+            .trim()
+            .parse::<f64>()
+            .unwrap_or(0.0);
+        remove_frame_value(tag, key);
+        // add_frame would replace every other RVA2 frame as well
+        tag.extend([rva2_frame(identification, gain)]);
+    } else if key == "COMM" {
+        let lang = tag
+            .comments()
+            .find(|comment| comment.description.is_empty())
+            .map(|comment| comment.lang.clone())
+            .unwrap_or_else(|| "eng".to_string());
+        tag.remove_comment(Some(""), None);
+        tag.add_frame(Comment {
+            lang,
+            description: String::new(),
+            text: value.to_string(),
+        });
+    } else if let Some(description) = key.strip_prefix("TXXX:") {
+        tag.remove_extended_text(Some(description), None);
+        tag.add_frame(ExtendedText {
+            description: description.to_string(),
+            value: value.to_string(),
+        });
+    } else if is_v24_year(tag, key) {
+        tag.remove("TYER");
+        tag.set_text("TDRC", value);
+    } else {
+        tag.set_text(key, value);
+    }
+}
+
+// ID3v2.4 replaced TYER with TDRC, which is also where the date of a FLAC, Ogg
+// or MP4 file ends up
+fn is_v24_year(tag: &Tag, key: &str) -> bool {
+    key == "TYER" && tag.version() == Version::Id3v24
+}
+
+pub fn remove_frame_value(tag: &mut Tag, key: &str) {
+    if let Some(description) = key.strip_prefix("TXXX:") {
+        tag.remove_extended_text(Some(description), None);
+    } else if key == "COMM" {
+        tag.remove_comment(Some(""), None);
+    } else if is_v24_year(tag, key) {
+        tag.remove("TYER");
+        tag.remove("TDRC");
+    } else if let Some(identification) = key.strip_prefix("RVA2:") {
+        let others = tag
+            .remove("RVA2")
+            .into_iter()
+            .filter(|frame| rva2_identification(frame).as_deref() != Some(identification))
+            .collect::<Vec<Frame>>();
+        tag.extend(others);
+    } else {
+        tag.remove(key);
+    }
+}
+
+// RVA2 frames hold an identification string followed by per-channel volume
+// adjustments in 1/512 dB steps. Only the master volume channel is used.
+// The id3 crate treats RVA2 as an opaque frame, and add_frame keeps only one
+// of them per tag.
+fn rva2_identification(frame: &Frame) -> Option<String> {
+    let data = &frame.content().to_unknown().ok()?.data;
+    let end = data.iter().position(|byte| *byte == 0)?;
+
+    Some(data[..end].iter().map(|byte| *byte as char).collect())
+}
+
+fn rva2_gain(frame: &Frame) -> Option<(String, f64)> {
+    let identification = rva2_identification(frame)?;
+    let data = &frame.content().to_unknown().ok()?.data;
+    let end = data.iter().position(|byte| *byte == 0)?;
+
+    let mut pos = end + 1;
+    while pos + 4 <= data.len() {
+        let channel = data[pos];
+        let adjustment = i16::from_be_bytes([data[pos + 1], data[pos + 2]]);
+        let peak_bytes = (data[pos + 3] as usize).div_ceil(8);
+        if channel == 1 {
+            return Some((identification, adjustment as f64 / 512.0));
+        }
+        pos += 4 + peak_bytes;
+    }
+
+    None
+}
+
+fn rva2_gains(tag: &Tag) -> Vec<(String, f64)> {
+    tag.frames()
+        .filter(|frame| frame.id() == "RVA2")
+        .filter_map(rva2_gain)
+        .collect()
+}
+
+fn rva2_frame(identification: &str, gain: f64) -> Frame {
+    let mut data = identification.bytes().collect::<Vec<u8>>();
+    data.push(0);
+    data.push(1);
+    data.extend(((gain * 512.0).round() as i16).to_be_bytes());
+    data.push(0);
+
+    Frame::with_content("RVA2", Content::Unknown(Unknown { data, version: Version::Id3v24 }))
+}
+
+pub fn text_value(tag: &Tag, id: &str) -> Option<String> {
+    tag.get(id)
+        .and_then(|frame| frame.content().text())
+        .map(|text| text.to_string())
+}
+
+// The year of a tag: TYER in ID3v2.3 tags, the start of TDRC in v2.4 ones and
+// in tags read from other formats
+pub fn year_value(tag: &Tag) -> Option<String> {
+    text_value(tag, "TYER").or_else(|| {
+        text_value(tag, "TDRC").map(|date| date.chars().take(4).collect())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(lang: &str, description: &str, text: &str) -> Comment {
+        Comment {
+            lang: lang.to_string(),
+            description: description.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn comment_key_ignores_described_comments() {
+        let mut tag = Tag::new();
+        tag.add_frame(comment("eng", "iTunNORM", " 000001F4 00000200"));
+        assert_eq!(frame_value_for_key(&tag, "COMM"), None);
+
+        tag.add_frame(comment("deu", "", "Live"));
+        assert_eq!(frame_value_for_key(&tag, "COMM"), Some("Live".to_string()));
+    }
+
+    #[test]
+    fn setting_comment_keeps_other_comments_and_lang() {
+        let mut tag = Tag::new();
+        tag.add_frame(comment("eng", "iTunNORM", " 000001F4 00000200"));
+        tag.add_frame(comment("eng", "iTunSMPB", " 00000000 00000210"));
+        tag.add_frame(comment("deu", "", "Live"));
+
+        set_frame_value(&mut tag, "COMM", "Studio");
+        let comments = tag.comments().collect::<Vec<&Comment>>();
+        assert_eq!(comments.len(), 3);
+        let plain = comments.iter().find(|comment| comment.description.is_empty()).unwrap();
+        assert_eq!((plain.lang.as_str(), plain.text.as_str()), ("deu", "Studio"));
+
+        remove_frame_value(&mut tag, "COMM");
+        assert_eq!(tag.comments().count(), 2);
+    }
+
+    #[test]
+    fn track_and_album_rva2_coexist() {
+        let mut tag = Tag::new();
+        set_frame_value(&mut tag, "RVA2:album", "-5.00 dB");
+        set_frame_value(&mut tag, "RVA2:track", "-4.25 dB");
+        set_frame_value(&mut tag, "RVA2:track", "-3.50 dB");
+
+        assert_eq!(frame_value_for_key(&tag, "RVA2:album"), Some("-5.00 dB".to_string()));
+        assert_eq!(frame_value_for_key(&tag, "RVA2:track"), Some("-3.50 dB".to_string()));
+        assert_eq!(tag.frames().filter(|frame| frame.id() == "RVA2").count(), 2);
+
+        remove_frame_value(&mut tag, "RVA2:track");
+        assert_eq!(frame_value_for_key(&tag, "RVA2:album"), Some("-5.00 dB".to_string()));
+        assert_eq!(frame_value_for_key(&tag, "RVA2:track"), None);
+    }
+
+    #[test]
+    fn year_edits_go_to_tdrc_in_v24_tags() {
+        let mut tag = Tag::with_version(Version::Id3v24);
+        tag.set_text("TDRC", "2001-05-04");
+        assert_eq!(frame_value_for_key(&tag, "TYER"), Some("2001".to_string()));
+
+        set_frame_value(&mut tag, "TYER", "1999");
+        assert_eq!((text_value(&tag, "TYER"), text_value(&tag, "TDRC")), (None, Some("1999".to_string())));
+        remove_frame_value(&mut tag, "TYER");
+        assert_eq!(year_value(&tag), None);
+
+        let mut tag = Tag::with_version(Version::Id3v23);
+        set_frame_value(&mut tag, "TYER", "1999");
+        assert_eq!(text_value(&tag, "TYER"), Some("1999".to_string()));
+    }
+}
@@ -0,0 +1,148 @@
+use id3::Tag;
+use std::{
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::config::*;
+use crate::mpeg::*;
+use crate::tags::*;
+
+const TEMPLATE_EXTENSION: &str = "template";
+
+// A named set of frame values, stored one "KEY=value" pair per line. Values
+// may contain %field% placeholders, filled in from each file's own tag.
+pub struct Template {
+    pub name: String,
+    pub values: Vec<(String, String)>,
+}
+
+impl Template {
+    pub fn from_tag(name: String, tag: &Tag) -> Self {
+        Self {
+            name,
+            values: frame_key_values(tag),
+        }
+    }
+
+    pub fn load(name: &str) -> Result<Self, io::Error> {
+        let contents = fs::read_to_string(template_path(name)?)?;
+        let values = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), unescape(value)))
+            .collect();
+
+        Ok(Self { name: name.to_string(), values })
+    }
+
+    pub fn save(&self) -> Result<(), io::Error> {
+        let mut contents = String::from("# rime tag template\n");
+        for (key, value) in self.values.iter() {
+            contents.push_str(&format!("{}={}\n", key, escape(value)));
+        }
+
+        fs::write(template_path(&self.name)?, contents)
+    }
+
+    pub fn apply(&self, tag: &mut Tag, path: &Path) {
+        let original = tag.clone();
+        let stream = if self.values.iter().any(|(_, value)| uses_stream_fields(value)) {
+            read_stream_info(path).ok()
+        } else {
+            None
+        };
+        for (key, value) in self.values.iter() {
+            set_frame_value(tag, key, &expand_placeholders(value, &original, stream.as_ref()));
+        }
+    }
+}
+
+pub fn list_templates() -> Result<Vec<String>, io::Error> {
+    let mut names = fs::read_dir(config_subdir("templates")?)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().map(|ext| ext == TEMPLATE_EXTENSION).unwrap_or(false)
+        })
+        .filter_map(|path| {
+            path.file_stem().map(|stem| stem.to_string_lossy().to_string())
+        })
+        .collect::<Vec<String>>();
+    names.sort();
+
+    Ok(names)
+}
+
+pub fn delete_template(name: &str) -> Result<(), io::Error> {
+    fs::remove_file(template_path(name)?)
+}
+
+// Names become file names, so they must not reach outside the templates
+// directory
+pub fn is_valid_template_name(name: &str) -> bool {
+    !name.trim().is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\', '\0'])
+}
+
+fn template_path(name: &str) -> Result<PathBuf, io::Error> {
+    if !is_valid_template_name(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid template name '{}'", name)
+        ));
+    }
+
+    let file_name = format!("{}.{}", name, TEMPLATE_EXTENSION);
+
+    Ok(config_subdir("templates")?.join(file_name))
+}
+
+// Multiple values are separated by NUL, which can't be stored in a line
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\0', "\\0")
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('0') => result.push('\0'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_names_stay_in_templates_directory() {
+        assert!(is_valid_template_name("Bandcamp"));
+        assert!(is_valid_template_name("Label 2024"));
+        assert!(!is_valid_template_name(""));
+        assert!(!is_valid_template_name("../../.bashrc"));
+        assert!(!is_valid_template_name("sub/name"));
+        assert!(!is_valid_template_name(".."));
+        assert!(template_path("../escape").is_err());
+    }
+}
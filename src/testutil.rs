@@ -0,0 +1,42 @@
+use std::{
+    env,
+    fs,
+    path::PathBuf,
+    process,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+// Writes a file into a directory of its own under the system temp directory,
+// so tests running in parallel never share files
+pub fn scratch_file(name: &str, contents: &[u8]) -> PathBuf {
+    let dir = env::temp_dir().join(format!(
+        "rime-test-{}-{}",
+        process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+
+    path
+}
+
+// Length of the frames mpeg_frames builds
+pub const MPEG_FRAME_LEN: usize = 417;
+
+// MPEG-1 layer III frames at 128 kbps, 44.1 kHz, stereo. The body of each
+// frame is filled with its index, so that frames can be told apart.
+pub fn mpeg_frames(count: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    for i in 0..count {
+        data.extend([0xFF, 0xFB, 0x90, 0x00]);
+        data.resize(data.len() + MPEG_FRAME_LEN - 4, i as u8);
+    }
+
+    data
+}
@@ -0,0 +1,473 @@
+use id3::Tag;
+use std::path::PathBuf;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::input::*;
+use crate::preview::*;
+use crate::tags::*;
+
+const DEFAULT_LOWERCASE_WORDS: &str =
+    "a, an, and, as, at, but, by, for, from, in, nor, of, on, or, the, to, vs, with";
+
+pub const TRANSFORM_FIELDS: [&str; 2] = ["Lowercase", "Acronyms"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    TitleCase,
+    SentenceCase,
+    Lower,
+    Upper,
+    Trim,
+    CollapseWhitespace,
+    // Unicode normalisation: composed characters, as most software expects,
+    // or decomposed, as macOS writes them
+    Nfc,
+    Nfd,
+}
+
+pub const TRANSFORMS: [Transform; 8] = [
+    Transform::TitleCase,
+    Transform::SentenceCase,
+    Transform::Lower,
+    Transform::Upper,
+    Transform::Trim,
+    Transform::CollapseWhitespace,
+    Transform::Nfc,
+    Transform::Nfd,
+];
+
+impl Transform {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::TitleCase => "Title",
+            Transform::SentenceCase => "Sentence",
+            Transform::Lower => "lower",
+            Transform::Upper => "UPPER",
+            Transform::Trim => "Trim",
+            Transform::CollapseWhitespace => "Collapse",
+            Transform::Nfc => "NFC",
+            Transform::Nfd => "NFD",
+        }
+    }
+
+    fn is_case(&self) -> bool {
+        !matches!(
+            self,
+            Transform::Trim | Transform::CollapseWhitespace | Transform::Nfc | Transform::Nfd
+        )
+    }
+
+    fn is_normalization(&self) -> bool {
+        matches!(self, Transform::Nfc | Transform::Nfd)
+    }
+
+    // Whitespace first, so case rules see clean word boundaries, and
+    // normalisation last, after anything that could change characters
+    fn order(&self) -> u8 {
+        if self.is_normalization() {
+            2
+        } else if self.is_case() {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+pub struct CaseOptions {
+    pub lowercase_words: Vec<String>,
+    pub acronyms: Vec<String>,
+}
+
+impl CaseOptions {
+    fn parse(lowercase_words: &str, acronyms: &str) -> Self {
+        let split = |list: &str| {
+            list.split(',')
+                .map(|word| word.trim().to_string())
+                .filter(|word| !word.is_empty())
+                .collect::<Vec<String>>()
+        };
+
+        Self {
+            lowercase_words: split(lowercase_words)
+                .into_iter()
+                .map(|word| word.to_lowercase())
+                .collect(),
+            acronyms: split(acronyms),
+        }
+    }
+}
+
+// The transforms chosen for one frame. Case transforms are mutually
+// exclusive, and so are the normalisation forms.
+#[derive(Clone, Default)]
+pub struct TransformChoice {
+    chosen: Vec<Transform>,
+}
+
+impl TransformChoice {
+    pub fn contains(&self, transform: Transform) -> bool {
+        self.chosen.contains(&transform)
+    }
+
+    fn toggle(&mut self, transform: Transform) {
+        if self.contains(transform) {
+            self.chosen.retain(|chosen| *chosen != transform);
+            return;
+        }
+
+        if transform.is_case() || transform.is_normalization() {
+            self.chosen.retain(|chosen| chosen.order() != transform.order());
+        }
+        self.chosen.push(transform);
+    }
+
+    // In the order they are applied
+    fn transforms(&self) -> Vec<Transform> {
+        let mut transforms = self.chosen.clone();
+        transforms.sort_by_key(|transform| transform.order());
+
+        transforms
+    }
+}
+
+pub struct TransformDialog {
+    pub frames: Vec<String>,
+    pub choices: Vec<TransformChoice>,
+    pub row: usize,
+    pub col: usize,
+    pub inputs: [TextInput; 2],
+    // 0 is the transform grid, the rest index into inputs
+    pub focus: usize,
+}
+
+impl TransformDialog {
+    pub fn new() -> Self {
+        Self {
+            frames: Vec::new(),
+            choices: Vec::new(),
+            row: 0,
+            col: 0,
+            inputs: [
+                TextInput::from(DEFAULT_LOWERCASE_WORDS.to_string()),
+                TextInput::new(),
+            ],
+            focus: 0,
+        }
+    }
+
+    // Offer every text frame present in the selection, keeping earlier
+    // choices for frames that are still around
+    pub fn load_frames(&mut self, tags: &[(PathBuf, Tag)]) {
+        let mut frames = tags
+            .iter()
+            .flat_map(|(_, tag)| text_frames(tag, &FrameFilter::AllText))
+            .map(|(id, _)| id)
+            .collect::<Vec<String>>();
+        frames.sort();
+        frames.dedup();
+
+        self.choices = frames
+            .iter()
+            .map(|id| {
+                self.frames
+                    .iter()
+                    .position(|old| old == id)
+                    .map(|i| self.choices[i].clone())
+                    .unwrap_or_default()
+            })
+            .collect();
+        self.frames = frames;
+        self.row = 0;
+        self.col = 0;
+        self.focus = 0;
+    }
+
+    pub fn focused_input(&mut self) -> Option<&mut TextInput> {
+        match self.focus {
+            0 => None,
+            i => Some(&mut self.inputs[i - 1]),
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        self.focus = (self.focus + 1) % (self.inputs.len() + 1);
+    }
+
+    pub fn prev_field(&mut self) {
+        self.focus = (self.focus + self.inputs.len()) % (self.inputs.len() + 1);
+    }
+
+    pub fn move_cursor(&mut self, rows: isize, cols: isize) {
+        if self.frames.is_empty() {
+            return;
+        }
+
+        let row = self.row as isize + rows;
+        let col = self.col as isize + cols;
+        self.row = row.clamp(0, self.frames.len() as isize - 1) as usize;
+        self.col = col.clamp(0, TRANSFORMS.len() as isize - 1) as usize;
+    }
+
+    pub fn toggle(&mut self) {
+        if self.frames.is_empty() {
+            return;
+        }
+
+        self.choices[self.row].toggle(TRANSFORMS[self.col]);
+    }
+
+    pub fn options(&self) -> CaseOptions {
+        CaseOptions::parse(&self.inputs[0].text, &self.inputs[1].text)
+    }
+
+}
+
+pub fn transform_tags(
+    tags: &[(PathBuf, Tag)],
+    dialog: &TransformDialog
+) -> Vec<FileDiff> {
+    let options = dialog.options();
+
+    tags.iter()
+        .map(|(path, tag)| {
+            let mut changes = Vec::new();
+            for (row, id) in dialog.frames.iter().enumerate() {
+                let transforms = dialog.choices[row].transforms();
+                if transforms.is_empty() {
+                    continue;
+                }
+
+                let filter = FrameFilter::Only(vec![id.clone()]);
+                for (id, old) in text_frames(tag, &filter) {
+                    // Multiple values are separated by NUL characters
+                    let new = old
+                        .split('\0')
+                        .map(|value| apply_transforms(value, &transforms, &options))
+                        .collect::<Vec<String>>()
+                        .join("\0");
+                    if new != old {
+                        changes.push(FrameChange { id, old: Some(old), new });
+                    }
+                }
+            }
+
+            FileDiff { path: path.clone(), changes, warning: None }
+        })
+        .collect()
+}
+
+pub fn apply_transforms(
+    text: &str,
+    transforms: &[Transform],
+    options: &CaseOptions
+) -> String {
+    let mut text = text.to_string();
+    for transform in transforms {
+        text = match transform {
+            Transform::TitleCase => title_case(&text, options),
+            Transform::SentenceCase => sentence_case(&text, options),
+            Transform::Lower => text.to_lowercase(),
+            Transform::Upper => text.to_uppercase(),
+            Transform::Trim => text.trim().to_string(),
+            Transform::CollapseWhitespace => collapse_whitespace(&text),
+            Transform::Nfc => text.nfc().collect(),
+            Transform::Nfd => text.nfd().collect(),
+        };
+    }
+
+    text
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::new();
+    let mut last_was_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_was_space {
+                result.push(' ');
+            }
+            last_was_space = true;
+        } else {
+            result.push(c);
+            last_was_space = false;
+        }
+    }
+
+    result
+}
+
+fn title_case(text: &str, options: &CaseOptions) -> String {
+    let words = split_words(text);
+    let last = words.iter().rposition(|(word, _)| !word.is_empty());
+    let mut result = String::new();
+    let mut force_capital = true;
+
+    for (i, (word, separator)) in words.iter().enumerate() {
+        let core = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+        if let Some(acronym) = find_acronym(word, options) {
+            result.push_str(&acronym);
+        } else if !force_capital
+            && Some(i) != last
+            && options.lowercase_words.contains(&core)
+        {
+            result.push_str(&word.to_lowercase());
+        } else {
+            result.push_str(&capitalize(word));
+        }
+
+        // Words after a colon, dash or opening bracket start a new phrase
+        force_capital = word.ends_with(':')
+            || word.chars().all(|c| c == '-')
+            || word.ends_with('(')
+            || word.ends_with('[');
+        if let Some(next) = words.get(i + 1) {
+            force_capital |= next.0.starts_with('(') || next.0.starts_with('[');
+        }
+        result.push_str(separator);
+    }
+
+    result
+}
+
+fn sentence_case(text: &str, options: &CaseOptions) -> String {
+    let mut result = String::new();
+    let mut capitalize_next = true;
+
+    for (word, separator) in split_words(text) {
+        if let Some(acronym) = find_acronym(&word, options) {
+            result.push_str(&acronym);
+        } else if capitalize_next {
+            result.push_str(&capitalize(&word));
+        } else {
+            result.push_str(&word.to_lowercase());
+        }
+
+        if word.chars().any(|c| c.is_alphanumeric()) {
+            capitalize_next = word.ends_with(['.', '!', '?', ':']);
+        }
+        result.push_str(&separator);
+    }
+
+    result
+}
+
+// Splits text into words, each paired with the whitespace that follows it
+fn split_words(text: &str) -> Vec<(String, String)> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut separator = String::new();
+
+    for c in text.chars() {
+        if c.is_whitespace() {
+            separator.push(c);
+        } else {
+            if !separator.is_empty() {
+                words.push((word, separator));
+                word = String::new();
+                separator = String::new();
+            }
+            word.push(c);
+        }
+    }
+    words.push((word, separator));
+
+    words
+}
+
+fn find_acronym(word: &str, options: &CaseOptions) -> Option<String> {
+    let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+    if core.is_empty() {
+        return None;
+    }
+
+    options.acronyms
+        .iter()
+        .find(|acronym| acronym.eq_ignore_ascii_case(core))
+        .map(|acronym| word.replacen(core, acronym, 1))
+}
+
+// Uppercases the first letter and lowercases the rest, skipping any leading
+// punctuation such as quotes or brackets
+fn capitalize(word: &str) -> String {
+    let mut result = String::new();
+    let mut done = false;
+    for c in word.chars() {
+        if !done && c.is_alphabetic() {
+            result.extend(c.to_uppercase());
+            done = true;
+        } else {
+            result.extend(c.to_lowercase());
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(acronyms: &str) -> CaseOptions {
+        CaseOptions::parse(DEFAULT_LOWERCASE_WORDS, acronyms)
+    }
+
+    #[test]
+    fn title_case_keeps_small_words_lowercase_inside_phrases() {
+        let options = options("");
+        assert_eq!(title_case("the END of the world", &options), "The End of the World");
+        assert_eq!(title_case("songs to live by", &options), "Songs to Live By");
+        assert_eq!(title_case("intro: in the beginning", &options), "Intro: In the Beginning");
+        assert_eq!(title_case("lost - and found", &options), "Lost - And Found");
+        assert_eq!(title_case("love song (of the year)", &options), "Love Song (Of the Year)");
+    }
+
+    #[test]
+    fn title_case_handles_apostrophes_and_quotes() {
+        let options = options("");
+        assert_eq!(title_case("DON'T STOP ME NOW", &options), "Don't Stop Me Now");
+        assert_eq!(title_case("rock 'n' roll", &options), "Rock 'N' Roll");
+        assert_eq!(title_case("\"heroes\"", &options), "\"Heroes\"");
+    }
+
+    #[test]
+    fn all_caps_words_are_only_kept_as_acronyms() {
+        let options = options("DJ, USA");
+        assert_eq!(title_case("BORN IN THE USA", &options), "Born in the USA");
+        assert_eq!(title_case("dj (remix)", &options), "DJ (Remix)");
+        assert_eq!(sentence_case("LIVE IN THE usa. AGAIN", &options), "Live in the USA. Again");
+    }
+
+    #[test]
+    fn sentence_case_capitalises_after_sentence_ends() {
+        let options = options("");
+        assert_eq!(sentence_case("HELLO WORLD! it's ME", &options), "Hello world! It's me");
+        assert_eq!(sentence_case("part one: THE START", &options), "Part one: The start");
+    }
+
+    #[test]
+    fn whitespace_is_collapsed_before_changing_case() {
+        let mut choice = TransformChoice::default();
+        choice.toggle(Transform::TitleCase);
+        choice.toggle(Transform::CollapseWhitespace);
+        choice.toggle(Transform::Trim);
+
+        let text = apply_transforms("  the \t long   way home ", &choice.transforms(), &options(""));
+        assert_eq!(text, "The Long Way Home");
+    }
+
+    #[test]
+    fn case_transforms_replace_each_other() {
+        let mut choice = TransformChoice::default();
+        choice.toggle(Transform::Upper);
+        choice.toggle(Transform::Trim);
+        choice.toggle(Transform::Nfd);
+        choice.toggle(Transform::TitleCase);
+        choice.toggle(Transform::Nfc);
+
+        assert_eq!(choice.transforms(), vec![Transform::Trim, Transform::TitleCase, Transform::Nfc]);
+
+        choice.toggle(Transform::Trim);
+        assert!(!choice.contains(Transform::Trim));
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
     cmp,
+    collections::HashMap,
     ffi::OsStr,
+    path::Path,
 };
 use tui::{
     backend::Backend,
@@ -18,7 +20,9 @@ use tui::{
     text::{Span, Spans},
     widgets::{
         Block,
+        Borders,
         Clear,
+        Gauge,
         List,
         ListItem,
         ListState,
@@ -28,20 +32,31 @@ use tui::{
     Frame,
 };
 
+use crate::ape::*;
 use crate::app::*;
+use crate::duplicates::*;
+use crate::health::*;
+use crate::normalize::*;
+use crate::tags::*;
+use crate::input::*;
+use crate::lint::*;
+use crate::list::*;
+use crate::merge::*;
+use crate::mpeg::*;
+use crate::preview::*;
+use crate::transform::*;
+use crate::worker::*;
 
 macro_rules! raw_para {
     ( $( $x:expr ),* ) => {
         {
-            let mut temp_para = Vec::new();
-            $(
-                temp_para.push(
+            vec![
+                $(
                     Spans::from(
                         Span::raw($x)
-                    )
-                );
-            )*
-            temp_para
+                    ),
+                )*
+            ]
         }
     };
 }
@@ -109,6 +124,137 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
 
     match app.state {
         AppState::FileNavigation => render_main_interface(f, app),
+        AppState::FindReplace => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.replace_form);
+        },
+        AppState::Transform => {
+            render_main_interface(f, app);
+            render_transform_popup(f, app);
+        },
+        AppState::NormalizeForm => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.normalize_form);
+        },
+        AppState::Renames => {
+            render_main_interface(f, app);
+            render_renames_popup(f, app);
+        },
+        AppState::Split => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.split_form);
+        },
+        AppState::Length => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.length_form);
+        },
+        AppState::Copy => {
+            render_main_interface(f, app);
+            render_copy_popup(f, app);
+        },
+        AppState::Templates => {
+            render_main_interface(f, app);
+            render_templates_popup(f, app);
+        },
+        AppState::TemplateName => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.template_form);
+        },
+        AppState::Edit => {
+            render_main_interface(f, app);
+            render_edit_popup(f, app);
+        },
+        AppState::Check => {
+            render_main_interface(f, app);
+            render_findings_popup(
+                f,
+                format!("Check ({} Problems)", app.findings.len()),
+                &mut app.findings,
+                "Enter: jump to file | f: apply fix | F: apply all fixes | Esc: close"
+            );
+        },
+        AppState::Integrity => {
+            render_main_interface(f, app);
+            render_findings_popup(
+                f,
+                format!("Audio Integrity ({} Findings)", app.integrity.len()),
+                &mut app.integrity,
+                "Enter: jump to file | Esc: close"
+            );
+        },
+        AppState::Health => {
+            render_main_interface(f, app);
+            render_health_popup(f, app);
+        },
+        AppState::Ape => {
+            render_main_interface(f, app);
+            render_ape_popup(f, app);
+        },
+        AppState::EncodingForm => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.encoding_form);
+        },
+        AppState::EncodingReview => {
+            render_main_interface(f, app);
+            render_encoding_review_popup(f, app);
+        },
+        AppState::MergeField => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.merge_form);
+        },
+        AppState::Merge => {
+            render_main_interface(f, app);
+            render_merge_popup(f, app);
+        },
+        AppState::DuplicatesForm => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.duplicates_form);
+        },
+        AppState::Duplicates => {
+            render_main_interface(f, app);
+            render_duplicates_popup(f, app);
+        },
+        AppState::ReplayGainForm => {
+            render_main_interface(f, app);
+            render_form_popup(f, &app.replaygain_form);
+        },
+        AppState::ReplayGain => {
+            render_main_interface(f, app);
+            if let Some(gain_job) = app.gain_job.as_ref() {
+                render_job_popup(f, "ReplayGain Analysis", &gain_job.job);
+            }
+        },
+        AppState::Bpm => {
+            render_main_interface(f, app);
+            if let Some(job) = app.bpm_job.as_ref() {
+                render_job_popup(f, "BPM Detection", job);
+            }
+        },
+        AppState::BpmReview => {
+            render_main_interface(f, app);
+            render_bpm_review_popup(f, app);
+        },
+        AppState::Profiles => {
+            render_main_interface(f, app);
+            render_profiles_popup(f, app);
+        },
+        AppState::WriteReport => {
+            render_main_interface(f, app);
+            let name = app.write_profile
+                .as_ref()
+                .map(|profile| profile.name.clone())
+                .unwrap_or_default();
+            render_findings_popup(
+                f,
+                format!("Writing as {} ({} Changes)", name, app.write_report.len()),
+                &mut app.write_report,
+                "y: write anyway | Esc: cancel"
+            );
+        },
+        AppState::Preview => {
+            render_main_interface(f, app);
+            render_preview_popup(f, app);
+        },
     }
 }
 
@@ -158,7 +304,7 @@ fn render_main_interface<B: Backend>(f: &mut Frame<B>, app: &mut App) {
             render_tag_columns(f, chunks[1], app);
         }
 
-        render_footer_info(f, chunks[2]);
+        render_footer_info(f, chunks[2], app);
     } else {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -204,7 +350,7 @@ fn render_main_interface<B: Backend>(f: &mut Frame<B>, app: &mut App) {
             render_tag_columns(f, chunks[1], app);
         }
 
-        render_empty_line(f, chunks[2]);
+        render_status_line(f, chunks[2], app);
     }
 }
 
@@ -260,7 +406,12 @@ fn render_tiny_banner<B: Backend>(
     f.render_widget(banner, chunk);
 }
 
-fn render_footer_info<B: Backend>(f: &mut Frame<B>, chunk: Rect) {
+fn render_footer_info<B: Backend>(f: &mut Frame<B>, chunk: Rect, app: &App) {
+    if app.status.is_some() {
+        render_status_line(f, shrink_rect(chunk, 1), app);
+        return;
+    }
+
     let info = raw_para!(
         "",
         "rime v1.0.0 by Ben Buchanan (https://github.com/Nynergy)"
@@ -273,10 +424,13 @@ fn render_footer_info<B: Backend>(f: &mut Frame<B>, chunk: Rect) {
     f.render_widget(info, chunk);
 }
 
-fn render_empty_line<B: Backend>(f: &mut Frame<B>, chunk: Rect) {
-    let line = raw_para!("");
+fn render_status_line<B: Backend>(f: &mut Frame<B>, chunk: Rect, app: &App) {
+    let status = app.status.clone().unwrap_or_default();
+    let line = raw_para!(status);
     let line = Paragraph::new(line)
-        .block(Block::default());
+        .block(Block::default())
+        .style(Style::default().fg(Color::Yellow))
+        .alignment(Alignment::Center);
 
     f.render_widget(line, chunk);
 }
@@ -303,7 +457,7 @@ fn render_file_navigator<B: Backend>(
                 item_style = item_style.fg(Color::Magenta);
             }
 
-            if let Some(_) = app.selected_files.get(i) {
+            if app.selected_files.contains_key(i) {
                 item_style = item_style
                     .fg(Color::Yellow)
                     .add_modifier(Modifier::BOLD);
@@ -330,7 +484,27 @@ fn render_file_navigator<B: Backend>(
         .block(Block::default())
         .highlight_style(highlight);
 
-    let inner_area = shrink_rect(chunk, 1);
+    let mut inner_area = shrink_rect(chunk, 1);
+    if let Some(info) = app.highlighted_stream_info() {
+        let lines = stream_info_lines(info);
+        if inner_area.height > lines.len() as u16 + 4 {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Min(3),
+                        Constraint::Length(lines.len() as u16 + 1),
+                    ]
+                    .as_ref()
+                )
+                .split(inner_area);
+
+            let info = Paragraph::new(lines)
+                .block(Block::default().borders(Borders::TOP).title("Stream"));
+            f.render_widget(info, chunks[1]);
+            inner_area = chunks[0];
+        }
+    }
 
     f.render_stateful_widget(
         list,
@@ -339,6 +513,41 @@ fn render_file_navigator<B: Backend>(
     );
 }
 
+fn stream_info_lines(info: &StreamInfo) -> Vec<Spans<'static>> {
+    let mut lines = vec![
+        Spans::from(info.format()),
+        Spans::from(format!("{} kbps {}", info.bitrate, info.bitrate_mode())),
+        Spans::from(format!("{} Hz {}", info.sample_rate, info.channels())),
+        Spans::from(format!(
+            "{}.{:03} ({} frames)",
+            format_duration(info.duration.trunc()),
+            (info.duration.fract() * 1000.0) as u32,
+            info.frame_count
+        )),
+    ];
+
+    if let Some(header) = &info.vbr_header {
+        let kind = match header.kind {
+            VbrHeaderKind::Xing => "Xing",
+            VbrHeaderKind::Info => "Info",
+            VbrHeaderKind::Vbri => "VBRI",
+        };
+        let mut line = match header.frames {
+            Some(frames) => format!("{} header ({} frames)", kind, frames),
+            None => format!("{} header", kind),
+        };
+        if let Some(lame) = &header.lame {
+            line.push_str(&format!(", {}", lame.version));
+            if let Some(preset) = &lame.preset {
+                line.push_str(&format!(" {}", preset));
+            }
+        }
+        lines.push(Spans::from(line));
+    }
+
+    lines
+}
+
 fn render_tag_columns<B: Backend>(
     f: &mut Frame<B>,
     chunk: Rect,
@@ -359,9 +568,13 @@ fn render_tag_columns<B: Backend>(
         f, chunks[0],
         format!("Current Tags ({} Files Selected)", app.num_selected_files())
     );
-    render_tag_list(f, chunks[0], app);
+    render_tag_list(f, chunks[0], &app.tag_sum);
 
-    render_column_block(f, chunks[1], "New Tags".to_string());
+    render_column_block(
+        f, chunks[1],
+        format!("New Tags ({} Files Pending)", app.pending_tags.len())
+    );
+    render_tag_list(f, chunks[1], &app.new_tag_sum);
 }
 
 fn render_column_block<B: Backend>(
@@ -378,13 +591,15 @@ fn render_column_block<B: Backend>(
 fn render_tag_list<B: Backend>(
     f: &mut Frame<B>,
     chunk: Rect,
-    app: &App
+    tag_sum: &HashMap<String, String>
 ) {
     let inner_area = shrink_rect(chunk, 1);
 
-    // TODO: Sort tag frames before rendering them
-    let items: Vec<ListItem> = app.tag_sum
-        .iter()
+    let mut frames = tag_sum.iter().collect::<Vec<(&String, &String)>>();
+    frames.sort();
+
+    let items: Vec<ListItem> = frames
+        .into_iter()
         .map(|(k, v)| {
             ListItem::new(
                 tag_span(
@@ -436,9 +651,7 @@ fn create_middle_line(
     value: String
 ) {
     // Left Side
-    let line = String::from(
-        format!("{} ", line::VERTICAL)
-    );
+    let line = format!("{} ", line::VERTICAL);
     let mut spans = vec![Span::raw(line)];
 
     // Name
@@ -448,8 +661,20 @@ fn create_middle_line(
             .add_modifier(Modifier::BOLD)
     ));
 
-    // Value
+    // Value, marked when it is not in the composed form most software expects
+    let form = if value != "<multiple>" {
+        match normal_form(&value) {
+            Some(NormalForm::Nfc) => None,
+            Some(NormalForm::Nfd) => Some(" [NFD]"),
+            None => Some(" [not NFC]"),
+        }
+    } else {
+        None
+    };
     spans.push(Span::raw(value));
+    if let Some(form) = form {
+        spans.push(Span::styled(form, Style::default().fg(Color::Yellow)));
+    }
 
     // Padding Spaces
     let current_width = spans
@@ -464,14 +689,12 @@ fn create_middle_line(
 
     let mut line = String::new();
     for _ in 0..remaining_width {
-        line.push_str(" ");
+        line.push(' ');
     }
     spans.push(Span::raw(line));
 
     // Right Side
-    let line = String::from(
-        format!(" {}", line::VERTICAL)
-    );
+    let line = format!(" {}", line::VERTICAL);
     spans.push(Span::raw(line));
     lines.push(Spans::from(spans));
 }
@@ -491,19 +714,998 @@ fn translate_tag_id(id: &str) -> String {
         "COMM" => String::from("Comment     "),
         "TALB" => String::from("Album       "),
         "TCON" => String::from("Genre       "),
+        "TCOP" => String::from("Copyright   "),
+        "TENC" => String::from("Encoded By  "),
         "TIT2" => String::from("Title       "),
         "TPE1" => String::from("Artist      "),
         "TPE2" => String::from("Album Artist"),
         "TPOS" => String::from("Disc        "),
+        "TPUB" => String::from("Publisher   "),
         "TRCK" => String::from("Track       "),
         "TSRC" => String::from("ISRC        "),
         "TSSE" => String::from("Encoding    "),
         "TYER" => String::from("Date        "),
+        "TDRC" => String::from("Recorded    "),
         // TODO: We may not want to handle custom frame data
         "TXXX" => String::from("Custom Frame"),
         "USLT" => String::from("Lyrics      "),
-        _ => panic!("Could not translate id3 frame '{}'", id)
+        _ => format!("{:<12}", id)
+    }
+}
+
+fn render_form_popup<B: Backend>(f: &mut Frame<B>, form: &InputForm) -> Rect {
+    let height = form.inputs.len() as u16 * 2 + 3;
+    let area = popup_rect(f.size(), 60, height);
+    render_popup_block(f, area, form.title.to_string());
+
+    let inner_area = shrink_rect(area, 1);
+    for (i, label) in form.labels.iter().enumerate() {
+        let line = Rect::new(inner_area.x, inner_area.y + i as u16 * 2, inner_area.width, 1);
+        render_text_input(f, line, label, &form.inputs[i], form.focus == i);
     }
+
+    let help = match &form.error {
+        Some(err) => Span::styled(err.clone(), Style::default().fg(Color::Red)),
+        None => Span::raw(form.help),
+    };
+    let help = Paragraph::new(Spans::from(help))
+        .alignment(Alignment::Center);
+    let line = Rect::new(inner_area.x, inner_area.bottom() - 1, inner_area.width, 1);
+    f.render_widget(help, line);
+
+    inner_area
+}
+
+fn render_edit_popup<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let dialog = &app.edit_dialog;
+    let inner_area = render_form_popup(f, &dialog.form);
+    if !dialog.editing_value() || dialog.suggestions.is_empty() {
+        return;
+    }
+
+    // Dropdown just below the value input, lined up with its text
+    let x = inner_area.x + 11;
+    let y = inner_area.y + 3;
+    let height = cmp::min(
+        dialog.suggestions.len() as u16 + 2,
+        f.size().height.saturating_sub(y)
+    );
+    let area = Rect::new(x, y, inner_area.width.saturating_sub(11), height);
+
+    let items: Vec<ListItem> = dialog.suggestions
+        .iter()
+        .map(|(value, count)| {
+            ListItem::new(Spans::from(vec![
+                Span::raw(value.clone()),
+                Span::styled(format!(" ({})", count), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default();
+    state.select(Some(dialog.suggestion));
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_transform_popup<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let dialog = &app.transform_dialog;
+    let height = dialog.frames.len() as u16 + 9;
+    let area = popup_rect(f.size(), 70, height);
+    render_popup_block(f, area, "Transform Text".to_string());
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Min(2),
+                Constraint::Length(4),
+                Constraint::Length(1),
+            ]
+            .as_ref()
+        )
+        .split(inner_area);
+
+    let mut header = vec![Span::raw(format!("{:<14}", ""))];
+    for transform in TRANSFORMS.iter() {
+        header.push(Span::styled(
+            format!("{:^10}", transform.name()),
+            Style::default().add_modifier(Modifier::BOLD)
+        ));
+    }
+    let mut rows = vec![Spans::from(header)];
+
+    for (row, id) in dialog.frames.iter().enumerate() {
+        let mut spans = vec![Span::styled(
+            format!("{:<14}", translate_tag_id(id).trim_end()),
+            Style::default().add_modifier(Modifier::BOLD)
+        )];
+        for (col, transform) in TRANSFORMS.iter().enumerate() {
+            let chosen = dialog.choices[row].contains(*transform);
+            let mut style = Style::default();
+            if chosen {
+                style = style.fg(Color::Green);
+            }
+            if dialog.focus == 0 && dialog.row == row && dialog.col == col {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            let mark = if chosen { "[x]" } else { "[ ]" };
+            spans.push(Span::styled(format!("{:^10}", mark), style));
+        }
+        rows.push(Spans::from(spans));
+    }
+    f.render_widget(Paragraph::new(rows), chunks[0]);
+
+    for (i, label) in TRANSFORM_FIELDS.iter().enumerate() {
+        let line = Rect::new(chunks[1].x, chunks[1].y + 1 + i as u16 * 2, chunks[1].width, 1);
+        render_text_input(f, line, label, &dialog.inputs[i], dialog.focus == i + 1);
+    }
+
+    let help = Paragraph::new(raw_para!("Space: toggle | Tab: next field | Enter: preview"))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[2]);
+}
+
+fn render_copy_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let dialog = match app.copy_dialog.as_mut() {
+        Some(dialog) => dialog,
+        None => return,
+    };
+
+    let height = dialog.frames.len() as u16 + 3;
+    let area = popup_rect(f.size(), 60, height);
+    render_popup_block(f, area, format!("Copy Tag from {}", dialog.source));
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(inner_area);
+
+    let items: Vec<ListItem> = dialog.frames
+        .items
+        .iter()
+        .zip(dialog.checked.iter())
+        .map(|(frame, checked)| {
+            let mark = if *checked { "[x] " } else { "[ ] " };
+            ListItem::new(Spans::from(vec![
+                Span::raw(mark),
+                Span::styled(
+                    format!("{}: ", translate_tag_id(frame.id())),
+                    Style::default().add_modifier(Modifier::BOLD)
+                ),
+                Span::raw(frame_value(frame)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[0], &mut dialog.frames.state);
+
+    let help = Paragraph::new(raw_para!("Space: toggle | a: toggle all | Enter: copy | Esc: cancel"))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[1]);
+}
+
+fn render_templates_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let height = cmp::max(app.templates.len() as u16, 1) + 3;
+    let area = popup_rect(f.size(), 40, height);
+    render_popup_block(f, area, "Templates".to_string());
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(inner_area);
+
+    if app.templates.is_empty() {
+        let empty = Paragraph::new(raw_para!("No templates yet"))
+            .alignment(Alignment::Center);
+        f.render_widget(empty, chunks[0]);
+    } else {
+        let items: Vec<ListItem> = app.templates
+            .items
+            .iter()
+            .map(|name| ListItem::new(name.clone()))
+            .collect();
+        let list = List::new(items)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, chunks[0], &mut app.templates.state);
+    }
+
+    let help = match app.templates.get_selected() {
+        Some(name) if app.confirm_template_delete => Span::styled(
+            format!("Delete template '{}'? y: yes | any other key: no", name),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+        ),
+        _ => Span::raw("Enter: apply | n: new from file | d: delete"),
+    };
+    let help = Paragraph::new(Spans::from(help))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[1]);
+}
+
+fn render_profiles_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let height = cmp::max(app.profiles.len() as u16, 1) + 3;
+    let area = popup_rect(f.size(), 60, height);
+    render_popup_block(f, area, "Write Profiles".to_string());
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(inner_area);
+
+    let items: Vec<ListItem> = app.profiles
+        .items
+        .iter()
+        .map(|profile| {
+            let is_default = app.default_profile.as_deref() == Some(profile.name.as_str());
+            let style = if is_default {
+                Style::default().fg(Color::Green)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Span::styled(
+                format!(
+                    "{} {:<12} {}",
+                    if is_default { "*" } else { " " },
+                    truncate_text(profile.name.clone(), 12),
+                    profile.describe()
+                ),
+                style
+            ))
+        })
+        .collect();
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[0], &mut app.profiles.state);
+
+    let help = Paragraph::new(raw_para!("Enter: write pending edits | d: set or clear default"))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[1]);
+}
+
+fn render_renames_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let form = NormalForm::parse(app.normalize_form.value(0))
+        .map(|form| form.name())
+        .unwrap_or("NFC");
+    let height = cmp::min(app.renames.len() as u16 + 3, f.size().height - 2);
+    let area = popup_rect(f.size(), 70, height);
+    render_popup_block(
+        f,
+        area,
+        format!("Normalise Filenames to {} ({} Files)", form, app.renames.len())
+    );
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(inner_area);
+
+    let items: Vec<ListItem> = app.renames
+        .items
+        .iter()
+        .map(|rename| ListItem::new(raw_para!(truncate_text(
+            rename.describe(),
+            chunks[0].width as usize
+        ))))
+        .collect();
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[0], &mut app.renames.state);
+
+    let help = Paragraph::new(raw_para!("y: rename | Esc: back"))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[1]);
+}
+
+fn render_findings_popup<B: Backend>(
+    f: &mut Frame<B>,
+    title: String,
+    findings: &mut GenericList<Finding>,
+    help: &str
+) {
+    let area = popup_rect(f.size(), 90, f.size().height.saturating_sub(4));
+    render_popup_block(f, area, title);
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(inner_area);
+
+    if findings.is_empty() {
+        let empty = Paragraph::new(raw_para!("No problems found"))
+            .alignment(Alignment::Center);
+        f.render_widget(empty, chunks[0]);
+    } else {
+        let items: Vec<ListItem> = findings
+            .items
+            .iter()
+            .map(|finding| {
+                let mut spans = vec![
+                    Span::styled(
+                        format!("{:<30} ", file_name(&finding.path)),
+                        Style::default().fg(Color::Magenta)
+                    ),
+                    Span::raw(finding.message.clone()),
+                ];
+                if let Some(fix) = &finding.fix {
+                    spans.push(Span::styled(
+                        format!("  [fix: {} = {}]", fix.id, fix.new),
+                        Style::default().fg(Color::Green)
+                    ));
+                }
+                ListItem::new(Spans::from(spans))
+            })
+            .collect();
+        let list = List::new(items)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, chunks[0], &mut findings.state);
+    }
+
+    let help = Paragraph::new(raw_para!(help))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[1]);
+}
+
+fn render_health_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let report = match app.health.as_mut() {
+        Some(report) => report,
+        None => return,
+    };
+
+    let area = popup_rect(f.size(), 90, f.size().height.saturating_sub(4));
+    render_popup_block(f, area, format!(
+        "Stream Health ({} Problems in {} Files, sorted by {})",
+        report.issues.len(), report.num_files, report.sort.name()
+    ));
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(1),
+                Constraint::Min(1),
+                Constraint::Length(1),
+            ]
+            .as_ref()
+        )
+        .split(inner_area);
+
+    let header = format!("{:<8} {:<30} {:<22} {}", "Severity", "File", "Problem", "Detail");
+    let header = Paragraph::new(Span::styled(header, Style::default().add_modifier(Modifier::BOLD)));
+    f.render_widget(header, chunks[0]);
+
+    if report.issues.is_empty() {
+        let empty = Paragraph::new(raw_para!("No problems found"))
+            .alignment(Alignment::Center);
+        f.render_widget(empty, chunks[1]);
+    } else {
+        let items: Vec<ListItem> = report.issues
+            .items
+            .iter()
+            .map(|issue| {
+                let color = match issue.severity {
+                    Severity::Error => Color::Red,
+                    Severity::Warning => Color::Yellow,
+                };
+                ListItem::new(Spans::from(vec![
+                    Span::styled(format!("{:<8} ", issue.severity.name()), Style::default().fg(color)),
+                    Span::styled(
+                        format!("{:<30} ", truncate_text(file_name(&issue.path), 30)),
+                        Style::default().fg(Color::Magenta)
+                    ),
+                    Span::raw(format!("{:<22} {}", issue.problem, issue.detail)),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, chunks[1], &mut report.issues.state);
+    }
+
+    let help = Paragraph::new(raw_para!("Enter: jump to file | s: change sort | Esc: close"))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[2]);
+}
+
+fn render_merge_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let view = match app.merge_view.as_mut() {
+        Some(view) => view,
+        None => return,
+    };
+
+    let area = popup_rect(f.size(), 80, f.size().height.saturating_sub(4));
+    render_popup_block(
+        f, area,
+        format!("Merge {} Spellings ({} Clusters)",
+                translate_tag_id(&view.id).trim_end(), view.clusters.len())
+    );
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(inner_area);
+
+    let items: Vec<ListItem> = view.lines
+        .items
+        .iter()
+        .map(|line| {
+            match line {
+                MergeLine::Cluster(i) => {
+                    let cluster = &view.clusters[*i];
+                    ListItem::new(Span::styled(
+                        format!("{} spellings in {} files", cluster.spellings.len(), cluster.num_files()),
+                        Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+                    ))
+                },
+                MergeLine::Spelling(i, j) => {
+                    let cluster = &view.clusters[*i];
+                    let (spelling, paths) = &cluster.spellings[*j];
+                    let canonical = cluster.canonical == Some(*j);
+                    let mark = if canonical { "(*)" } else { "( )" };
+                    let mut style = Style::default();
+                    if canonical {
+                        style = style.fg(Color::Green);
+                    }
+                    ListItem::new(Span::styled(
+                        format!("  {} {} ({} files)", mark, spelling, paths.len()),
+                        style
+                    ))
+                },
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[0], &mut view.lines.state);
+
+    let help = Paragraph::new(raw_para!("Space: choose canonical spelling | Enter: preview | Esc: back"))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[1]);
+}
+
+fn render_duplicates_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let view = match app.duplicates.as_mut() {
+        Some(view) => view,
+        None => return,
+    };
+
+    let area = popup_rect(f.size(), 90, f.size().height.saturating_sub(4));
+    render_popup_block(f, area, format!("Duplicate Tracks ({} Groups)", view.groups.len()));
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(1),
+                Constraint::Min(1),
+                Constraint::Length(1),
+            ]
+            .as_ref()
+        )
+        .split(inner_area);
+
+    let path_width = (inner_area.width as usize).saturating_sub(46).max(10);
+    let header = format!(
+        "       {:<width$} {:>9} {:>8} {:>9} {:>8}",
+        "File", "Bitrate", "Length", "Size", "Tags",
+        width = path_width
+    );
+    let header = Paragraph::new(Span::styled(header, Style::default().add_modifier(Modifier::BOLD)));
+    f.render_widget(header, chunks[0]);
+
+    let root = view.root.clone();
+    let items: Vec<ListItem> = view.lines
+        .items
+        .iter()
+        .map(|line| {
+            match line {
+                DuplicateLine::Group(i) => {
+                    let group = &view.groups[*i];
+                    ListItem::new(Span::styled(
+                        format!("{} ({} copies)", group.title, group.files.len()),
+                        Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+                    ))
+                },
+                DuplicateLine::File(i, j) => {
+                    let file = &view.groups[*i].files[*j];
+                    let path = file.path
+                        .strip_prefix(&root)
+                        .unwrap_or(&file.path)
+                        .to_string_lossy()
+                        .to_string();
+                    let mark = if file.keep { "[keep]" } else { "[ -- ]" };
+                    let style = if file.keep {
+                        Style::default().fg(Color::Green)
+                    } else {
+                        Style::default().fg(Color::Red)
+                    };
+                    ListItem::new(Span::styled(
+                        format!(
+                            "{} {:<width$} {:>9} {:>8} {:>9} {:>8}",
+                            mark,
+                            truncate_text(path, path_width),
+                            file.bitrate.map(|b| format!("{} kbps", b)).unwrap_or("?".to_string()),
+                            file.duration.map(format_duration).unwrap_or("?".to_string()),
+                            format_size(file.size),
+                            format!("{}/{}", file.completeness, max_completeness()),
+                            width = path_width
+                        ),
+                        style
+                    ))
+                },
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[1], &mut view.lines.state);
+
+    let help = if view.confirm {
+        Span::styled(
+            format!("Delete {} files from disk? y: yes | any other key: no",
+                    view.files_to_remove().len()),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+        )
+    } else {
+        Span::raw("Space: toggle keep | D: delete copies not kept | Esc: back")
+    };
+    let help = Paragraph::new(Spans::from(help))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[2]);
+}
+
+fn render_ape_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let view = match app.ape.as_mut() {
+        Some(view) => view,
+        None => return,
+    };
+
+    let area = popup_rect(f.size(), 90, f.size().height.saturating_sub(4));
+    render_popup_block(f, area, format!(
+        "APE Tags ({} Items in {} of {} MPEG Files)",
+        view.num_items(), view.files.len(), view.num_checked
+    ));
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(1),
+                Constraint::Min(1),
+                Constraint::Length(1),
+            ]
+            .as_ref()
+        )
+        .split(inner_area);
+
+    let value_width = (inner_area.width as usize).saturating_sub(60).max(10);
+    let header = format!(
+        "  {:<24} {:<width$} {}",
+        "Item", "Value", "ID3 Frame",
+        width = value_width
+    );
+    let header = Paragraph::new(Span::styled(header, Style::default().add_modifier(Modifier::BOLD)));
+    f.render_widget(header, chunks[0]);
+
+    let items: Vec<ListItem> = view.lines
+        .items
+        .iter()
+        .map(|line| {
+            match line {
+                ApeLine::File(i) => {
+                    let file = &view.files[*i];
+                    ListItem::new(Span::styled(
+                        format!(
+                            "{} (APEv{}, {} bytes)",
+                            file.path.display(),
+                            file.tag.version / 1000,
+                            file.tag.end - file.tag.start
+                        ),
+                        Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+                    ))
+                },
+                ApeLine::Item(i, j) => {
+                    let item = &view.files[*i].tag.items[*j];
+                    let frame = item.frame_key();
+                    let style = if frame.is_some() {
+                        Style::default()
+                    } else {
+                        Style::default().fg(Color::DarkGray)
+                    };
+                    ListItem::new(Span::styled(
+                        format!(
+                            "  {:<24} {:<width$} {}",
+                            truncate_text(item.key.clone(), 24),
+                            truncate_text(item.value.display(), value_width),
+                            truncate_text(frame.unwrap_or("-".to_string()), 32),
+                            width = value_width
+                        ),
+                        style
+                    ))
+                },
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[1], &mut view.lines.state);
+
+    let help = if view.confirm {
+        Span::styled(
+            format!("Remove the APE tags from {} files? y: yes | any other key: no",
+                    view.files.len()),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+        )
+    } else {
+        Span::raw("m: migrate missing frames | M: migrate, replacing ID3 values | X: remove APE tags | Esc: close")
+    };
+    let help = Paragraph::new(Spans::from(help))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[2]);
+}
+
+fn render_encoding_review_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let review = match app.encoding_review.as_mut() {
+        Some(review) => review,
+        None => return,
+    };
+
+    let area = popup_rect(f.size(), 90, f.size().height.saturating_sub(4));
+    render_popup_block(f, area, format!(
+        "Repair Text Encoding ({} of {} Values)",
+        review.num_accepted(), review.rows.len()
+    ));
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(1),
+                Constraint::Min(1),
+                Constraint::Length(8),
+                Constraint::Length(1),
+            ]
+            .as_ref()
+        )
+        .split(inner_area);
+
+    let text_width = (inner_area.width as usize).saturating_sub(68).max(20) / 2;
+    let header = format!(
+        "       {:<24} {:<12} {:<width$} {:<width$} {}",
+        "File", "Frame", "Current", "Repaired", "Encoding",
+        width = text_width
+    );
+    let header = Paragraph::new(Span::styled(header, Style::default().add_modifier(Modifier::BOLD)));
+    f.render_widget(header, chunks[0]);
+
+    let items: Vec<ListItem> = review.rows
+        .items
+        .iter()
+        .map(|row| {
+            let chosen = row.chosen();
+            let style = if !row.accepted {
+                Style::default().fg(Color::DarkGray)
+            } else if row.likely {
+                Style::default().fg(Color::Green)
+            } else {
+                Style::default().fg(Color::Yellow)
+            };
+            ListItem::new(Span::styled(
+                format!(
+                    "{} {:<24} {:<12} {:<width$} {:<width$} {}",
+                    if row.accepted { "[ ok ]" } else { "[ -- ]" },
+                    truncate_text(file_name(&row.path), 24),
+                    truncate_text(row.key.clone(), 12),
+                    // C1 controls from the legacy bytes would garble the terminal
+                    truncate_text(row.old.replace(char::is_control, "?"), text_width),
+                    truncate_text(chosen.text.clone(), text_width),
+                    chosen.encoding.name(),
+                    width = text_width
+                ),
+                style
+            ))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[1], &mut review.rows.state);
+
+    // Every reading of the highlighted value, so the choice can be made
+    // before anything is staged
+    let mut lines = vec![Spans::from(Span::styled(
+        "Candidates",
+        Style::default().add_modifier(Modifier::BOLD)
+    ))];
+    let selected = review.rows.get_index().and_then(|index| review.rows.items.get(index));
+    if let Some(row) = selected {
+        for (i, candidate) in row.candidates.iter().enumerate() {
+            let style = if i == row.choice {
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            lines.push(Spans::from(Span::styled(
+                format!(
+                    "{} {:<14} {:>5.2}  {}",
+                    if i == row.choice { ">" } else { " " },
+                    candidate.encoding.name(),
+                    candidate.score,
+                    candidate.text
+                ),
+                style
+            )));
+        }
+    }
+    f.render_widget(Paragraph::new(lines), chunks[2]);
+
+    let help = Paragraph::new(raw_para!(
+        "h/l: encoding | Space: accept | a: use for all | Enter: preview | Esc: back"
+    ))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[3]);
+}
+
+// Progress of a background analysis job
+fn render_job_popup<B: Backend, T>(f: &mut Frame<B>, title: &str, job: &Job<T>) {
+    let area = popup_rect(f.size(), 60, 7);
+    render_popup_block(f, area, title.to_string());
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Min(0),
+            ]
+            .as_ref()
+        )
+        .split(inner_area);
+
+    let current = job.paths
+        .get(job.current)
+        .map(|path| file_name(path))
+        .unwrap_or_default();
+    let file = Paragraph::new(raw_para!(truncate_text(current, inner_area.width as usize)))
+        .alignment(Alignment::Center);
+    f.render_widget(file, chunks[0]);
+
+    let total = job.paths.len().max(1);
+    let gauge = Gauge::default()
+        .gauge_style(Style::default().fg(Color::Cyan))
+        .label(format!("{}/{} files", job.num_done(), job.paths.len()))
+        .ratio(job.num_done() as f64 / total as f64);
+    f.render_widget(gauge, chunks[2]);
+
+    let help = Paragraph::new(raw_para!("Esc: cancel"))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[3]);
+}
+
+fn render_bpm_review_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let review = match app.bpm_review.as_mut() {
+        Some(review) => review,
+        None => return,
+    };
+
+    let area = popup_rect(f.size(), 90, f.size().height.saturating_sub(4));
+    render_popup_block(f, area, format!("BPM Review ({} Files)", review.rows.len()));
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(1),
+                Constraint::Min(1),
+                Constraint::Length(1),
+            ]
+            .as_ref()
+        )
+        .split(inner_area);
+
+    let header = format!(
+        "       {:<30} {:>9} {:>10} {:>8} {:>8}",
+        "File", "Estimate", "Confidence", "Current", "New"
+    );
+    let header = Paragraph::new(Span::styled(header, Style::default().add_modifier(Modifier::BOLD)));
+    f.render_widget(header, chunks[0]);
+
+    let items: Vec<ListItem> = review.rows
+        .items
+        .iter()
+        .map(|row| {
+            let name = truncate_text(file_name(&row.path), 30);
+            let line = match &row.estimate {
+                Ok(estimate) => Span::styled(
+                    format!(
+                        "{} {:<30} {:>9.1} {:>9.0}% {:>8} {:>8}",
+                        if row.accepted { "[ ok ]" } else { "[ -- ]" },
+                        name,
+                        estimate.bpm,
+                        estimate.confidence * 100.0,
+                        row.old.clone().unwrap_or_default(),
+                        review.new_value(row)
+                    ),
+                    if row.accepted {
+                        Style::default().fg(Color::Green)
+                    } else {
+                        Style::default().fg(Color::DarkGray)
+                    }
+                ),
+                Err(err) => Span::styled(
+                    format!("[ -- ] {:<30} {}", name, err),
+                    Style::default().fg(Color::Red)
+                ),
+            };
+            ListItem::new(line)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[1], &mut review.rows.state);
+
+    let help = Paragraph::new(raw_para!(format!(
+        "Space: accept | t: tenths ({}) | h: halve | d: double | Enter: preview | Esc: close",
+        if review.tenths { "on" } else { "off" }
+    )))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[2]);
+}
+
+fn render_preview_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let preview = match app.preview.as_mut() {
+        Some(preview) => preview,
+        None => return,
+    };
+
+    let area = popup_rect(f.size(), 90, f.size().height.saturating_sub(4));
+    render_popup_block(
+        f, area,
+        format!("{} ({} Files Changed)", preview.title, preview.num_changed_files())
+    );
+
+    let inner_area = shrink_rect(area, 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(inner_area);
+
+    let items: Vec<ListItem> = preview.lines
+        .items
+        .iter()
+        .map(|line| {
+            match line {
+                PreviewLine::File(path, warning) => {
+                    let mut spans = vec![Span::styled(
+                        file_name(path),
+                        Style::default()
+                            .fg(Color::Magenta)
+                            .add_modifier(Modifier::BOLD)
+                    )];
+                    if let Some(warning) = warning {
+                        spans.push(Span::styled(
+                            format!("  ({})", warning),
+                            Style::default().fg(Color::Red)
+                        ));
+                    }
+                    ListItem::new(Spans::from(spans))
+                },
+                PreviewLine::Change(change) => {
+                    ListItem::new(Spans::from(vec![
+                        Span::styled(
+                            format!("  {}: ", translate_tag_id(&change.id).trim_end()),
+                            Style::default().add_modifier(Modifier::BOLD)
+                        ),
+                        Span::styled(
+                            change.old.clone().unwrap_or("<none>".to_string()),
+                            Style::default().fg(Color::Red)
+                        ),
+                        Span::raw(" -> "),
+                        Span::styled(
+                            change.new.clone(),
+                            Style::default().fg(Color::Green)
+                        ),
+                    ]))
+                },
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[0], &mut preview.lines.state);
+
+    let help = Paragraph::new(raw_para!("Enter/y: stage edits | Esc: back"))
+        .alignment(Alignment::Center);
+    f.render_widget(help, chunks[1]);
+}
+
+fn render_popup_block<B: Backend>(f: &mut Frame<B>, area: Rect, title: String) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan))
+        .title(Span::styled(
+            format!(" {} ", title),
+            Style::default().add_modifier(Modifier::BOLD)
+        ));
+
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+}
+
+fn render_text_input<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    label: &str,
+    input: &TextInput,
+    focused: bool
+) {
+    let label = format!("{:>9}: ", label);
+    let mut label_style = Style::default().add_modifier(Modifier::BOLD);
+    if focused {
+        label_style = label_style.fg(Color::Yellow);
+    }
+
+    let line = Spans::from(vec![
+        Span::styled(label.clone(), label_style),
+        Span::raw(input.text.clone()),
+    ]);
+    f.render_widget(Paragraph::new(line), area);
+
+    if focused {
+        let x = area.x + (label.len() + input.cursor()) as u16;
+        f.set_cursor(cmp::min(x, area.right().saturating_sub(1)), area.y);
+    }
+}
+
+// A rectangle centered in the given area, clamped to fit inside it
+fn popup_rect(area: Rect, width_percent: u16, height: u16) -> Rect {
+    let width = area.width * width_percent / 100;
+    let height = cmp::min(height, area.height);
+    let x = area.x + (area.width - width) / 2;
+    let y = area.y + (area.height - height) / 2;
+
+    Rect::new(x, y, width, height)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(OsStr::new(".."))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
 }
 
 fn shrink_rect(rect: Rect, amount: u16) -> Rect {
@@ -511,10 +1713,10 @@ fn shrink_rect(rect: Rect, amount: u16) -> Rect {
     rect.inner(&margin)
 }
 
-fn truncate_text(mut text: String, max_length: usize) -> String {
-    if text.len() > max_length {
-        text.truncate(max_length - 3);
-        return format!("{}...", text);
+fn truncate_text(text: String, max_length: usize) -> String {
+    if text.chars().count() > max_length {
+        let truncated = text.chars().take(max_length.saturating_sub(3)).collect::<String>();
+        return format!("{}...", truncated);
     }
 
     text
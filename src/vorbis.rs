@@ -0,0 +1,218 @@
+use crate::backend::*;
+
+// Vorbis comment fields and the logical fields they are read as. Fields
+// without a mapping are kept as custom fields named after the comment.
+const COMMENT_FIELDS: [(&str, Field); 27] = [
+    ("TITLE", Field::Title),
+    ("ARTIST", Field::Artist),
+    ("ALBUM", Field::Album),
+    ("ALBUMARTIST", Field::AlbumArtist),
+    ("COMPOSER", Field::Composer),
+    ("GENRE", Field::Genre),
+    ("DATE", Field::Date),
+    ("BPM", Field::Bpm),
+    ("ISRC", Field::Isrc),
+    ("ENCODER", Field::Encoder),
+    ("ENCODEDBY", Field::EncodedBy),
+    ("PUBLISHER", Field::Publisher),
+    ("COPYRIGHT", Field::Copyright),
+    ("CONDUCTOR", Field::Conductor),
+    ("REMIXER", Field::Remixer),
+    ("LYRICIST", Field::Lyricist),
+    ("GROUPING", Field::Grouping),
+    ("SUBTITLE", Field::Subtitle),
+    ("COMPILATION", Field::Compilation),
+    ("LANGUAGE", Field::Language),
+    ("MOOD", Field::Mood),
+    ("ORIGINALDATE", Field::OriginalDate),
+    ("TITLESORT", Field::TitleSort),
+    ("ARTISTSORT", Field::ArtistSort),
+    ("ALBUMSORT", Field::AlbumSort),
+    ("ALBUMARTISTSORT", Field::AlbumArtistSort),
+    ("LENGTH", Field::Length),
+];
+
+// Other spellings in common use
+const COMMENT_ALIASES: [(&str, &str); 7] = [
+    ("ALBUM ARTIST", "ALBUMARTIST"),
+    ("LABEL", "PUBLISHER"),
+    ("ORGANIZATION", "PUBLISHER"),
+    ("YEAR", "DATE"),
+    ("TOTALTRACKS", "TRACKTOTAL"),
+    ("TOTALDISCS", "DISCTOTAL"),
+    ("DESCRIPTION", "COMMENT"),
+];
+
+pub struct Comments {
+    pub vendor: String,
+    pub fields: Vec<(String, String)>,
+}
+
+// The body of a Vorbis comment header: little endian length prefixed vendor
+// string, then a count of length prefixed "KEY=value" strings
+pub fn parse_comments(data: &[u8]) -> Option<Comments> {
+    let mut reader = Reader { data, pos: 0 };
+    let vendor = reader.string_le()?;
+    let count = reader.u32_le()?;
+
+    let mut fields = Vec::new();
+    for _ in 0..count {
+        let field = reader.string_le()?;
+        if let Some((key, value)) = field.split_once('=') {
+            fields.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    Some(Comments { vendor, fields })
+}
+
+pub fn serialize_comments(comments: &Comments) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend((comments.vendor.len() as u32).to_le_bytes());
+    data.extend(comments.vendor.as_bytes());
+    data.extend((comments.fields.len() as u32).to_le_bytes());
+    for (key, value) in comments.fields.iter() {
+        let field = format!("{}={}", key, value);
+        data.extend((field.len() as u32).to_le_bytes());
+        data.extend(field.as_bytes());
+    }
+
+    data
+}
+
+// A FLAC picture block, also used base64 encoded as METADATA_BLOCK_PICTURE
+// in Ogg comments. The dimensions and colour depth are not kept.
+pub fn parse_picture(data: &[u8]) -> Option<Cover> {
+    let mut reader = Reader { data, pos: 0 };
+    let picture_type = reader.u32_be()?;
+    let mime_type = reader.string_be()?;
+    let description = reader.string_be()?;
+    reader.take(16)?;
+    let len = reader.u32_be()? as usize;
+    let data = reader.take(len)?.to_vec();
+
+    Some(Cover {
+        mime_type,
+        picture_type: picture_type.min(255) as u8,
+        description,
+        data,
+    })
+}
+
+pub fn serialize_picture(picture: &Cover) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend((picture.picture_type as u32).to_be_bytes());
+    data.extend((picture.mime_type.len() as u32).to_be_bytes());
+    data.extend(picture.mime_type.as_bytes());
+    data.extend((picture.description.len() as u32).to_be_bytes());
+    data.extend(picture.description.as_bytes());
+    // Width, height, colour depth and palette size are left unspecified
+    data.extend([0; 16]);
+    data.extend((picture.data.len() as u32).to_be_bytes());
+    data.extend(picture.data.iter());
+
+    data
+}
+
+// Repeated comments become multiple values of one field
+pub fn comments_to_model(fields: &[(String, String)], pictures: Vec<Cover>) -> TagModel {
+    let mut model = TagModel::new(TagFormat::Vorbis);
+    for (key, value) in fields {
+        let key = canonical_key(key);
+        match key.as_str() {
+            // Some taggers write "3/12" in TRACKNUMBER
+            "TRACKNUMBER" | "DISCNUMBER" => {
+                let (number, total) = if key == "TRACKNUMBER" {
+                    (Field::Track, Field::TrackTotal)
+                } else {
+                    (Field::Disc, Field::DiscTotal)
+                };
+                let (value, count) = value.split_once('/').unwrap_or((value, ""));
+                if !value.is_empty() {
+                    model.add(number, value.to_string());
+                }
+                if !count.is_empty() && model.values(&total).is_none() {
+                    model.add(total, count.to_string());
+                }
+            },
+            "TRACKTOTAL" => model.add(Field::TrackTotal, value.clone()),
+            "DISCTOTAL" => model.add(Field::DiscTotal, value.clone()),
+            "COMMENT" => model.add(Field::Comment, value.clone()),
+            _ => match COMMENT_FIELDS.iter().find(|(comment, _)| *comment == key) {
+                Some((_, field)) => model.add(field.clone(), value.clone()),
+                None => model.add(Field::Custom(key), value.clone()),
+            },
+        }
+    }
+    model.pictures = pictures;
+
+    model
+}
+
+// The inverse of comments_to_model. Native frames from other formats, such
+// as RVA2 or private frames, have no Vorbis equivalent and are dropped.
+pub fn model_to_comments(model: &TagModel) -> (Vec<(String, String)>, Vec<Cover>) {
+    let mut fields = Vec::new();
+    for (field, values) in model.fields.iter() {
+        let key = match field {
+            Field::Track => "TRACKNUMBER".to_string(),
+            Field::TrackTotal => "TRACKTOTAL".to_string(),
+            Field::Disc => "DISCNUMBER".to_string(),
+            Field::DiscTotal => "DISCTOTAL".to_string(),
+            Field::Comment => "COMMENT".to_string(),
+            // The recording time takes the place of the year when a tag has both
+            Field::Date if model.values(&Field::RecordingDate).is_some() => continue,
+            Field::RecordingDate => "DATE".to_string(),
+            Field::Custom(name) => name.to_uppercase(),
+            field => match COMMENT_FIELDS.iter().find(|(_, other)| other == field) {
+                Some((comment, _)) => comment.to_string(),
+                None => continue,
+            },
+        };
+        for value in values {
+            fields.push((key.clone(), value.clone()));
+        }
+    }
+
+    (fields, model.pictures.clone())
+}
+
+fn canonical_key(key: &str) -> String {
+    let key = key.to_uppercase();
+    COMMENT_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map(|(_, canonical)| canonical.to_string())
+        .unwrap_or(key)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u32_le(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u32_be(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn string_le(&mut self) -> Option<String> {
+        let len = self.u32_le()? as usize;
+        self.take(len).map(|bytes| String::from_utf8_lossy(bytes).to_string())
+    }
+
+    fn string_be(&mut self) -> Option<String> {
+        let len = self.u32_be()? as usize;
+        self.take(len).map(|bytes| String::from_utf8_lossy(bytes).to_string())
+    }
+}
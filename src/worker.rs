@@ -0,0 +1,92 @@
+use std::{
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        mpsc::{
+            self,
+            Receiver,
+            TryRecvError,
+        },
+        Arc,
+    },
+    thread,
+};
+
+enum Message<T> {
+    Started(usize),
+    Finished(PathBuf, Result<T, String>),
+}
+
+// Runs an analysis over each file on a background thread, reporting back
+// over a channel so the UI stays responsive. The analysis should give up
+// once the cancel flag is set.
+pub struct Job<T> {
+    receiver: Receiver<Message<T>>,
+    cancel: Arc<AtomicBool>,
+    pub paths: Vec<PathBuf>,
+    pub current: usize,
+    pub results: Vec<(PathBuf, Result<T, String>)>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    pub fn start(
+        paths: Vec<PathBuf>,
+        analyse: fn(&Path, &AtomicBool) -> Result<T, String>
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let worker_paths = paths.clone();
+        let worker_cancel = cancel.clone();
+        thread::spawn(move || {
+            for (i, path) in worker_paths.into_iter().enumerate() {
+                if worker_cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                if sender.send(Message::Started(i)).is_err() {
+                    break;
+                }
+                let result = analyse(&path, &worker_cancel);
+                if sender.send(Message::Finished(path, result)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            receiver,
+            cancel,
+            paths,
+            current: 0,
+            results: Vec::new(),
+        }
+    }
+}
+
+impl<T> Job<T> {
+    // Takes in any messages from the worker, returning true once every file
+    // has been analysed
+    pub fn poll(&mut self) -> bool {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(Message::Started(i)) => self.current = i,
+                Ok(Message::Finished(path, result)) => self.results.push((path, result)),
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Disconnected) => return true,
+            }
+        }
+    }
+
+    pub fn num_done(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}